@@ -0,0 +1,10 @@
+{ flake
+, rust-project
+, pkgs
+, lib
+, ...
+}:
+
+{
+  autoWire = [ "doc" "clippy" ];
+}
@@ -0,0 +1,59 @@
+use std::cell::RefCell;
+use std::convert::Infallible;
+use std::rc::Rc;
+
+use exercises::adc::Adc;
+use fixed::types::U1F15;
+
+// MockAdc returns the level it is set to, or WouldBlock when told to, like
+// an adc still converting
+#[derive(Clone, Default)]
+pub struct MockAdc {
+    state: Rc<RefCell<AdcState>>,
+}
+
+#[derive(Default)]
+struct AdcState {
+    level: U1F15,
+    // The number of reads that will still return WouldBlock
+    blocking_reads: u32,
+    reads: u32,
+}
+
+impl MockAdc {
+    pub fn new(level: U1F15) -> MockAdc {
+        let adc = MockAdc::default();
+        adc.set_level(level);
+        adc
+    }
+
+    pub fn set_level(&self, level: U1F15) {
+        self.state.borrow_mut().level = level
+    }
+
+    // block makes the next n reads return WouldBlock
+    pub fn block(&self, n: u32) {
+        self.state.borrow_mut().blocking_reads = n
+    }
+
+    // reads is the number of calls to read_nonblocking, blocked or not
+    pub fn reads(&self) -> u32 {
+        self.state.borrow().reads
+    }
+}
+
+// The pin is ignored, the mock has a single channel
+impl<PIN> Adc<PIN> for MockAdc {
+    type Error = Infallible;
+
+    fn read_nonblocking(&mut self, _: &PIN) -> nb::Result<U1F15, Infallible> {
+        let mut state = self.state.borrow_mut();
+        state.reads += 1;
+        if state.blocking_reads > 0 {
+            state.blocking_reads -= 1;
+            Err(nb::Error::WouldBlock)
+        } else {
+            Ok(state.level)
+        }
+    }
+}
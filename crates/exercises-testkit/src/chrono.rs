@@ -0,0 +1,60 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use exercises::chrono::{Chrono, Instant};
+
+// ManualChrono is a clock that only moves when told to
+#[derive(Clone, Default)]
+pub struct ManualChrono {
+    ms: Rc<Cell<u32>>,
+}
+
+impl ManualChrono {
+    pub fn new(ms: u32) -> ManualChrono {
+        ManualChrono {
+            ms: Rc::new(Cell::new(ms)),
+        }
+    }
+
+    pub fn set(&self, ms: u32) {
+        self.ms.set(ms)
+    }
+
+    // advance moves the clock forward, rolling over like the mcu counter
+    pub fn advance(&self, ms: u32) {
+        self.ms.set(self.ms.get().wrapping_add(ms))
+    }
+}
+
+impl Chrono for ManualChrono {
+    fn now(&self) -> Instant {
+        Instant::from_millis(self.ms.get())
+    }
+
+    fn reset(&self) {
+        self.ms.set(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_clock() {
+        let chrono = ManualChrono::new(u32::MAX - 1);
+        let handle = chrono.clone();
+        handle.advance(3);
+        assert_eq!(
+            Instant::from_millis(1),
+            chrono.now(),
+            "the clock should roll over"
+        );
+        chrono.reset();
+        assert_eq!(
+            Instant::from_millis(0),
+            handle.now(),
+            "the clones should share the time"
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use std::cell::RefCell;
+use std::convert::Infallible;
+use std::rc::Rc;
+
+use exercises::led_bank::LedBank;
+use exercises::pattern::Mask;
+
+// RecordingLeds records the brightness of the leds on every write, the leds
+// shown from a mask are either off (0) or full (255)
+#[derive(Clone, Default)]
+pub struct RecordingLeds<const N: usize> {
+    writes: Rc<RefCell<Vec<[u8; N]>>>,
+}
+
+impl<const N: usize> RecordingLeds<N> {
+    pub fn new() -> RecordingLeds<N> {
+        RecordingLeds::default()
+    }
+
+    // levels is the last brightness of each led, all off if never written
+    pub fn levels(&self) -> [u8; N] {
+        self.writes.borrow().last().copied().unwrap_or([0; N])
+    }
+
+    // mask is the leds lit by the last write, even dimly
+    pub fn mask(&self) -> Mask {
+        to_mask(&self.levels())
+    }
+
+    // history is the leds lit by every write
+    pub fn history(&self) -> Vec<Mask> {
+        self.writes.borrow().iter().map(to_mask).collect()
+    }
+}
+
+fn to_mask<const N: usize>(levels: &[u8; N]) -> Mask {
+    levels
+        .iter()
+        .enumerate()
+        .filter(|(_, level)| **level > 0)
+        .fold(0, |mask, (idx, _)| mask | 1 << idx)
+}
+
+impl<const N: usize> LedBank<N> for RecordingLeds<N> {
+    type Error = Infallible;
+
+    fn show(&mut self, mask: Mask) -> Result<(), Infallible> {
+        let levels = std::array::from_fn(|idx| if mask >> idx & 0x1 != 0 { 255 } else { 0 });
+        self.writes.borrow_mut().push(levels);
+        Ok(())
+    }
+
+    fn dim(&mut self, levels: &[u8; N]) -> Result<(), Infallible> {
+        self.writes.borrow_mut().push(*levels);
+        Ok(())
+    }
+}
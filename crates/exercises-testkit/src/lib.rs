@@ -0,0 +1,21 @@
+//! Mock peripherals to test the exercises on the computer.
+//!
+//! Every mock is a cheap handle on a shared state: clone it before giving it
+//! to an exercise, then use the clone to script the inputs or to look at the
+//! outputs between two steps of an `exercises::exercise::Runner`.
+
+pub mod adc;
+pub mod chrono;
+pub mod led_bank;
+pub mod pin;
+pub mod pwm;
+pub mod serial;
+pub mod shift_register;
+
+pub use adc::MockAdc;
+pub use chrono::ManualChrono;
+pub use led_bank::RecordingLeds;
+pub use pin::{Disconnected, RecordingPin, ScriptedPin};
+pub use pwm::RecordingPwm;
+pub use serial::SerialSink;
+pub use shift_register::MockShiftRegister;
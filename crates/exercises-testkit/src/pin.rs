@@ -0,0 +1,175 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use embedded_hal::digital::{ErrorKind, ErrorType, InputPin, OutputPin, PinState};
+
+// Disconnected is the error of the mock pins once disconnected, e.g. to test
+// a pin behind an expander that stops answering
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Disconnected;
+
+impl embedded_hal::digital::Error for Disconnected {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+// ScriptedPin is an input pin that reads its levels from a script, one
+// level per read. The last level is kept once the script is over.
+#[derive(Clone)]
+pub struct ScriptedPin {
+    script: Rc<RefCell<Script>>,
+}
+
+struct Script {
+    next: VecDeque<PinState>,
+    last: PinState,
+    disconnected: bool,
+}
+
+impl ScriptedPin {
+    // new creates a pin that stays at level
+    pub fn new(level: PinState) -> ScriptedPin {
+        ScriptedPin {
+            script: Rc::new(RefCell::new(Script {
+                next: VecDeque::new(),
+                last: level,
+                disconnected: false,
+            })),
+        }
+    }
+
+    // released creates a button with a pull up, which is high when released
+    pub fn released() -> ScriptedPin {
+        ScriptedPin::new(PinState::High)
+    }
+
+    // push adds levels at the end of the script
+    pub fn push(&self, levels: impl IntoIterator<Item = PinState>) {
+        self.script.borrow_mut().next.extend(levels)
+    }
+
+    // set drops the rest of the script and stays at level
+    pub fn set(&self, level: PinState) {
+        let mut script = self.script.borrow_mut();
+        script.next.clear();
+        script.last = level;
+    }
+
+    // press and release set a button with a pull up
+    pub fn press(&self) {
+        self.set(PinState::Low)
+    }
+
+    pub fn release(&self) {
+        self.set(PinState::High)
+    }
+
+    // disconnect makes every following read fail
+    pub fn disconnect(&self) {
+        self.script.borrow_mut().disconnected = true
+    }
+
+    fn read(&self) -> Result<PinState, Disconnected> {
+        let mut script = self.script.borrow_mut();
+        if script.disconnected {
+            return Err(Disconnected);
+        }
+        if let Some(level) = script.next.pop_front() {
+            script.last = level;
+        }
+        Ok(script.last)
+    }
+}
+
+impl ErrorType for ScriptedPin {
+    type Error = Disconnected;
+}
+
+impl InputPin for ScriptedPin {
+    fn is_high(&mut self) -> Result<bool, Disconnected> {
+        Ok(self.read()? == PinState::High)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Disconnected> {
+        Ok(self.read()? == PinState::Low)
+    }
+}
+
+// RecordingPin is an output pin that records every level it is set to
+#[derive(Clone)]
+pub struct RecordingPin {
+    history: Rc<RefCell<Vec<PinState>>>,
+    disconnected: Rc<Cell<bool>>,
+}
+
+impl RecordingPin {
+    pub fn new() -> RecordingPin {
+        RecordingPin {
+            history: Rc::new(RefCell::new(Vec::new())),
+            disconnected: Rc::new(Cell::new(false)),
+        }
+    }
+
+    // state is the last level, None if the pin was never set
+    pub fn state(&self) -> Option<PinState> {
+        self.history.borrow().last().copied()
+    }
+
+    pub fn is_set_high(&self) -> bool {
+        self.state() == Some(PinState::High)
+    }
+
+    pub fn history(&self) -> Vec<PinState> {
+        self.history.borrow().clone()
+    }
+
+    // disconnect makes every following write fail, without recording it
+    pub fn disconnect(&self) {
+        self.disconnected.set(true)
+    }
+
+    fn write(&self, level: PinState) -> Result<(), Disconnected> {
+        if self.disconnected.get() {
+            return Err(Disconnected);
+        }
+        self.history.borrow_mut().push(level);
+        Ok(())
+    }
+}
+
+impl Default for RecordingPin {
+    fn default() -> RecordingPin {
+        RecordingPin::new()
+    }
+}
+
+impl ErrorType for RecordingPin {
+    type Error = Disconnected;
+}
+
+impl OutputPin for RecordingPin {
+    fn set_low(&mut self) -> Result<(), Disconnected> {
+        self.write(PinState::Low)
+    }
+
+    fn set_high(&mut self) -> Result<(), Disconnected> {
+        self.write(PinState::High)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script() {
+        let mut pin = ScriptedPin::released();
+        pin.push([PinState::Low, PinState::Low, PinState::High]);
+        let levels: Vec<bool> = (0..4).map(|_| pin.is_low().unwrap()).collect();
+        assert_eq!(vec![true, true, false, false], levels, "wrong levels");
+        pin.clone().press();
+        assert!(pin.is_low().unwrap(), "the clones should share the script");
+    }
+}
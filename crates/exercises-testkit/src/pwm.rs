@@ -0,0 +1,45 @@
+use std::cell::RefCell;
+use std::convert::Infallible;
+use std::rc::Rc;
+
+use embedded_hal::pwm::{ErrorType, SetDutyCycle};
+
+// RecordingPwm records every duty cycle it is set to
+#[derive(Clone)]
+pub struct RecordingPwm {
+    max_duty: u16,
+    duties: Rc<RefCell<Vec<u16>>>,
+}
+
+impl RecordingPwm {
+    pub fn new(max_duty: u16) -> RecordingPwm {
+        RecordingPwm {
+            max_duty,
+            duties: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    // duty is the last duty cycle, None if it was never set
+    pub fn duty(&self) -> Option<u16> {
+        self.duties.borrow().last().copied()
+    }
+
+    pub fn duties(&self) -> Vec<u16> {
+        self.duties.borrow().clone()
+    }
+}
+
+impl ErrorType for RecordingPwm {
+    type Error = Infallible;
+}
+
+impl SetDutyCycle for RecordingPwm {
+    fn max_duty_cycle(&self) -> u16 {
+        self.max_duty
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Infallible> {
+        self.duties.borrow_mut().push(duty);
+        Ok(())
+    }
+}
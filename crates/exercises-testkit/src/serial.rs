@@ -0,0 +1,85 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::rc::Rc;
+
+use embedded_hal_nb::serial;
+
+// SerialSink captures everything written to it, either text or the frames
+// of exercises::telemetry. It can also be read, it then returns the bytes
+// sent to it with send, or WouldBlock when there are none left.
+#[derive(Clone, Default)]
+pub struct SerialSink {
+    output: Rc<RefCell<Vec<u8>>>,
+    input: Rc<RefCell<VecDeque<u8>>>,
+}
+
+impl SerialSink {
+    pub fn new() -> SerialSink {
+        SerialSink::default()
+    }
+
+    pub fn output(&self) -> String {
+        String::from_utf8_lossy(&self.output.borrow()).into_owned()
+    }
+
+    // take returns the output and clears it
+    pub fn take(&self) -> String {
+        String::from_utf8_lossy(&std::mem::take(&mut *self.output.borrow_mut())).into_owned()
+    }
+
+    // lines returns the complete lines of the output
+    pub fn lines(&self) -> Vec<String> {
+        let output = self.output();
+        let complete = output.rsplit_once('\n').map_or("", |(lines, _)| lines);
+        complete.lines().map(String::from).collect()
+    }
+
+    // send queues some text to be read, like typed in a terminal
+    pub fn send(&self, text: &str) {
+        self.input.borrow_mut().extend(text.bytes());
+    }
+
+    // frames returns the complete telemetry frames, without their final 0
+    pub fn frames(&self) -> Vec<Vec<u8>> {
+        let output = self.output.borrow();
+        let complete = output
+            .iter()
+            .rposition(|b| *b == 0)
+            .map_or(&[][..], |end| &output[..end]);
+        complete.split(|b| *b == 0).map(<[u8]>::to_vec).collect()
+    }
+}
+
+impl ufmt::uWrite for SerialSink {
+    type Error = Infallible;
+
+    fn write_str(&mut self, s: &str) -> Result<(), Infallible> {
+        self.output.borrow_mut().extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl serial::ErrorType for SerialSink {
+    type Error = Infallible;
+}
+
+impl serial::Write<u8> for SerialSink {
+    fn write(&mut self, b: u8) -> nb::Result<(), Infallible> {
+        self.output.borrow_mut().push(b);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+impl serial::Read<u8> for SerialSink {
+    fn read(&mut self) -> nb::Result<u8, Infallible> {
+        self.input
+            .borrow_mut()
+            .pop_front()
+            .ok_or(nb::Error::WouldBlock)
+    }
+}
@@ -0,0 +1,110 @@
+use std::cell::RefCell;
+use std::convert::Infallible;
+use std::rc::Rc;
+
+use embedded_hal::digital::{ErrorType, OutputPin};
+
+// MockShiftRegister is a chain of 74HC595 seen from its 3 input pins: the
+// data bit is shifted in on a rising edge of the clock, and the shifted bits
+// go to the outputs on a rising edge of the latch
+#[derive(Clone)]
+pub struct MockShiftRegister {
+    chain: Rc<RefCell<Chain>>,
+}
+
+struct Chain {
+    data: bool,
+    clock: bool,
+    latch: bool,
+    // The bits shifted in, chip 0 first, 8 bits per chip from Q0 to Q7
+    shifted: Vec<bool>,
+    outputs: Vec<bool>,
+    nb_latches: u32,
+}
+
+impl MockShiftRegister {
+    pub fn new(nb_chips: usize) -> MockShiftRegister {
+        MockShiftRegister {
+            chain: Rc::new(RefCell::new(Chain {
+                data: false,
+                clock: false,
+                latch: false,
+                shifted: vec![false; 8 * nb_chips],
+                outputs: vec![false; 8 * nb_chips],
+                nb_latches: 0,
+            })),
+        }
+    }
+
+    // pins are the data, clock and latch inputs of the chain
+    pub fn pins(&self) -> [ChainPin; 3] {
+        [Input::Data, Input::Clock, Input::Latch].map(|input| ChainPin {
+            chain: self.chain.clone(),
+            input,
+        })
+    }
+
+    // outputs are the levels of Q0 to Q7 of each chip
+    pub fn outputs(&self) -> Vec<bool> {
+        self.chain.borrow().outputs.clone()
+    }
+
+    // nb_latches is the number of times the outputs were updated
+    pub fn nb_latches(&self) -> u32 {
+        self.chain.borrow().nb_latches
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Input {
+    Data,
+    Clock,
+    Latch,
+}
+
+// ChainPin is an input of the chain, driven by the mcu
+pub struct ChainPin {
+    chain: Rc<RefCell<Chain>>,
+    input: Input,
+}
+
+impl ChainPin {
+    fn write(&self, high: bool) {
+        let mut chain = self.chain.borrow_mut();
+        match self.input {
+            Input::Data => chain.data = high,
+            Input::Clock => {
+                if high && !chain.clock {
+                    // Each bit moves to the next output, Q7 to the next chip
+                    let data = chain.data;
+                    chain.shifted.pop();
+                    chain.shifted.insert(0, data);
+                }
+                chain.clock = high;
+            }
+            Input::Latch => {
+                if high && !chain.latch {
+                    chain.outputs = chain.shifted.clone();
+                    chain.nb_latches += 1;
+                }
+                chain.latch = high;
+            }
+        }
+    }
+}
+
+impl ErrorType for ChainPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for ChainPin {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.write(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.write(true);
+        Ok(())
+    }
+}
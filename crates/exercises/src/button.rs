@@ -0,0 +1,122 @@
+//! This module contains the push buttons that know when they were pressed.
+//!
+//! A button read with is_low once per loop is only as precise as the loop,
+//! and misses a press shorter than a loop. A button driven by an interrupt
+//! timestamps its edges instead, they are turned into presses by Presses.
+
+use embedded_hal::digital::{ErrorType, InputPin};
+
+use crate::chrono::Instant;
+
+// Button is a push button, pressed when low
+pub trait Button: ErrorType {
+    // press is the time of the first press since the last call, or of the
+    // current one if the button is held. It is None if the button is released
+    // and was not pressed meanwhile.
+    // now is the time of the loop, for the buttons that can't tell better.
+    fn press(&mut self, now: Instant) -> Result<Option<Instant>, Self::Error>;
+}
+
+// Polled is a button read once per loop, its presses happen at the time of
+// the loop
+pub struct Polled<P>(pub P);
+
+impl<P: InputPin> ErrorType for Polled<P> {
+    type Error = P::Error;
+}
+
+impl<P: InputPin> Button for Polled<P> {
+    fn press(&mut self, now: Instant) -> Result<Option<Instant>, P::Error> {
+        Ok(self.0.is_low()?.then_some(now))
+    }
+}
+
+// Edge is a change of a button, at the time it happened
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Edge {
+    pub pressed: bool,
+    pub at: Instant,
+}
+
+// Presses turns the edges of a button into the presses of Button::press.
+// A bounce on release is seen as a short press.
+#[derive(Default)]
+pub struct Presses {
+    // The time of the current press, None while the button is released
+    held_since: Option<Instant>,
+}
+
+impl Presses {
+    pub const fn new() -> Presses {
+        Presses { held_since: None }
+    }
+
+    // update reads the edges received since the last update, in order, and
+    // returns the press
+    pub fn update(&mut self, edges: impl Iterator<Item = Edge>) -> Option<Instant> {
+        let mut first = self.held_since;
+        for edge in edges {
+            self.held_since = edge.pressed.then_some(edge.at);
+            first = first.or(self.held_since);
+        }
+        first
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(pressed: bool, ms: u32) -> Edge {
+        Edge {
+            pressed,
+            at: Instant::from_millis(ms),
+        }
+    }
+
+    #[test]
+    fn test_presses() {
+        struct TestCase {
+            name: &'static str,
+            // The edges of each update
+            updates: &'static [&'static [(bool, u32)]],
+            expected: &'static [Option<u32>],
+        }
+        let cases = [
+            TestCase {
+                name: "released",
+                updates: &[&[], &[]],
+                expected: &[None, None],
+            },
+            TestCase {
+                name: "held",
+                updates: &[&[(true, 10)], &[], &[(false, 30)], &[]],
+                expected: &[Some(10), Some(10), Some(10), None],
+            },
+            // A press shorter than a loop is not missed
+            TestCase {
+                name: "short press",
+                updates: &[&[(true, 10), (false, 12)], &[]],
+                expected: &[Some(10), None],
+            },
+            // The first press counts, e.g. for the fastest player
+            TestCase {
+                name: "bounces",
+                updates: &[&[(true, 10), (false, 11), (true, 12)], &[(false, 40)]],
+                expected: &[Some(10), Some(12)],
+            },
+        ];
+        for tc in cases {
+            let mut presses = Presses::new();
+            for (edges, expected) in tc.updates.iter().zip(tc.expected) {
+                let actual = presses.update(edges.iter().map(|(p, ms)| edge(*p, *ms)));
+                assert_eq!(
+                    expected.map(Instant::from_millis),
+                    actual,
+                    "wrong press for {}",
+                    tc.name
+                );
+            }
+        }
+    }
+}
@@ -1,91 +1,501 @@
-use crate::chrono::Chrono;
-use core::mem::size_of;
-use embedded_hal::digital::{OutputPin, PinState};
+use crate::adc::Adc;
+use crate::chrono::{Chrono, Duration, Instant};
+use crate::console::{Tunable, TuneError};
+use crate::exercise::{self, Exercise, Fault, Sleep};
+use crate::led_bank::LedBank;
+use crate::pattern::{Builtin, Pattern, Phase, MAX_TRAIL};
+use crate::telemetry::{FieldType, Value};
+use crate::trace::{write_bits, Tick, TraceInputs};
+use core::marker::PhantomData;
+use embedded_hal::digital::InputPin;
+use fixed::types::U1F15;
+use ufmt::{uDisplay, uWrite};
 
-pub fn run<C: Chrono, P: OutputPin>(
+// The faster and slower buttons double or halve the speed, up to 8 times
+pub const MAX_SHIFT: i8 = 3;
+
+pub fn run<C: Chrono, IP: InputPin, PIN, A: Adc<PIN>, P: LedBank<N>, const N: usize>(
     p: &Parameters,
-    ips: &InputPeripherals<C>,
-    ops: &mut OutputPeripherals<P>,
+    ips: &mut InputPeripherals<C, IP, PIN, A>,
+    ops: &mut OutputPeripherals<P, N>,
 ) -> ! {
-    loop {
-        let inputs = read_inputs(&ips);
-        let outputs = advance(p, inputs);
-        apply_outputs(outputs, ops)
+    exercise::run::<Chenillard<C, IP, PIN, A, P, N>>(p, ips, ops)
+}
+
+// Chenillard plugs the functions of this module into the Exercise trait.
+// N is the number of leds, up to pattern::MAX_LEDS.
+pub struct Chenillard<C, IP, PIN, A, P, const N: usize = 8>(PhantomData<(C, IP, PIN, A, P)>);
+
+impl<C: Chrono, IP: InputPin, PIN, A: Adc<PIN>, P: LedBank<N>, const N: usize> Exercise
+    for Chenillard<C, IP, PIN, A, P, N>
+{
+    type Parameters = Parameters;
+    type Inputs = Inputs;
+    type State = State;
+    type Outputs = Outputs<N>;
+    type InputPeripherals = InputPeripherals<C, IP, PIN, A>;
+    type OutputPeripherals = OutputPeripherals<P, N>;
+    type Error = Fault<InputError<IP::Error, A::Error>, P::Error>;
+
+    fn init(_: &Parameters) -> State {
+        State {
+            skipped: 0,
+            buttons: Buttons::default(),
+            phase: Phase::ZERO,
+            // The chrono starts with the exercise
+            last: Instant::from_millis(0),
+            shift: 0,
+            reverse: false,
+            // Full speed until the potentiometer is read
+            level: U1F15::ONE,
+        }
+    }
+
+    fn read_inputs(ps: &mut InputPeripherals<C, IP, PIN, A>) -> Result<Inputs, Self::Error> {
+        read_inputs(ps).map_err(Fault::Input)
+    }
+
+    fn advance(p: &Parameters, s: State, inputs: Inputs) -> (State, Outputs<N>) {
+        advance(p, s, inputs)
+    }
+
+    fn apply_outputs(
+        _: &Parameters,
+        outputs: Outputs<N>,
+        ps: &mut OutputPeripherals<P, N>,
+    ) -> Result<(), Self::Error> {
+        apply_outputs(outputs, ps).map_err(Fault::Output)
+    }
+
+    fn stop(_: &Parameters, _: &Self::Error, ps: &mut OutputPeripherals<P, N>) {
+        stop(ps)
+    }
+
+    // Nothing happens between two ticks of the chrono
+    fn sleep(_: &Parameters, _: &State) -> Sleep {
+        Sleep::Idle
     }
 }
 
 pub struct Inputs {
-    pub ms: u32,
+    pub now: Instant,
+    pub buttons: Buttons,
+    // The level of the speed potentiometer, None while the adc is converting
+    pub level: Option<U1F15>,
+}
+
+// Buttons tells the buttons that are pressed
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Buttons {
+    // Goes to the next pattern
+    pub next: bool,
+    // Doubles the speed
+    pub faster: bool,
+    // Halves the speed
+    pub slower: bool,
+    // Changes the direction
+    pub reverse: bool,
+}
+
+impl Buttons {
+    // pressed_since is the buttons pressed since before, on their edge
+    pub fn pressed_since(self, before: Buttons) -> Buttons {
+        Buttons {
+            next: self.next && !before.next,
+            faster: self.faster && !before.faster,
+            slower: self.slower && !before.slower,
+            reverse: self.reverse && !before.reverse,
+        }
+    }
 }
 
-pub struct Outputs {
-    // Each bit is the state of a led
-    pub leds: u8,
+pub struct Outputs<const N: usize = 8> {
+    // The brightness of each led, from 0 (off) to 255
+    pub leds: [u8; N],
 }
 
+#[derive(Clone)]
 pub struct Parameters {
-    pub period_ms: u32,
+    // The duration of a cycle of the pattern, with the potentiometer at the
+    // max and no press on the faster and slower buttons
+    pub period: Duration,
+    // The pattern at the start, the button then goes to the next ones
+    pub pattern: Builtin,
+    // The number of steps a led fades out for after being on, 0 for no trail
+    pub trail: u8,
 }
 
-pub fn advance(p: &Parameters, inputs: Inputs) -> Outputs {
-    let period = p.period_ms;
-    let nb_leds = 8 * size_of::<u8>() as u32;
-    // led_idx is the number of the led that should be on
-    let led_idx = (nb_leds * (inputs.ms % period)) / period;
-    let leds: u8 = 1 << led_idx;
-    Outputs { leds }
+// The speed changes during the cycle, so the state keeps the position in it
+pub struct State {
+    // The number of patterns skipped by the button after p.pattern
+    pub skipped: u8,
+    // The buttons at the last loop, to find the presses
+    pub buttons: Buttons,
+    pub phase: Phase,
+    // The time of the last loop, the phase was advanced up to it
+    pub last: Instant,
+    // The speed is multiplied by 2^shift, from -MAX_SHIFT to MAX_SHIFT
+    pub shift: i8,
+    pub reverse: bool,
+    // The last level of the potentiometer, kept while the adc is converting
+    pub level: U1F15,
 }
 
-pub struct InputPeripherals<T: Chrono> {
-    pub chrono: T,
+impl State {
+    pub fn pattern(&self, p: &Parameters) -> Builtin {
+        let idx = (p.pattern.index() as usize + self.skipped as usize) % Builtin::ALL.len();
+        Builtin::ALL[idx]
+    }
+
+    // speed is the potentiometer level times 2^shift, with the 15 fractional
+    // bits of the level
+    pub fn speed(&self) -> u32 {
+        let level = self.level.to_bits() as u32;
+        if self.shift >= 0 {
+            level << self.shift
+        } else {
+            level >> -self.shift
+        }
+    }
+}
+
+pub fn advance<const N: usize>(p: &Parameters, s: State, inputs: Inputs) -> (State, Outputs<N>) {
+    // The time since the last loop went by at the speed of the last loop, the
+    // new speed applies from now on
+    let elapsed = inputs.now.duration_since(s.last);
+    let phase = s.phase.advance(elapsed, p.period, s.speed(), s.reverse);
+
+    let pressed = inputs.buttons.pressed_since(s.buttons);
+    let skipped = (s.skipped + pressed.next as u8) % Builtin::ALL.len() as u8;
+    let shift =
+        (s.shift + pressed.faster as i8 - pressed.slower as i8).clamp(-MAX_SHIFT, MAX_SHIFT);
+    let new_state = State {
+        skipped,
+        buttons: inputs.buttons,
+        phase,
+        last: inputs.now,
+        shift,
+        reverse: s.reverse != pressed.reverse,
+        level: inputs.level.unwrap_or(s.level),
+    };
+    let leds = new_state
+        .pattern(p)
+        .brightness(phase, p.trail, new_state.reverse);
+    (new_state, Outputs { leds })
 }
 
-fn read_inputs<T: Chrono>(ps: &InputPeripherals<T>) -> Inputs {
-    let ms = ps.chrono.millis();
-    Inputs { ms }
+impl TraceInputs for Inputs {
+    fn from_tick(tick: &Tick) -> Inputs {
+        Inputs {
+            now: Instant::from_millis(tick.ms),
+            buttons: Buttons {
+                next: tick.button(0),
+                faster: tick.button(1),
+                slower: tick.button(2),
+                reverse: tick.button(3),
+            },
+            level: tick.level,
+        }
+    }
+
+    fn to_tick(&self) -> Tick {
+        let b = self.buttons;
+        Tick {
+            ms: self.now.as_millis(),
+            buttons: b.next as u8
+                | (b.faster as u8) << 1
+                | (b.slower as u8) << 2
+                | (b.reverse as u8) << 3,
+            nb_buttons: 4,
+            level: self.level,
+            roll: None,
+        }
+    }
 }
 
-pub struct OutputPeripherals<T: OutputPin> {
-    pub ps: [T; 8],
+impl<const N: usize> uDisplay for Outputs<N> {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error> {
+        f.write_str("leds=")?;
+        write_bits(f, self.leds.iter().map(|level| *level > 0))
+    }
 }
 
-fn apply_outputs<T: OutputPin>(outputs: Outputs, pins: &mut OutputPeripherals<T>) -> () {
-    // Set every pin by iterating on the pins list
-    for (led_idx, pin) in pins.ps.iter_mut().enumerate() {
-        let led_on = outputs.leds >> led_idx & 0x1 != 0;
-        pin.set_state(PinState::from(led_on)).unwrap()
+impl Tunable for Parameters {
+    const FIELDS: &'static [(&'static str, FieldType)] = &[
+        ("period_ms", FieldType::U32),
+        // The index of the pattern in pattern::Builtin::ALL
+        ("pattern", FieldType::U8),
+        ("trail", FieldType::U8),
+    ];
+
+    fn get(&self, field: &str) -> Option<Value> {
+        match field {
+            "period_ms" => Some(Value::U32(self.period.as_millis())),
+            "pattern" => Some(Value::U8(self.pattern.index())),
+            "trail" => Some(Value::U8(self.trail)),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, field: &str, value: Value) -> Result<(), TuneError> {
+        match (field, value) {
+            ("period_ms", Value::U32(ms)) => {
+                self.period = Duration::from_millis(ms);
+                Ok(())
+            }
+            ("pattern", Value::U8(idx)) => {
+                self.pattern = Builtin::from_index(idx).ok_or(TuneError::InvalidValue)?;
+                Ok(())
+            }
+            ("trail", Value::U8(trail)) => {
+                self.trail = trail;
+                Ok(())
+            }
+            _ => Err(TuneError::UnknownField),
+        }
+    }
+
+    // The period divides the time
+    fn is_valid(&self) -> bool {
+        self.period.as_millis() > 0 && self.trail <= MAX_TRAIL
     }
 }
 
+// The buttons are pressed when low, the potentiometer sets the speed from
+// stopped to a cycle per period
+pub struct InputPeripherals<T: Chrono, IP: InputPin, PIN, A: Adc<PIN>> {
+    pub chrono: T,
+    // The button that goes to the next pattern
+    pub button: IP,
+    pub faster: IP,
+    pub slower: IP,
+    pub reverse: IP,
+    pub adc: A,
+    pub pin: PIN,
+}
+
+// InputError tells which of the input peripherals failed
+#[derive(Debug, PartialEq)]
+pub enum InputError<B, A> {
+    Buttons(B),
+    Adc(A),
+}
+
+pub fn read_inputs<T: Chrono, IP: InputPin, PIN, A: Adc<PIN>>(
+    ps: &mut InputPeripherals<T, IP, PIN, A>,
+) -> Result<Inputs, InputError<IP::Error, A::Error>> {
+    let buttons = read_buttons(ps).map_err(InputError::Buttons)?;
+    let level = match ps.adc.read_nonblocking(&ps.pin) {
+        Ok(level) => Some(level),
+        // The adc is still converting, the last level will be used
+        Err(nb::Error::WouldBlock) => None,
+        Err(nb::Error::Other(e)) => return Err(InputError::Adc(e)),
+    };
+    Ok(Inputs {
+        now: ps.chrono.now(),
+        buttons,
+        level,
+    })
+}
+
+fn read_buttons<T: Chrono, IP: InputPin, PIN, A: Adc<PIN>>(
+    ps: &mut InputPeripherals<T, IP, PIN, A>,
+) -> Result<Buttons, IP::Error> {
+    Ok(Buttons {
+        next: ps.button.is_low()?,
+        faster: ps.faster.is_low()?,
+        slower: ps.slower.is_low()?,
+        reverse: ps.reverse.is_low()?,
+    })
+}
+
+// The leds are dimmed if the bank can, e.g. with a software pwm on the uno
+pub struct OutputPeripherals<T: LedBank<N>, const N: usize = 8> {
+    pub ps: T,
+}
+
+pub fn apply_outputs<T: LedBank<N>, const N: usize>(
+    outputs: Outputs<N>,
+    pins: &mut OutputPeripherals<T, N>,
+) -> Result<(), T::Error> {
+    pins.ps.dim(&outputs.leds)
+}
+
+// stop turns all the leds off
+pub fn stop<T: LedBank<N>, const N: usize>(pins: &mut OutputPeripherals<T, N>) {
+    pins.ps.off().unwrap_or(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn start() -> State {
+        State {
+            skipped: 0,
+            buttons: Buttons::default(),
+            phase: Phase::ZERO,
+            last: Instant::from_millis(0),
+            shift: 0,
+            reverse: false,
+            level: U1F15::ONE,
+        }
+    }
+
+    fn at(ms: u32, buttons: Buttons, level: Option<U1F15>) -> Inputs {
+        Inputs {
+            now: Instant::from_millis(ms),
+            buttons,
+            level,
+        }
+    }
+
     #[test]
     fn test_outputs() {
         struct TestCase {
             ms: u32,
-            expected: u8,
+            expected: [u8; 8],
         }
         let cases = [
             TestCase {
                 ms: 50,
-                expected: 1,
+                expected: [255, 0, 0, 0, 0, 0, 0, 0],
             },
             TestCase {
                 ms: 950,
-                expected: 128,
+                expected: [0, 0, 0, 0, 0, 0, 127, 255],
             },
             TestCase {
                 ms: 300,
-                expected: 4,
+                expected: [0, 127, 255, 0, 0, 0, 0, 0],
             },
         ];
-        let p = Parameters { period_ms: 1000 };
+        let p = Parameters {
+            period: Duration::from_millis(1000),
+            pattern: Builtin::Walk,
+            trail: 1,
+        };
         for tc in cases {
-            let inputs = Inputs { ms: tc.ms };
-            let outputs = advance(&p, inputs);
+            let inputs = at(tc.ms, Buttons::default(), None);
+            let (_, outputs) = advance(&p, start(), inputs);
             assert_eq!(tc.expected, outputs.leds, "wrong led pattern")
         }
     }
+
+    #[test]
+    fn test_button() {
+        let p = Parameters {
+            period: Duration::from_millis(1000),
+            pattern: Builtin::Sparkle,
+            trail: 0,
+        };
+        let mut s = start();
+        // The button is held for two loops, then pressed again
+        for next in [true, true, false, true] {
+            let buttons = Buttons {
+                next,
+                ..Buttons::default()
+            };
+            (s, _) = advance::<8>(&p, s, at(0, buttons, None));
+        }
+        assert_eq!(Builtin::Walk, s.pattern(&p), "two presses after sparkle");
+    }
+
+    #[test]
+    fn test_speed() {
+        struct TestCase {
+            name: &'static str,
+            ms: u32,
+            buttons: Buttons,
+            level: Option<U1F15>,
+            // The led that is on
+            expected: usize,
+        }
+        let faster = Buttons {
+            faster: true,
+            ..Buttons::default()
+        };
+        let reverse = Buttons {
+            reverse: true,
+            ..Buttons::default()
+        };
+        // The led goes on from one case to the next, never jumping
+        let cases = [
+            TestCase {
+                name: "full speed",
+                ms: 500,
+                buttons: faster,
+                level: None,
+                expected: 4,
+            },
+            TestCase {
+                name: "twice faster",
+                ms: 625,
+                buttons: Buttons::default(),
+                level: Some(U1F15::lit("0.5")),
+                expected: 6,
+            },
+            TestCase {
+                name: "potentiometer at half",
+                ms: 750,
+                buttons: reverse,
+                level: None,
+                expected: 7,
+            },
+            // The reverse button is held
+            TestCase {
+                name: "backward",
+                ms: 1_000,
+                buttons: reverse,
+                level: None,
+                expected: 5,
+            },
+            TestCase {
+                name: "stopped",
+                ms: 2_000,
+                buttons: Buttons::default(),
+                level: Some(U1F15::ZERO),
+                expected: 5,
+            },
+            TestCase {
+                name: "still stopped",
+                ms: 3_000,
+                buttons: Buttons::default(),
+                level: None,
+                expected: 5,
+            },
+        ];
+        let p = Parameters {
+            period: Duration::from_millis(1000),
+            pattern: Builtin::Walk,
+            trail: 0,
+        };
+        let mut s = start();
+        for tc in cases {
+            let outputs;
+            (s, outputs) = advance::<8>(&p, s, at(tc.ms, tc.buttons, tc.level));
+            let mut expected = [0; 8];
+            expected[tc.expected] = 255;
+            assert_eq!(expected, outputs.leds, "wrong leds for {}", tc.name);
+        }
+    }
+
+    #[test]
+    fn test_max_shift() {
+        let p = Parameters {
+            period: Duration::from_millis(1000),
+            pattern: Builtin::Walk,
+            trail: 0,
+        };
+        let mut s = start();
+        for ms in 0..10 {
+            let buttons = Buttons {
+                faster: ms % 2 == 0,
+                ..Buttons::default()
+            };
+            (s, _) = advance::<8>(&p, s, at(ms, buttons, None));
+        }
+        assert_eq!(MAX_SHIFT, s.shift, "the speed should be capped");
+        assert_eq!(U1F15::ONE.to_bits() as u32 * 8, s.speed());
+    }
 }
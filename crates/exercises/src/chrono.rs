@@ -1,7 +1,213 @@
-//! This module contains a trait to get the millis elapsed since the last reset
-//! call.
+//! This module contains a trait to get the time elapsed since the last reset
+//! call, and the types to compute with it.
+//!
+//! The time is counted in ms on a u32, which rolls over after ~49.7 days, or
+//! in us, which rolls over after ~71.6 minutes.
+//! Instants are never compared directly, only through the duration between
+//! them, which is computed with a wrapping subtraction and stays correct
+//! across a rollover.
+
+use core::marker::PhantomData;
 
 pub trait Chrono {
-    fn millis(&self) -> u32;
+    fn now(&self) -> Instant;
     fn reset(&self);
+
+    // micros is the time with a finer resolution, to measure short durations.
+    // The default is for the chronos that only count the ms.
+    fn micros(&self) -> Instant<Micros> {
+        // The us counter rolls over in sync with the ms one, as 2^32 * 1000
+        // is a multiple of 2^32
+        Instant::from_micros(self.now().as_millis().wrapping_mul(1000))
+    }
+
+    // elapsed is the duration between since and now
+    fn elapsed(&self, since: Instant) -> Duration {
+        self.now().duration_since(since)
+    }
+}
+
+// Millis and Micros are the units of the instants and durations
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Millis;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Micros;
+
+// Instant is a point in time, in ms (or us) since the last reset of the chrono
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Instant<U = Millis>(u32, PhantomData<U>);
+
+// Duration is a number of ms (or us)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Duration<U = Millis>(u32, PhantomData<U>);
+
+impl Instant<Millis> {
+    pub const fn from_millis(ms: u32) -> Instant {
+        Instant(ms, PhantomData)
+    }
+
+    // as_millis is the raw value of the counter
+    pub const fn as_millis(self) -> u32 {
+        self.0
+    }
+}
+
+impl Instant<Micros> {
+    pub const fn from_micros(us: u32) -> Instant<Micros> {
+        Instant(us, PhantomData)
+    }
+
+    // as_micros is the raw value of the counter
+    pub const fn as_micros(self) -> u32 {
+        self.0
+    }
+}
+
+impl<U> Instant<U> {
+    // duration_since is the time elapsed from earlier to self.
+    // It is correct across a rollover as long as less than a whole counter
+    // range elapsed between the two.
+    pub const fn duration_since(self, earlier: Instant<U>) -> Duration<U> {
+        Duration(self.0.wrapping_sub(earlier.0), PhantomData)
+    }
+
+    // checked_add is the instant d after self, rolling over if needed.
+    // It is None if d is too long to tell if the result is before or after
+    // self, i.e. longer than half the counter range.
+    pub const fn checked_add(self, d: Duration<U>) -> Option<Instant<U>> {
+        if d.0 > i32::MAX as u32 {
+            None
+        } else {
+            Some(Instant(self.0.wrapping_add(d.0), PhantomData))
+        }
+    }
+
+    // has_reached is true when self is at or after deadline, for a deadline
+    // computed with checked_add
+    pub const fn has_reached(self, deadline: Instant<U>) -> bool {
+        self.0.wrapping_sub(deadline.0) <= i32::MAX as u32
+    }
+}
+
+impl Duration<Millis> {
+    pub const fn from_millis(ms: u32) -> Duration {
+        Duration(ms, PhantomData)
+    }
+
+    pub const fn as_millis(self) -> u32 {
+        self.0
+    }
+}
+
+impl Duration<Micros> {
+    pub const fn from_micros(us: u32) -> Duration<Micros> {
+        Duration(us, PhantomData)
+    }
+
+    pub const fn as_micros(self) -> u32 {
+        self.0
+    }
+
+    // as_millis truncates the duration to whole ms
+    pub const fn as_millis(self) -> u32 {
+        self.0 / 1000
+    }
+}
+
+impl<U> Duration<U> {
+    pub const ZERO: Duration<U> = Duration(0, PhantomData);
+
+    pub const fn checked_add(self, other: Duration<U>) -> Option<Duration<U>> {
+        match self.0.checked_add(other.0) {
+            Some(v) => Some(Duration(v, PhantomData)),
+            None => None,
+        }
+    }
+
+    pub const fn saturating_sub(self, other: Duration<U>) -> Duration<U> {
+        Duration(self.0.saturating_sub(other.0), PhantomData)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duration_since() {
+        struct TestCase {
+            earlier: u32,
+            later: u32,
+            expected: u32,
+        }
+        let cases = [
+            TestCase {
+                earlier: 1_000,
+                later: 1_500,
+                expected: 500,
+            },
+            TestCase {
+                earlier: u32::MAX - 99,
+                later: 400,
+                expected: 500,
+            },
+            TestCase {
+                earlier: 0,
+                later: u32::MAX,
+                expected: u32::MAX,
+            },
+        ];
+        for tc in cases {
+            let actual =
+                Instant::from_millis(tc.later).duration_since(Instant::from_millis(tc.earlier));
+            assert_eq!(Duration::from_millis(tc.expected), actual, "wrong duration")
+        }
+    }
+
+    #[test]
+    fn test_deadline() {
+        let start = Instant::from_millis(u32::MAX - 99);
+        let deadline = start.checked_add(Duration::from_millis(500)).unwrap();
+        assert_eq!(Instant::from_millis(400), deadline, "wrong deadline");
+        assert!(
+            !start.has_reached(deadline),
+            "the start is before the deadline"
+        );
+        assert!(
+            !Instant::from_millis(399).has_reached(deadline),
+            "399 is before the deadline"
+        );
+        assert!(
+            Instant::from_millis(400).has_reached(deadline),
+            "400 is the deadline"
+        );
+        assert!(
+            Instant::from_millis(10_000).has_reached(deadline),
+            "10_000 is after the deadline"
+        );
+        assert_eq!(
+            None,
+            start.checked_add(Duration::from_millis(u32::MAX / 2 + 1)),
+            "the deadline is too far"
+        );
+    }
+
+    #[test]
+    fn test_default_micros() {
+        struct MsChrono(u32);
+        impl Chrono for MsChrono {
+            fn now(&self) -> Instant {
+                Instant::from_millis(self.0)
+            }
+            fn reset(&self) {}
+        }
+        let before = MsChrono(u32::MAX).micros();
+        let after = MsChrono(1).micros();
+        assert_eq!(
+            Duration::from_micros(2_000),
+            after.duration_since(before),
+            "the us should follow the ms rollover"
+        );
+    }
 }
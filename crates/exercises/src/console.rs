@@ -0,0 +1,512 @@
+//! This module contains a console to tune the parameters of an exercise while
+//! it runs, from a serial terminal.
+//!
+//! The commands are lines of text:
+//! - `list` prints the tunable fields and their types
+//! - `get` prints the value of every field, `get <field>` of a single one
+//! - `set <field> <value>` changes a field, e.g. `set lambda 0.01`
+//! - `save` saves the params in a storage (e.g. the EEPROM), see the persist
+//!   module
+//!
+//! The console reads the serial port without blocking and runs a command once
+//! its line is complete, between two loops of the exercise. The changes are
+//! lost on a reset unless they are saved.
+//!
+//! The replies end with a 0 byte, so on a port shared with the telemetry the
+//! decoder drops a reply as a single invalid frame.
+
+use core::cell::RefCell;
+
+use embedded_hal_nb::serial;
+use fixed::types::{I3F13, U1F15};
+use ufmt::{uDisplay, uWrite, uwrite};
+
+use crate::exercise::{Exercise, Idle, Runner, Sleep};
+use crate::persist::{self, Storage};
+use crate::telemetry::{FieldType, Value};
+
+// The longest command, longer lines are dropped
+pub const MAX_LINE: usize = 32;
+
+// Tunable is implemented by the parameters that can be changed at runtime
+pub trait Tunable: Clone {
+    // The names and types of the fields, the durations are in ms
+    const FIELDS: &'static [(&'static str, FieldType)];
+
+    fn get(&self, field: &str) -> Option<Value>;
+
+    // set changes a field, the value has the type given in FIELDS
+    fn set(&mut self, field: &str, value: Value) -> Result<(), TuneError>;
+
+    // is_valid is false for the values that would break the exercise (e.g. a
+    // zero period). The fields are checked together, after being set.
+    fn is_valid(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TuneError {
+    UnknownField,
+    InvalidValue,
+}
+
+// run is exercise::run_idle with a console on the serial port, it never
+// returns. The params are saved to storage, use persist::NoStorage to not save
+// them.
+// The mcu never sleeps deeper than Sleep::Idle, the serial port must be able
+// to wake it up.
+pub fn run<E: Exercise, S: serial::Read<u8> + uWrite, ST: Storage, I: Idle>(
+    p: &E::Parameters,
+    ips: &mut E::InputPeripherals,
+    ops: &mut E::OutputPeripherals,
+    serial: &mut S,
+    storage: &mut ST,
+    idle: &mut I,
+) -> !
+where
+    E::Parameters: Tunable,
+{
+    let mut runner = Runner::<E>::new(p, ips, ops);
+    let mut console = Console::new();
+    loop {
+        console.poll(serial, runner.params_mut(), storage);
+        runner.step();
+        idle.sleep(runner.sleep_depth().min(Sleep::Idle))
+    }
+}
+
+pub struct Console {
+    line: [u8; MAX_LINE],
+    len: usize,
+    // True when the current line is too long or lost a byte
+    dropped: bool,
+}
+
+impl Console {
+    pub const fn new() -> Console {
+        Console {
+            line: [0; MAX_LINE],
+            len: 0,
+            dropped: false,
+        }
+    }
+
+    // poll reads the bytes received so far and runs the complete commands.
+    // The replies are best effort, their errors are ignored.
+    pub fn poll<S: serial::Read<u8> + uWrite, P: Tunable, ST: Storage>(
+        &mut self,
+        serial: &mut S,
+        params: &mut P,
+        storage: &mut ST,
+    ) {
+        loop {
+            let b = match serial.read() {
+                Ok(b) => b,
+                Err(nb::Error::WouldBlock) => return,
+                // A byte was lost (e.g. an overrun), the line can't be trusted
+                Err(nb::Error::Other(_)) => {
+                    self.dropped = true;
+                    continue;
+                }
+            };
+            match b {
+                b'\r' | b'\n' => {
+                    let line = &self.line[..self.len];
+                    let reply = if self.dropped {
+                        uwrite!(serial, "error: line dropped\n")
+                    } else if line.is_empty() {
+                        continue;
+                    } else {
+                        execute(line, serial, params, storage)
+                    };
+                    reply.and_then(|_| serial.write_str("\0")).unwrap_or(());
+                    self.len = 0;
+                    self.dropped = false;
+                }
+                _ if self.len < MAX_LINE => {
+                    self.line[self.len] = b;
+                    self.len += 1;
+                }
+                _ => self.dropped = true,
+            }
+        }
+    }
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Console::new()
+    }
+}
+
+fn execute<W: uWrite + ?Sized, P: Tunable, ST: Storage>(
+    line: &[u8],
+    w: &mut W,
+    params: &mut P,
+    storage: &mut ST,
+) -> Result<(), W::Error> {
+    let mut words = core::str::from_utf8(line)
+        .unwrap_or("")
+        .split_ascii_whitespace();
+    match (words.next(), words.next(), words.next(), words.next()) {
+        (Some("list"), None, None, None) => {
+            for (name, ty) in P::FIELDS {
+                uwrite!(w, "{} {}\n", *name, type_name(*ty))?;
+            }
+            Ok(())
+        }
+        (Some("get"), None, None, None) => {
+            for (name, _) in P::FIELDS {
+                if let Some(value) = params.get(name) {
+                    uwrite!(w, "{}={}\n", *name, value)?;
+                }
+            }
+            Ok(())
+        }
+        (Some("get"), Some(name), None, None) => match params.get(name) {
+            Some(value) => uwrite!(w, "{}={}\n", name, value),
+            None => uwrite!(w, "error: unknown field\n"),
+        },
+        (Some("set"), Some(name), Some(value), None) => {
+            let Some((_, ty)) = P::FIELDS.iter().find(|(n, _)| *n == name) else {
+                return uwrite!(w, "error: unknown field\n");
+            };
+            let mut tuned = params.clone();
+            let result = parse(*ty, value)
+                .ok_or(TuneError::InvalidValue)
+                .and_then(|v| tuned.set(name, v))
+                .and_then(|_| match tuned.is_valid() {
+                    true => Ok(()),
+                    false => Err(TuneError::InvalidValue),
+                });
+            if result.is_ok() {
+                *params = tuned;
+            }
+            match result {
+                Ok(()) => uwrite!(w, "ok\n"),
+                Err(TuneError::UnknownField) => uwrite!(w, "error: unknown field\n"),
+                Err(TuneError::InvalidValue) => uwrite!(w, "error: invalid value\n"),
+            }
+        }
+        (Some("save"), None, None, None) => match persist::save(storage, params) {
+            Ok(()) => uwrite!(w, "ok\n"),
+            Err(_) => uwrite!(w, "error: storage\n"),
+        },
+        _ => uwrite!(w, "error: unknown command\n"),
+    }
+}
+
+fn type_name(ty: FieldType) -> &'static str {
+    match ty {
+        FieldType::Bool => "bool",
+        FieldType::U8 => "u8",
+        FieldType::U16 => "u16",
+        FieldType::U32 => "u32",
+        FieldType::U1F15 => "u1f15",
+        FieldType::I3F13 => "i3f13",
+    }
+}
+
+fn parse(ty: FieldType, s: &str) -> Option<Value> {
+    match ty {
+        FieldType::Bool => match s {
+            "0" | "false" => Some(Value::Bool(false)),
+            "1" | "true" => Some(Value::Bool(true)),
+            _ => None,
+        },
+        FieldType::U8 => s.parse().ok().map(Value::U8),
+        FieldType::U16 => s.parse().ok().map(Value::U16),
+        FieldType::U32 => s.parse().ok().map(Value::U32),
+        FieldType::U1F15 => U1F15::from_str(s).ok().map(Value::U1F15),
+        FieldType::I3F13 => I3F13::from_str(s).ok().map(Value::I3F13),
+    }
+}
+
+impl uDisplay for Value {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error> {
+        match self {
+            Value::Bool(v) => uwrite!(f, "{}", *v as u8),
+            Value::U8(v) => uwrite!(f, "{}", *v),
+            Value::U16(v) => uwrite!(f, "{}", *v),
+            Value::U32(v) => uwrite!(f, "{}", *v),
+            Value::U1F15(v) => write_fixed(f, false, v.to_bits() as u32, U1F15::FRAC_NBITS),
+            Value::I3F13(v) => write_fixed(
+                f,
+                v.is_negative(),
+                v.to_bits().unsigned_abs() as u32,
+                I3F13::FRAC_NBITS,
+            ),
+        }
+    }
+}
+
+// write_fixed prints a fixed point number with 5 decimals, truncated.
+// ufmt has no float, and core::fmt is too big for the uno.
+fn write_fixed<W: uWrite + ?Sized>(
+    f: &mut ufmt::Formatter<'_, W>,
+    negative: bool,
+    bits: u32,
+    frac_bits: u32,
+) -> Result<(), W::Error> {
+    let mask = (1 << frac_bits) - 1;
+    let mut frac = bits & mask;
+    if negative {
+        f.write_char('-')?;
+    }
+    uwrite!(f, "{}.", bits >> frac_bits)?;
+    for _ in 0..5 {
+        frac *= 10;
+        uwrite!(f, "{}", frac >> frac_bits)?;
+        frac &= mask;
+    }
+    Ok(())
+}
+
+// Shared lets an exercise and the console use the same serial port, e.g. for
+// the telemetry and the commands
+pub struct Shared<'a, S>(&'a RefCell<S>);
+
+impl<'a, S> Shared<'a, S> {
+    pub fn new(serial: &'a RefCell<S>) -> Shared<'a, S> {
+        Shared(serial)
+    }
+}
+
+impl<S> Clone for Shared<'_, S> {
+    fn clone(&self) -> Self {
+        Shared(self.0)
+    }
+}
+
+impl<S: serial::ErrorType> serial::ErrorType for Shared<'_, S> {
+    type Error = S::Error;
+}
+
+impl<S: serial::Read<u8>> serial::Read<u8> for Shared<'_, S> {
+    fn read(&mut self) -> nb::Result<u8, S::Error> {
+        self.0.borrow_mut().read()
+    }
+}
+
+impl<S: serial::Write<u8>> serial::Write<u8> for Shared<'_, S> {
+    fn write(&mut self, b: u8) -> nb::Result<(), S::Error> {
+        self.0.borrow_mut().write(b)
+    }
+
+    fn flush(&mut self) -> nb::Result<(), S::Error> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+impl<S: uWrite> uWrite for Shared<'_, S> {
+    type Error = S::Error;
+
+    fn write_str(&mut self, s: &str) -> Result<(), S::Error> {
+        self.0.borrow_mut().write_str(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persist::NoStorage;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Params {
+        speed: U1F15,
+        offset: I3F13,
+        count: u8,
+    }
+
+    impl Tunable for Params {
+        const FIELDS: &'static [(&'static str, FieldType)] = &[
+            ("speed", FieldType::U1F15),
+            ("offset", FieldType::I3F13),
+            ("count", FieldType::U8),
+        ];
+
+        fn get(&self, field: &str) -> Option<Value> {
+            match field {
+                "speed" => Some(Value::U1F15(self.speed)),
+                "offset" => Some(Value::I3F13(self.offset)),
+                "count" => Some(Value::U8(self.count)),
+                _ => None,
+            }
+        }
+
+        fn set(&mut self, field: &str, value: Value) -> Result<(), TuneError> {
+            match (field, value) {
+                ("speed", Value::U1F15(v)) => self.speed = v,
+                ("offset", Value::I3F13(v)) => self.offset = v,
+                ("count", Value::U8(v)) => self.count = v,
+                _ => return Err(TuneError::UnknownField),
+            }
+            Ok(())
+        }
+
+        fn is_valid(&self) -> bool {
+            self.count > 0
+        }
+    }
+
+    // Terminal replays its input then records the replies in fixed buffers,
+    // the tests are no_std
+    struct Terminal {
+        input: &'static [u8],
+        output: [u8; 256],
+        len: usize,
+    }
+
+    impl Terminal {
+        fn new(input: &'static str) -> Terminal {
+            Terminal {
+                input: input.as_bytes(),
+                output: [0; 256],
+                len: 0,
+            }
+        }
+
+        fn output(&self) -> &str {
+            core::str::from_utf8(&self.output[..self.len]).unwrap()
+        }
+    }
+
+    impl serial::ErrorType for Terminal {
+        type Error = core::convert::Infallible;
+    }
+
+    impl serial::Read<u8> for Terminal {
+        fn read(&mut self) -> nb::Result<u8, Self::Error> {
+            let (b, rest) = self.input.split_first().ok_or(nb::Error::WouldBlock)?;
+            self.input = rest;
+            Ok(*b)
+        }
+    }
+
+    impl uWrite for Terminal {
+        type Error = core::convert::Infallible;
+
+        fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
+            self.output[self.len..self.len + s.len()].copy_from_slice(s.as_bytes());
+            self.len += s.len();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_commands() {
+        struct TestCase {
+            input: &'static str,
+            expected: &'static str,
+        }
+        let cases = [
+            TestCase {
+                input: "list\n",
+                expected: "speed u1f15\noffset i3f13\ncount u8\n\0",
+            },
+            TestCase {
+                input: "get\r\n",
+                expected: "speed=0.50000\noffset=-1.25000\ncount=3\n\0",
+            },
+            TestCase {
+                input: "set speed 0.25\nget speed\n",
+                expected: "ok\n\0speed=0.25000\n\0",
+            },
+            TestCase {
+                input: "set count 0\n",
+                expected: "error: invalid value\n\0",
+            },
+            TestCase {
+                input: "set count 300\n",
+                expected: "error: invalid value\n\0",
+            },
+            TestCase {
+                input: "set size 3\n",
+                expected: "error: unknown field\n\0",
+            },
+            TestCase {
+                input: "reset\n",
+                expected: "error: unknown command\n\0",
+            },
+            TestCase {
+                input: "save\n",
+                expected: "error: storage\n\0",
+            },
+            TestCase {
+                input: "set speed 0.250000000000000000000000000000\nget count\n",
+                expected: "error: line dropped\n\0count=3\n\0",
+            },
+        ];
+        for tc in cases {
+            let mut params = Params {
+                speed: U1F15::lit("0.5"),
+                offset: I3F13::lit("-1.25"),
+                count: 3,
+            };
+            let mut terminal = Terminal::new(tc.input);
+            Console::new().poll(&mut terminal, &mut params, &mut NoStorage);
+            assert_eq!(
+                tc.expected,
+                terminal.output(),
+                "wrong reply to {}",
+                tc.input
+            );
+        }
+    }
+
+    #[test]
+    fn test_partial_line() {
+        let mut params = Params {
+            speed: U1F15::lit("0.5"),
+            offset: I3F13::ZERO,
+            count: 3,
+        };
+        let mut console = Console::new();
+        let mut terminal = Terminal::new("set cou");
+        console.poll(&mut terminal, &mut params, &mut NoStorage);
+        assert_eq!("", terminal.output(), "the line is not complete");
+        terminal.input = b"nt 7\n";
+        console.poll(&mut terminal, &mut params, &mut NoStorage);
+        assert_eq!("ok\n\0", terminal.output(), "wrong reply");
+        assert_eq!(7, params.count, "the count should be set");
+    }
+
+    #[test]
+    fn test_save() {
+        // Memory is a storage in RAM, like a tiny EEPROM
+        struct Memory([u8; persist::MAX_BLOCK]);
+
+        impl Storage for Memory {
+            type Error = ();
+
+            fn read(&mut self, block: &mut [u8]) -> Result<(), ()> {
+                block.copy_from_slice(&self.0[..block.len()]);
+                Ok(())
+            }
+
+            fn write(&mut self, block: &[u8]) -> Result<(), ()> {
+                self.0[..block.len()].copy_from_slice(block);
+                Ok(())
+            }
+        }
+
+        let defaults = Params {
+            speed: U1F15::lit("0.5"),
+            offset: I3F13::ZERO,
+            count: 3,
+        };
+        let mut memory = Memory([0xFF; persist::MAX_BLOCK]);
+        assert_eq!(
+            Err(persist::LoadError::Missing),
+            persist::load(&mut memory, &defaults),
+            "nothing is saved yet"
+        );
+        let mut params = defaults.clone();
+        let mut terminal = Terminal::new("set count 7\nsave\n");
+        Console::new().poll(&mut terminal, &mut params, &mut memory);
+        assert_eq!("ok\n\0ok\n\0", terminal.output(), "wrong reply");
+        let loaded = persist::load(&mut memory, &defaults);
+        assert_eq!(Ok(params), loaded, "the params should be saved");
+    }
+}
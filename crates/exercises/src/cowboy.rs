@@ -1,39 +1,93 @@
-use embedded_hal::digital::{InputPin, OutputPin, PinState};
+use core::marker::PhantomData;
 
-use crate::chrono::Chrono;
+use embedded_hal::digital::{OutputPin, PinState};
+use rand::{rngs::SmallRng, RngCore};
+use ufmt::{uDisplay, uWrite, uwrite};
 
-pub fn run<C: Chrono, IP: InputPin, OP: OutputPin>(
+use crate::button::Button;
+use crate::chrono::{Chrono, Duration, Instant};
+use crate::console::{Tunable, TuneError};
+use crate::exercise::{self, Exercise, Fault, Sleep};
+use crate::telemetry::{FieldType, Value};
+use crate::trace::{write_bits, Tick, TraceInputs};
+
+pub fn run<C: Chrono, B: Button, OP: OutputPin, S: uWrite>(
     params: &Parameters,
-    ips: &mut InputPeripherals<C, IP>,
-    ops: &mut OutputPeripherals<OP>,
+    ips: &mut InputPeripherals<C, B>,
+    ops: &mut OutputPeripherals<OP, S>,
 ) -> ! {
-    let mut state = State::Blinking(0);
-    loop {
-        let inputs = read_inputs(ips);
-        // Generate a new state of the game instead of mutating the current state
-        let (new_state, outputs) = advance(params, state, inputs);
-        state = new_state;
-        apply_outputs(outputs, ops)
+    exercise::run::<Cowboy<C, B, OP, S>>(params, ips, ops)
+}
+
+// Cowboy plugs the functions of this module into the Exercise trait
+pub struct Cowboy<C, B, OP, S>(PhantomData<(C, B, OP, S)>);
+
+impl<C: Chrono, B: Button, OP: OutputPin, S: uWrite> Exercise for Cowboy<C, B, OP, S> {
+    type Parameters = Parameters;
+    type Inputs = Inputs;
+    type State = State;
+    type Outputs = Outputs;
+    type InputPeripherals = InputPeripherals<C, B>;
+    type OutputPeripherals = OutputPeripherals<OP, S>;
+    type Error = Fault<B::Error, OutputError<OP::Error, S::Error>>;
+
+    fn init(_: &Parameters) -> State {
+        State::new(Instant::from_millis(0))
+    }
+
+    fn read_inputs(ps: &mut InputPeripherals<C, B>) -> Result<Inputs, Self::Error> {
+        read_inputs(ps).map_err(Fault::Input)
+    }
+
+    fn advance(p: &Parameters, s: State, inputs: Inputs) -> (State, Outputs) {
+        advance(p, s, inputs)
+    }
+
+    fn apply_outputs(
+        _: &Parameters,
+        outputs: Outputs,
+        ps: &mut OutputPeripherals<OP, S>,
+    ) -> Result<(), Self::Error> {
+        apply_outputs(outputs, ps).map_err(Fault::Output)
+    }
+
+    fn stop(_: &Parameters, _: &Self::Error, ps: &mut OutputPeripherals<OP, S>) {
+        stop(ps)
+    }
+
+    // The chrono must run to time the presses, even when waiting for the
+    // fastest
+    fn sleep(_: &Parameters, _: &State) -> Sleep {
+        Sleep::Idle
     }
 }
 
-struct Inputs {
-    ms: u32,
-    // true when the buttons are pressed
-    left_button: bool,
-    right_button: bool,
+pub struct Inputs {
+    pub now: Instant,
+    // The time at which the buttons were pressed, None when they are not
+    pub left_button: Option<Instant>,
+    pub right_button: Option<Instant>,
+    // A random number for the hold before the start signal
+    pub roll: u32,
 }
 
-struct Outputs {
-    left_led: bool,
-    right_led: bool,
+pub struct Outputs {
+    pub left_led: bool,
+    pub right_led: bool,
+    // The players, left then right, at the end of a round
+    pub summary: Option<[Player; 2]>,
 }
 
-pub enum State {
+pub enum Round {
     // Blinking is the pregame wait, when the leds are flashing to make the players ready
     // Pressing the button in this state makes the player loses
     // It contains the time at which it started blinking to know when to start the game.
-    Blinking(u32),
+    Blinking(Instant),
+    // Holding is the random wait after the blinking, with the leds on, so that the
+    // players can't learn when to press. Pressing the button in this state is a false
+    // start too.
+    // It contains the time of the start signal.
+    Holding(Instant),
     // WaitingForFastest means the mcu is waiting for at least one of the players to
     // press their button
     WaitingForFastest(),
@@ -41,111 +95,672 @@ pub enum State {
     // one or two leds (on ex-aequo)
     // It contains the time at which the mcu started to show the winner in order to
     // know when to restart the game.
-    ShowingWinner(u32, bool, bool),
+    ShowingWinner(Instant, bool, bool),
+}
+
+pub struct State {
+    pub round: Round,
+    // The time the leds turned off, the start signal of the reaction times.
+    // It is None before it and after a false start.
+    pub signal: Option<Instant>,
+    // The left then right player
+    pub players: [Player; 2],
+}
+
+impl State {
+    // new starts a session, blinking from start
+    pub fn new(start: Instant) -> State {
+        State {
+            round: Round::Blinking(start),
+            signal: None,
+            players: [Player::default(); 2],
+        }
+    }
+
+    // react times the presses after the start signal
+    fn react(&mut self, input: &Inputs) {
+        if let Some(signal) = self.signal {
+            self.players[0].react(signal, input.left_button);
+            self.players[1].react(signal, input.right_button);
+        }
+    }
+}
+
+// Player is the reaction times of a player during a session
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Player {
+    // The reaction time of the current round, None until the press
+    pub reaction: Option<Duration>,
+    pub best: Option<Duration>,
+    // The sum of the reaction times, for their average
+    pub total: Duration,
+    pub nb_reactions: u32,
+    pub false_starts: u32,
+}
+
+impl Player {
+    pub fn average(&self) -> Option<Duration> {
+        (self.nb_reactions > 0)
+            .then(|| Duration::from_millis(self.total.as_millis() / self.nb_reactions))
+    }
+
+    // react times a press after the start signal, once per round
+    fn react(&mut self, signal: Instant, press: Option<Instant>) {
+        let Some(press) = press else {
+            return;
+        };
+        if self.reaction.is_some() {
+            return;
+        }
+        let reaction = press.duration_since(signal);
+        self.reaction = Some(reaction);
+        self.best = Some(self.best.map_or(reaction, |best| best.min(reaction)));
+        // A session would need 49 days of reactions to saturate
+        self.total = self.total.checked_add(reaction).unwrap_or(self.total);
+        self.nb_reactions += 1;
+    }
 }
 
+// The longest hold that can be tuned
+pub const MAX_HOLD_MS: u32 = 60_000;
+
+#[derive(Clone)]
 pub struct Parameters {
     // The number of blink before the players have to press their button
     pub nb_blinks: u32,
     // The whole duration of the pre-game blinking
-    pub blink_duration_ms: u32,
+    pub blink_duration: Duration,
     // The duration of the game end results
-    pub show_winner_duration: u32,
+    pub show_winner_duration: Duration,
+    // The leds stay on from min_hold to max_hold (included) after the blinking,
+    // at random. Both at 0 to give the start signal right after the blinking.
+    pub min_hold: Duration,
+    pub max_hold: Duration,
+}
+
+impl Parameters {
+    // hold is the wait before the start signal for a random roll
+    pub fn hold(&self, roll: u32) -> Duration {
+        let range = self.max_hold.saturating_sub(self.min_hold).as_millis() as u64 + 1;
+        let extra = Duration::from_millis((roll as u64 % range) as u32);
+        self.min_hold.checked_add(extra).unwrap_or(self.max_hold)
+    }
+}
+
+// false_start ends the round, the players who pressed lose
+fn false_start(mut s: State, input: &Inputs) -> State {
+    s.players[0].false_starts += input.left_button.is_some() as u32;
+    s.players[1].false_starts += input.right_button.is_some() as u32;
+    // Notice the left/right inversion to make the other win.
+    s.round = Round::ShowingWinner(
+        input.now,
+        input.right_button.is_some(),
+        input.left_button.is_some(),
+    );
+    s
 }
 
-fn advance(p: &Parameters, s: State, input: Inputs) -> (State, Outputs) {
+// start_signal turns the leds off, the players can press
+fn start_signal(mut s: State, input: &Inputs) -> State {
+    s.round = Round::WaitingForFastest();
+    s.signal = Some(input.now);
+    s
+}
+
+pub fn advance(p: &Parameters, mut s: State, input: Inputs) -> (State, Outputs) {
     let led_off = Outputs {
         left_led: false,
         right_led: false,
+        summary: None,
     };
-    return match s {
-        State::Blinking(start) => {
-            if start > input.ms {
-                // nitpicky check: detect clock rollover if the mcu is on for a
-                // long time
-                (State::Blinking(input.ms), led_off)
-            } else if input.ms > p.blink_duration_ms + start {
-                // If the blinking period has ended, wait the fastest player with
-                // the leds turned off
-                (State::WaitingForFastest(), led_off)
-            } else if input.left_button || input.right_button {
+    let led_hold = Outputs {
+        left_led: true,
+        right_led: true,
+        summary: None,
+    };
+    return match s.round {
+        Round::Blinking(start) => {
+            let since_blink = input.now.duration_since(start);
+            if input.left_button.is_some() || input.right_button.is_some() {
                 // If at least one the button was pressed (can be both), the players
-                // who pressed have lost.
-                // Notice the left/right inversion to make the other win.
-                (
-                    State::ShowingWinner(input.ms, input.right_button, input.left_button),
-                    led_off,
-                )
+                // who pressed have lost, even on the last loop of the blinking.
+                (false_start(s, &input), led_off)
+            } else if since_blink > p.blink_duration {
+                // If the blinking period has ended, hold the leds on for a while
+                // or wait the fastest player with the leds turned off
+                let hold = p.hold(input.roll);
+                if hold == Duration::ZERO {
+                    (start_signal(s, &input), led_off)
+                } else {
+                    s.round = Round::Holding(input.now.checked_add(hold).unwrap_or(input.now));
+                    (s, led_hold)
+                }
             } else {
                 // Make the leds blink p.nb_blinks time
-                let since_blink = input.ms - start;
-                let period = p.blink_duration_ms / p.nb_blinks;
-                let cycle_position = since_blink % period;
+                let period = p.blink_duration.as_millis() / p.nb_blinks;
+                let cycle_position = since_blink.as_millis() % period;
                 let led_on = cycle_position > period / 2;
                 (
                     s,
                     Outputs {
                         left_led: led_on,
                         right_led: led_on,
+                        summary: None,
                     },
                 )
             }
         }
-        State::WaitingForFastest() => {
+        Round::Holding(signal) => {
+            if input.left_button.is_some() || input.right_button.is_some() {
+                (false_start(s, &input), led_off)
+            } else if input.now.has_reached(signal) {
+                (start_signal(s, &input), led_off)
+            } else {
+                (s, led_hold)
+            }
+        }
+        Round::WaitingForFastest() => {
+            s.react(&input);
             // If a player or both have pressed during the game, move to showing the results.
+            // The first press wins, both if they were pressed at the same time.
             // If nothing happened, keep waiting
-            let new_state = if input.left_button || input.right_button {
-                State::ShowingWinner(input.ms, input.left_button, input.right_button)
-            } else {
-                s
+            match (input.left_button, input.right_button) {
+                (Some(left), Some(right)) => {
+                    s.round = Round::ShowingWinner(
+                        input.now,
+                        right.has_reached(left),
+                        left.has_reached(right),
+                    )
+                }
+                (Some(_), None) => s.round = Round::ShowingWinner(input.now, true, false),
+                (None, Some(_)) => s.round = Round::ShowingWinner(input.now, false, true),
+                (None, None) => {}
             };
-            (new_state, led_off)
+            (s, led_off)
         }
-        State::ShowingWinner(start, left_won, right_won) => {
-            let result = Outputs {
-                left_led: left_won,
-                right_led: right_won,
-            };
-            if start > input.ms {
-                // nitpicky check: detect clock rollover if the mcu is on for a
-                // long time
-                (State::ShowingWinner(input.ms, left_won, right_won), result)
-            } else if input.ms > p.show_winner_duration + start {
-                // If the mcu showed the result for long enough, restart the game
-                (State::Blinking(input.ms), led_off)
+        Round::ShowingWinner(start, left_won, right_won) => {
+            // The loser is still timed when they press after the winner
+            s.react(&input);
+            if input.now.duration_since(start) > p.show_winner_duration {
+                // If the mcu showed the result for long enough, print the
+                // reaction times and restart the game
+                let summary = Some(s.players);
+                let mut s = State {
+                    players: s.players,
+                    ..State::new(input.now)
+                };
+                for player in s.players.iter_mut() {
+                    player.reaction = None;
+                }
+                (s, Outputs { summary, ..led_off })
             } else {
                 // If we're still showing the results, show them
-                (s, result)
+                (
+                    s,
+                    Outputs {
+                        left_led: left_won,
+                        right_led: right_won,
+                        summary: None,
+                    },
+                )
             }
         }
     };
 }
 
-pub struct InputPeripherals<T: Chrono, IP: InputPin> {
-    pub chrono: T,
-    pub left_button: IP,
-    pub right_button: IP,
+impl TraceInputs for Inputs {
+    fn from_tick(tick: &Tick) -> Inputs {
+        Inputs {
+            now: Instant::from_millis(tick.ms),
+            // A trace only has the time of the ticks
+            left_button: tick.button(0).then_some(Instant::from_millis(tick.ms)),
+            right_button: tick.button(1).then_some(Instant::from_millis(tick.ms)),
+            // The traces written by hand may have no roll, it is then derived
+            // from the time to keep the replay deterministic
+            roll: tick.roll.unwrap_or(tick.ms),
+        }
+    }
+
+    fn to_tick(&self) -> Tick {
+        Tick {
+            ms: self.now.as_millis(),
+            buttons: self.left_button.is_some() as u8 | (self.right_button.is_some() as u8) << 1,
+            nb_buttons: 2,
+            level: None,
+            roll: Some(self.roll),
+        }
+    }
 }
 
-fn read_inputs<T: Chrono, IP: InputPin>(ps: &mut InputPeripherals<T, IP>) -> Inputs {
-    Inputs {
-        ms: ps.chrono.millis(),
-        left_button: ps.left_button.is_low().unwrap(),
-        right_button: ps.right_button.is_low().unwrap(),
+impl uDisplay for Outputs {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error> {
+        f.write_str("leds=")?;
+        write_bits(f, [self.left_led, self.right_led].into_iter())
     }
 }
 
-pub struct OutputPeripherals<OP: OutputPin> {
+// The summary of a round is a comment of the trace format, so that a trace
+// recorded on the same serial port can still be replayed
+struct Summary<'a>(&'a [Player; 2]);
+
+impl uDisplay for Summary<'_> {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error> {
+        uwrite!(f, "# left: {} | right: {}\n", self.0[0], self.0[1])
+    }
+}
+
+impl uDisplay for Player {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error> {
+        f.write_str("last=")?;
+        write_ms(f, self.reaction)?;
+        f.write_str(" best=")?;
+        write_ms(f, self.best)?;
+        f.write_str(" avg=")?;
+        write_ms(f, self.average())?;
+        uwrite!(f, " false_starts={}", self.false_starts)
+    }
+}
+
+// write_ms writes - for no time
+fn write_ms<W: uWrite + ?Sized>(
+    f: &mut ufmt::Formatter<'_, W>,
+    d: Option<Duration>,
+) -> Result<(), W::Error> {
+    match d {
+        Some(d) => uwrite!(f, "{}ms", d.as_millis()),
+        None => f.write_str("-"),
+    }
+}
+
+impl Tunable for Parameters {
+    const FIELDS: &'static [(&'static str, FieldType)] = &[
+        ("nb_blinks", FieldType::U32),
+        ("blink_ms", FieldType::U32),
+        ("show_winner_ms", FieldType::U32),
+        ("min_hold_ms", FieldType::U32),
+        ("max_hold_ms", FieldType::U32),
+    ];
+
+    fn get(&self, field: &str) -> Option<Value> {
+        match field {
+            "nb_blinks" => Some(Value::U32(self.nb_blinks)),
+            "blink_ms" => Some(Value::U32(self.blink_duration.as_millis())),
+            "show_winner_ms" => Some(Value::U32(self.show_winner_duration.as_millis())),
+            "min_hold_ms" => Some(Value::U32(self.min_hold.as_millis())),
+            "max_hold_ms" => Some(Value::U32(self.max_hold.as_millis())),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, field: &str, value: Value) -> Result<(), TuneError> {
+        match (field, value) {
+            ("nb_blinks", Value::U32(n)) => self.nb_blinks = n,
+            ("blink_ms", Value::U32(ms)) => self.blink_duration = Duration::from_millis(ms),
+            ("show_winner_ms", Value::U32(ms)) => {
+                self.show_winner_duration = Duration::from_millis(ms)
+            }
+            ("min_hold_ms", Value::U32(ms)) => self.min_hold = Duration::from_millis(ms),
+            ("max_hold_ms", Value::U32(ms)) => self.max_hold = Duration::from_millis(ms),
+            _ => return Err(TuneError::UnknownField),
+        }
+        Ok(())
+    }
+
+    // A blink must last at least 1ms, its period divides the time. The hold
+    // must be shorter than a chrono can time, a minute is plenty.
+    fn is_valid(&self) -> bool {
+        self.nb_blinks > 0
+            && self.blink_duration.as_millis() >= self.nb_blinks
+            && self.min_hold <= self.max_hold
+            && self.max_hold.as_millis() <= MAX_HOLD_MS
+    }
+}
+
+// The buttons are e.g. button::Polled pins, or buttons timestamped by an
+// interrupt for a fairer game
+pub struct InputPeripherals<T: Chrono, B: Button> {
+    pub chrono: T,
+    pub left_button: B,
+    pub right_button: B,
+    // The rng is setup as an input peripheral to keep the core logic pure
+    pub rng: SmallRng,
+}
+
+pub fn read_inputs<T: Chrono, B: Button>(
+    ps: &mut InputPeripherals<T, B>,
+) -> Result<Inputs, B::Error> {
+    let now = ps.chrono.now();
+    Ok(Inputs {
+        now,
+        left_button: ps.left_button.press(now)?,
+        right_button: ps.right_button.press(now)?,
+        roll: ps.rng.next_u32(),
+    })
+}
+
+// The serial port gets a summary line after each round
+pub struct OutputPeripherals<OP: OutputPin, S: uWrite> {
     pub left_led: OP,
     pub right_led: OP,
+    pub serial: S,
+}
+
+// OutputError tells which of the output peripherals failed
+#[derive(Debug, PartialEq)]
+pub enum OutputError<P, S> {
+    Leds(P),
+    Serial(S),
 }
 
-fn apply_outputs<T: OutputPin>(outputs: Outputs, pins: &mut OutputPeripherals<T>) -> () {
+pub fn apply_outputs<T: OutputPin, S: uWrite>(
+    outputs: Outputs,
+    pins: &mut OutputPeripherals<T, S>,
+) -> Result<(), OutputError<T::Error, S::Error>> {
     // Set the state of the two leds
     pins.left_led
         .set_state(PinState::from(outputs.left_led))
-        .unwrap();
+        .map_err(OutputError::Leds)?;
     pins.right_led
         .set_state(PinState::from(outputs.right_led))
-        .unwrap();
+        .map_err(OutputError::Leds)?;
+    match outputs.summary {
+        Some(players) => {
+            uwrite!(&mut pins.serial, "{}", Summary(&players)).map_err(OutputError::Serial)
+        }
+        None => Ok(()),
+    }
+}
+
+// stop turns both leds off
+pub fn stop<T: OutputPin, S: uWrite>(pins: &mut OutputPeripherals<T, S>) {
+    pins.left_led.set_low().unwrap_or(());
+    pins.right_led.set_low().unwrap_or(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::assert_golden;
+
+    #[test]
+    fn test_traces() {
+        struct TestCase {
+            name: &'static str,
+            trace: &'static str,
+            golden: &'static str,
+        }
+        let cases = [
+            TestCase {
+                name: "false_start",
+                trace: include_str!("../traces/cowboy_false_start.trace"),
+                golden: include_str!("../traces/cowboy_false_start.golden"),
+            },
+            TestCase {
+                name: "winner",
+                trace: include_str!("../traces/cowboy_winner.trace"),
+                golden: include_str!("../traces/cowboy_winner.golden"),
+            },
+            TestCase {
+                name: "rollover",
+                trace: include_str!("../traces/cowboy_rollover.trace"),
+                golden: include_str!("../traces/cowboy_rollover.golden"),
+            },
+        ];
+        let p = Parameters {
+            nb_blinks: 3,
+            blink_duration: Duration::from_millis(3_000),
+            show_winner_duration: Duration::from_millis(5_000),
+            min_hold: Duration::ZERO,
+            max_hold: Duration::ZERO,
+        };
+        for tc in cases {
+            assert_golden(
+                tc.name,
+                || State::new(Instant::from_millis(0)),
+                |s, inputs| advance(&p, s, inputs),
+                tc.trace,
+                tc.golden,
+            )
+        }
+    }
+
+    #[test]
+    fn test_fastest() {
+        struct TestCase {
+            // The press times of the left and right players
+            presses: (Option<u32>, Option<u32>),
+            // The winners, left and right
+            expected: Option<(bool, bool)>,
+        }
+        let cases = [
+            TestCase {
+                presses: (None, None),
+                expected: None,
+            },
+            TestCase {
+                presses: (Some(1_000), None),
+                expected: Some((true, false)),
+            },
+            // Both pressed during the same loop, the first press wins
+            TestCase {
+                presses: (Some(1_003), Some(1_001)),
+                expected: Some((false, true)),
+            },
+            TestCase {
+                presses: (Some(1_002), Some(1_002)),
+                expected: Some((true, true)),
+            },
+        ];
+        let p = Parameters {
+            nb_blinks: 3,
+            blink_duration: Duration::from_millis(3_000),
+            show_winner_duration: Duration::from_millis(5_000),
+            min_hold: Duration::ZERO,
+            max_hold: Duration::ZERO,
+        };
+        for tc in cases {
+            let inputs = Inputs {
+                now: Instant::from_millis(1_010),
+                left_button: tc.presses.0.map(Instant::from_millis),
+                right_button: tc.presses.1.map(Instant::from_millis),
+                roll: 0,
+            };
+            let s = State {
+                round: Round::WaitingForFastest(),
+                ..State::new(Instant::from_millis(0))
+            };
+            let actual = match advance(&p, s, inputs).0.round {
+                Round::ShowingWinner(_, left, right) => Some((left, right)),
+                _ => None,
+            };
+            assert_eq!(tc.expected, actual, "wrong winners for {:?}", tc.presses);
+        }
+    }
+
+    #[test]
+    fn test_player() {
+        struct TestCase {
+            // The press of the round, after a signal at 1_000ms
+            press: Option<u32>,
+            expected: Player,
+        }
+        let cases = [
+            TestCase {
+                press: None,
+                expected: Player::default(),
+            },
+            TestCase {
+                press: Some(1_300),
+                expected: Player {
+                    reaction: Some(Duration::from_millis(300)),
+                    best: Some(Duration::from_millis(300)),
+                    total: Duration::from_millis(300),
+                    nb_reactions: 1,
+                    false_starts: 0,
+                },
+            },
+            TestCase {
+                press: Some(1_200),
+                expected: Player {
+                    reaction: Some(Duration::from_millis(200)),
+                    best: Some(Duration::from_millis(200)),
+                    total: Duration::from_millis(500),
+                    nb_reactions: 2,
+                    false_starts: 0,
+                },
+            },
+            TestCase {
+                press: Some(1_500),
+                expected: Player {
+                    reaction: Some(Duration::from_millis(500)),
+                    best: Some(Duration::from_millis(200)),
+                    total: Duration::from_millis(1_000),
+                    nb_reactions: 3,
+                    false_starts: 0,
+                },
+            },
+        ];
+        let mut player = Player::default();
+        for tc in cases {
+            player.reaction = None;
+            player.react(
+                Instant::from_millis(1_000),
+                tc.press.map(Instant::from_millis),
+            );
+            assert_eq!(tc.expected, player, "wrong player after {:?}", tc.press);
+        }
+        // A second press in the same round is ignored
+        player.react(
+            Instant::from_millis(1_000),
+            Some(Instant::from_millis(1_001)),
+        );
+        assert_eq!(3, player.nb_reactions, "the second press should be ignored");
+        assert_eq!(
+            Some(Duration::from_millis(333)),
+            player.average(),
+            "wrong average"
+        );
+    }
+
+    #[test]
+    fn test_hold() {
+        struct TestCase {
+            min_hold: u32,
+            max_hold: u32,
+            roll: u32,
+            expected: u32,
+        }
+        let cases = [
+            TestCase {
+                min_hold: 0,
+                max_hold: 0,
+                roll: 1234,
+                expected: 0,
+            },
+            TestCase {
+                min_hold: 1_000,
+                max_hold: 1_000,
+                roll: 1234,
+                expected: 1_000,
+            },
+            TestCase {
+                min_hold: 1_000,
+                max_hold: 3_000,
+                roll: 1234,
+                expected: 2_234,
+            },
+            // The max is included
+            TestCase {
+                min_hold: 1_000,
+                max_hold: 3_000,
+                roll: 2_000,
+                expected: 3_000,
+            },
+            TestCase {
+                min_hold: 1_000,
+                max_hold: 3_000,
+                roll: u32::MAX,
+                expected: 1_000 + (u32::MAX % 2_001),
+            },
+        ];
+        for tc in cases {
+            let p = Parameters {
+                nb_blinks: 3,
+                blink_duration: Duration::from_millis(3_000),
+                show_winner_duration: Duration::from_millis(5_000),
+                min_hold: Duration::from_millis(tc.min_hold),
+                max_hold: Duration::from_millis(tc.max_hold),
+            };
+            assert_eq!(
+                Duration::from_millis(tc.expected),
+                p.hold(tc.roll),
+                "wrong hold from {} to {} for {}",
+                tc.min_hold,
+                tc.max_hold,
+                tc.roll
+            );
+        }
+    }
+
+    #[test]
+    fn test_holding() {
+        struct TestCase {
+            // The time and the left press of the loop after the blinking
+            now: u32,
+            left_button: Option<u32>,
+            // The leds and the false starts of the left player
+            expected: (bool, u32),
+        }
+        let cases = [
+            TestCase {
+                now: 3_500,
+                left_button: None,
+                expected: (true, 0),
+            },
+            // Anticipating the start signal is a false start
+            TestCase {
+                now: 3_500,
+                left_button: Some(3_499),
+                expected: (false, 1),
+            },
+            TestCase {
+                now: 4_010,
+                left_button: None,
+                expected: (false, 0),
+            },
+        ];
+        let p = Parameters {
+            nb_blinks: 3,
+            blink_duration: Duration::from_millis(3_000),
+            show_winner_duration: Duration::from_millis(5_000),
+            min_hold: Duration::from_millis(500),
+            max_hold: Duration::from_millis(1_500),
+        };
+        for tc in cases {
+            // The blinking ends at 3_010 with a hold of 500 + 500ms
+            let inputs = Inputs {
+                now: Instant::from_millis(3_010),
+                left_button: None,
+                right_button: None,
+                roll: 500,
+            };
+            let (s, outputs) = advance(&p, State::new(Instant::from_millis(0)), inputs);
+            assert!(outputs.left_led, "the leds should be on during the hold");
+            let inputs = Inputs {
+                now: Instant::from_millis(tc.now),
+                left_button: tc.left_button.map(Instant::from_millis),
+                right_button: None,
+                roll: 0,
+            };
+            let (s, outputs) = advance(&p, s, inputs);
+            assert_eq!(
+                tc.expected,
+                (outputs.left_led, s.players[0].false_starts),
+                "wrong outputs at {}",
+                tc.now
+            );
+            let expected_signal = (tc.now == 4_010).then_some(Instant::from_millis(4_010));
+            assert_eq!(expected_signal, s.signal, "wrong signal at {}", tc.now);
+        }
+    }
 }
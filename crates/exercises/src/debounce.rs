@@ -1,30 +1,68 @@
-use crate::chrono::Chrono;
+use crate::chrono::{Chrono, Instant};
+use crate::console::{Tunable, TuneError};
+use crate::exercise::{self, Exercise, Fault};
 use crate::low_pass::{FixedLowPassParams, FixedLowPassState};
+use crate::telemetry::{self, FieldType, Payload, Schema, Telemetry, Value};
+use crate::trace::{Tick, TraceInputs};
+use core::marker::PhantomData;
 use embedded_hal::digital::InputPin;
+use embedded_hal_nb::serial;
 use fixed::types::U1F15;
+use ufmt::{uDisplay, uWrite, uwrite};
 
-pub fn run<C: Chrono, IP: InputPin, S: ufmt::uWrite>(
+pub fn run<C: Chrono, IP: InputPin, S: serial::Write<u8>>(
     p: &Parameters,
     ips: &mut InputPeripherals<C, IP>,
     ops: &mut OutputPeripherals<S>,
 ) -> ! {
-    let mut state = State {
-        left_button: FixedLowPassState::new(),
-        right_button: false,
+    exercise::run::<Debounce<C, IP, S>>(p, ips, ops)
+}
 
-        left_presses: 0,
-        right_presses: 0,
-    };
-    loop {
-        let inputs = read_inputs(ips);
-        let (new_state, outputs) = advance(p, state, inputs);
-        state = new_state;
-        apply_outputs(outputs, ops)
+// Debounce plugs the functions of this module into the Exercise trait
+pub struct Debounce<C, IP, S>(PhantomData<(C, IP, S)>);
+
+impl<C: Chrono, IP: InputPin, S: serial::Write<u8>> Exercise for Debounce<C, IP, S> {
+    type Parameters = Parameters;
+    type Inputs = Inputs;
+    type State = State;
+    type Outputs = Outputs;
+    type InputPeripherals = InputPeripherals<C, IP>;
+    type OutputPeripherals = OutputPeripherals<S>;
+    type Error = Fault<IP::Error, S::Error>;
+
+    fn init(_: &Parameters) -> State {
+        State {
+            left_button: FixedLowPassState::new(),
+            right_button: false,
+
+            left_presses: 0,
+            right_presses: 0,
+        }
+    }
+
+    fn read_inputs(ps: &mut InputPeripherals<C, IP>) -> Result<Inputs, Self::Error> {
+        read_inputs(ps).map_err(Fault::Input)
+    }
+
+    fn advance(p: &Parameters, s: State, inputs: Inputs) -> (State, Outputs) {
+        advance(p, s, inputs)
+    }
+
+    fn apply_outputs(
+        _: &Parameters,
+        outputs: Outputs,
+        ps: &mut OutputPeripherals<S>,
+    ) -> Result<(), Self::Error> {
+        apply_outputs(outputs, ps).map_err(Fault::Output)
+    }
+
+    fn stop(_: &Parameters, error: &Self::Error, ps: &mut OutputPeripherals<S>) {
+        telemetry::send(&mut ps.serial, error).unwrap_or(())
     }
 }
 
 pub struct Inputs {
-    pub ms: u32,
+    pub now: Instant,
     pub left_button: bool,
     pub right_button: bool,
 }
@@ -35,6 +73,7 @@ pub struct Outputs {
     pub right_presses: u32,
 }
 
+#[derive(Clone)]
 pub struct Parameters {
     pub low_pass_params: FixedLowPassParams,
     // Threshold between 0 and 1 at which we consider the left button as pressed
@@ -55,7 +94,7 @@ pub fn advance(p: &Parameters, s: State, inputs: Inputs) -> (State, Outputs) {
     let new_left_button = s.left_button.advance(
         &p.low_pass_params,
         U1F15::unwrapped_from_num(inputs.left_button),
-        inputs.ms,
+        inputs.now,
     );
     let new_left_value = new_left_button.current_val();
     let left_pressed = new_left_button.current_val() > p.threshold && old_left_value <= p.threshold;
@@ -74,31 +113,115 @@ pub fn advance(p: &Parameters, s: State, inputs: Inputs) -> (State, Outputs) {
     (new_state, outputs)
 }
 
+impl TraceInputs for Inputs {
+    fn from_tick(tick: &Tick) -> Inputs {
+        Inputs {
+            now: Instant::from_millis(tick.ms),
+            left_button: tick.button(0),
+            right_button: tick.button(1),
+        }
+    }
+
+    fn to_tick(&self) -> Tick {
+        Tick {
+            ms: self.now.as_millis(),
+            buttons: self.left_button as u8 | (self.right_button as u8) << 1,
+            nb_buttons: 2,
+            level: None,
+            roll: None,
+        }
+    }
+}
+
+impl uDisplay for Outputs {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error> {
+        uwrite!(
+            f,
+            "left_button_val={} left_presses={} right_presses={}",
+            self.left_button_val.to_bits(),
+            self.left_presses,
+            self.right_presses,
+        )
+    }
+}
+
+pub const SCHEMA: Schema = Schema {
+    kind: 0x01,
+    name: "debounce",
+    fields: &[
+        ("left_button_val", FieldType::U1F15),
+        ("left_presses", FieldType::U32),
+        ("right_presses", FieldType::U32),
+    ],
+};
+
+impl Telemetry for Outputs {
+    const SCHEMA: Schema = SCHEMA;
+
+    fn write_fields(&self, payload: &mut Payload) {
+        payload.push(Value::U1F15(self.left_button_val));
+        payload.push(Value::U32(self.left_presses));
+        payload.push(Value::U32(self.right_presses));
+    }
+}
+
+impl Tunable for Parameters {
+    const FIELDS: &'static [(&'static str, FieldType)] = &[
+        ("lambda", FieldType::U1F15),
+        ("threshold", FieldType::U1F15),
+    ];
+
+    fn get(&self, field: &str) -> Option<Value> {
+        match field {
+            "lambda" => Some(Value::U1F15(self.low_pass_params.lambda)),
+            "threshold" => Some(Value::U1F15(self.threshold)),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, field: &str, value: Value) -> Result<(), TuneError> {
+        match (field, value) {
+            ("lambda", Value::U1F15(v)) => {
+                self.low_pass_params.lambda = v;
+                Ok(())
+            }
+            ("threshold", Value::U1F15(v)) => {
+                self.threshold = v;
+                Ok(())
+            }
+            _ => Err(TuneError::UnknownField),
+        }
+    }
+
+    // The low pass computes 1 - lambda
+    fn is_valid(&self) -> bool {
+        self.low_pass_params.lambda <= U1F15::ONE
+    }
+}
+
 pub struct InputPeripherals<T: Chrono, IP: InputPin> {
     pub chrono: T,
     pub left_button: IP,
     pub right_button: IP,
 }
 
-fn read_inputs<T: Chrono, IP: InputPin>(ps: &mut InputPeripherals<T, IP>) -> Inputs {
-    Inputs {
-        ms: ps.chrono.millis(),
-        left_button: ps.left_button.is_low().unwrap(),
-        right_button: ps.right_button.is_low().unwrap(),
-    }
+pub fn read_inputs<T: Chrono, IP: InputPin>(
+    ps: &mut InputPeripherals<T, IP>,
+) -> Result<Inputs, IP::Error> {
+    Ok(Inputs {
+        now: ps.chrono.now(),
+        left_button: ps.left_button.is_low()?,
+        right_button: ps.right_button.is_low()?,
+    })
 }
 
 pub struct OutputPeripherals<S> {
     pub serial: S,
 }
 
-fn apply_outputs<S: ufmt::uWrite>(outputs: Outputs, pins: &mut OutputPeripherals<S>) -> () {
-    ufmt::uwrite!(
-        &mut pins.serial,
-        "{}\t{}\t{}\n",
-        outputs.left_button_val.to_bits(),
-        outputs.left_presses,
-        outputs.right_presses,
-    )
-    .unwrap_or(());
+pub fn apply_outputs<S: serial::Write<u8>>(
+    outputs: Outputs,
+    pins: &mut OutputPeripherals<S>,
+) -> Result<(), S::Error> {
+    telemetry::send(&mut pins.serial, &outputs)
 }
@@ -1,81 +1,137 @@
-use embedded_hal::digital::{InputPin, OutputPin, PinState};
+use core::marker::PhantomData;
+
+use embedded_hal::digital::InputPin;
 use rand::{rngs::SmallRng, RngCore};
+use ufmt::{uDisplay, uWrite};
 
-use crate::chrono::Chrono;
+use crate::chrono::{Chrono, Duration, Instant};
+use crate::console::{Tunable, TuneError};
+use crate::exercise::{self, Exercise, Fault, Sleep};
+use crate::led_bank::LedBank;
+use crate::pattern::Mask;
+use crate::scheduler::Timer;
+use crate::telemetry::{FieldType, Value};
+use crate::trace::{write_bits, Tick, TraceInputs};
 
-pub fn run<C: Chrono, IP: InputPin, OP: OutputPin>(
+pub fn run<C: Chrono, IP: InputPin, OP: LedBank<5>>(
     params: &Parameters,
     ips: &mut InputPeripherals<C, IP>,
     ops: &mut OutputPeripherals<OP>,
 ) -> ! {
-    let mut state = State::ShowingResult(DiceRoll::from_int_modulo(6));
-    loop {
-        let inputs = read_inputs(ips);
-        // Generate a new state of the game instead of mutating the current state
-        let new_state = update_state(params, state, inputs);
-        state = new_state;
-        // Build the appropriate output values from the new state
-        let outputs = make_outputs(&state);
-        apply_outputs(outputs, ops)
+    exercise::run::<Dice<C, IP, OP>>(params, ips, ops)
+}
+
+// Dice plugs the functions of this module into the Exercise trait
+pub struct Dice<C, IP, OP>(PhantomData<(C, IP, OP)>);
+
+impl<C: Chrono, IP: InputPin, OP: LedBank<5>> Exercise for Dice<C, IP, OP> {
+    type Parameters = Parameters;
+    type Inputs = Inputs;
+    type State = State;
+    type Outputs = Outputs;
+    type InputPeripherals = InputPeripherals<C, IP>;
+    type OutputPeripherals = OutputPeripherals<OP>;
+    type Error = Fault<IP::Error, OP::Error>;
+
+    fn init(_: &Parameters) -> State {
+        State::ShowingResult(DiceRoll::from_int_modulo(6))
+    }
+
+    fn read_inputs(ps: &mut InputPeripherals<C, IP>) -> Result<Inputs, Self::Error> {
+        read_inputs(ps).map_err(Fault::Input)
+    }
+
+    fn advance(p: &Parameters, s: State, inputs: Inputs) -> (State, Outputs) {
+        advance(p, s, inputs)
+    }
+
+    fn apply_outputs(
+        _: &Parameters,
+        outputs: Outputs,
+        ps: &mut OutputPeripherals<OP>,
+    ) -> Result<(), Self::Error> {
+        apply_outputs(outputs, ps).map_err(Fault::Output)
+    }
+
+    fn stop(_: &Parameters, _: &Self::Error, ps: &mut OutputPeripherals<OP>) {
+        stop(ps)
+    }
+
+    // The result is shown until the button is pressed
+    fn sleep(_: &Parameters, s: &State) -> Sleep {
+        match s {
+            State::ShowingResult(_) => Sleep::Deep,
+            State::Blinking(..) => Sleep::Idle,
+        }
     }
 }
 
 // A wrapper that contains a dice roll between 1 and 6
-struct DiceRoll(u8);
+pub struct DiceRoll(u8);
 
 impl DiceRoll {
-    fn from_int_modulo(v: u32) -> DiceRoll {
-        DiceRoll((((v - 1) % 6) + 1) as u8)
+    pub fn from_int_modulo(v: u32) -> DiceRoll {
+        DiceRoll(((v.wrapping_sub(1) % 6) + 1) as u8)
     }
 
-    fn to_u8(&self) -> u8 {
+    pub fn to_u8(&self) -> u8 {
         self.0
     }
 }
 
-struct Inputs {
-    ms: u32,
-    button_pressed: bool,
-    roll: DiceRoll,
+pub struct Inputs {
+    pub now: Instant,
+    pub button_pressed: bool,
+    pub roll: DiceRoll,
 }
 
-struct Outputs {
-    leds: [bool; 5],
+pub struct Outputs {
+    pub leds: [bool; 5],
 }
 
-enum State {
+pub enum State {
     Blinking(
         u8, /*blink index */
         DiceRoll,
-        u32, /* blink end time */
+        Timer, /* blink end */
     ),
     ShowingResult(DiceRoll),
 }
 
+#[derive(Clone)]
 pub struct Parameters {
     // The number of blink before the players have to press their button
     pub nb_blinks: u8,
-    pub first_blink_duration_ms: u32,
-    pub last_blink_duration_ms: u32,
+    pub first_blink_duration: Duration,
+    pub last_blink_duration: Duration,
+}
+
+pub fn advance(p: &Parameters, s: State, inputs: Inputs) -> (State, Outputs) {
+    // Generate a new state of the game instead of mutating the current state
+    let new_state = update_state(p, s, inputs);
+    // Build the appropriate output values from the new state
+    let outputs = make_outputs(&new_state);
+    (new_state, outputs)
 }
 
-fn update_state(p: &Parameters, s: State, input: Inputs) -> State {
+pub fn update_state(p: &Parameters, s: State, input: Inputs) -> State {
     match s {
-        State::Blinking(blink_number, _, blink_end_time) => {
-            if input.ms > blink_end_time {
+        State::Blinking(blink_number, _, blink_end) => {
+            if blink_end.expired(input.now) {
                 // If we spent enough time on the current dice roll, move to the
                 // next state
-                let next_blink_number = blink_number + 1;
-                if next_blink_number > p.nb_blinks {
+                if blink_number >= p.nb_blinks {
                     // If it has blinked enough times, show the result
                     State::ShowingResult(input.roll)
                 } else {
                     // If it has not blinked, blink on another dice roll, for a
-                    // longer duration
+                    // longer duration. It can't overflow as it is below
+                    // nb_blinks.
+                    let next_blink_number = blink_number + 1;
                     State::Blinking(
                         next_blink_number,
                         input.roll,
-                        input.ms + blink_duration_at(p, next_blink_number),
+                        Timer::start(input.now, blink_duration_at(p, next_blink_number)),
                     )
                 }
             } else {
@@ -88,7 +144,11 @@ fn update_state(p: &Parameters, s: State, input: Inputs) -> State {
                 // If the button is pressed, throw the dice again
                 if p.nb_blinks > 0 {
                     // If we configured some blinking, blink
-                    State::Blinking(0, input.roll, p.first_blink_duration_ms + input.ms)
+                    State::Blinking(
+                        0,
+                        input.roll,
+                        Timer::start(input.now, p.first_blink_duration),
+                    )
                 } else {
                     // Otherwise, move directly to a new result
                     State::ShowingResult(input.roll)
@@ -101,13 +161,22 @@ fn update_state(p: &Parameters, s: State, input: Inputs) -> State {
     }
 }
 
-// computes the duration of the i-th blink, using an affine function
-fn blink_duration_at(p: &Parameters, i: u8) -> u32 {
-    p.first_blink_duration_ms
-        + ((p.last_blink_duration_ms - p.first_blink_duration_ms) * i as u32) / p.nb_blinks as u32
+// computes the duration of the i-th blink, using an affine function.
+// The blinks can get longer or shorter, the multiplication saturates on
+// durations too long to make sense for a dice.
+fn blink_duration_at(p: &Parameters, i: u8) -> Duration {
+    let first = p.first_blink_duration.as_millis();
+    let last = p.last_blink_duration.as_millis();
+    let step = |range: u32| range.saturating_mul(i as u32) / p.nb_blinks as u32;
+    let ms = if last >= first {
+        first + step(last - first)
+    } else {
+        first - step(first - last)
+    };
+    Duration::from_millis(ms)
 }
 
-fn make_outputs(s: &State) -> Outputs {
+pub fn make_outputs(s: &State) -> Outputs {
     let roll = match s {
         State::Blinking(_, roll, _) => roll,
         State::ShowingResult(roll) => roll,
@@ -147,6 +216,68 @@ fn u8_to_array5(v: u8) -> [bool; 5] {
     }
 }
 
+impl TraceInputs for Inputs {
+    fn from_tick(tick: &Tick) -> Inputs {
+        Inputs {
+            now: Instant::from_millis(tick.ms),
+            button_pressed: tick.button(0),
+            // The rng is not recorded, the roll is derived from the time to
+            // keep the replay deterministic
+            roll: DiceRoll::from_int_modulo(tick.ms),
+        }
+    }
+
+    fn to_tick(&self) -> Tick {
+        Tick {
+            ms: self.now.as_millis(),
+            buttons: self.button_pressed as u8,
+            nb_buttons: 1,
+            level: None,
+            roll: None,
+        }
+    }
+}
+
+impl uDisplay for Outputs {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error> {
+        f.write_str("leds=")?;
+        write_bits(f, self.leds.iter().copied())
+    }
+}
+
+impl Tunable for Parameters {
+    const FIELDS: &'static [(&'static str, FieldType)] = &[
+        ("nb_blinks", FieldType::U8),
+        ("first_blink_ms", FieldType::U32),
+        ("last_blink_ms", FieldType::U32),
+    ];
+
+    fn get(&self, field: &str) -> Option<Value> {
+        match field {
+            "nb_blinks" => Some(Value::U8(self.nb_blinks)),
+            "first_blink_ms" => Some(Value::U32(self.first_blink_duration.as_millis())),
+            "last_blink_ms" => Some(Value::U32(self.last_blink_duration.as_millis())),
+            _ => None,
+        }
+    }
+
+    // Every value works, 0 blinks shows the result directly and up to 255
+    // blinks before it
+    fn set(&mut self, field: &str, value: Value) -> Result<(), TuneError> {
+        match (field, value) {
+            ("nb_blinks", Value::U8(n)) => self.nb_blinks = n,
+            ("first_blink_ms", Value::U32(ms)) => {
+                self.first_blink_duration = Duration::from_millis(ms)
+            }
+            ("last_blink_ms", Value::U32(ms)) => {
+                self.last_blink_duration = Duration::from_millis(ms)
+            }
+            _ => return Err(TuneError::UnknownField),
+        }
+        Ok(())
+    }
+}
+
 pub struct InputPeripherals<T: Chrono, IP: InputPin> {
     pub chrono: T,
     pub button: IP,
@@ -154,22 +285,56 @@ pub struct InputPeripherals<T: Chrono, IP: InputPin> {
     pub rng: SmallRng,
 }
 
-fn read_inputs<T: Chrono, IP: InputPin>(ps: &mut InputPeripherals<T, IP>) -> Inputs {
-    Inputs {
-        ms: ps.chrono.millis(),
-        button_pressed: ps.button.is_low().unwrap(),
+pub fn read_inputs<T: Chrono, IP: InputPin>(
+    ps: &mut InputPeripherals<T, IP>,
+) -> Result<Inputs, IP::Error> {
+    Ok(Inputs {
+        now: ps.chrono.now(),
+        button_pressed: ps.button.is_low()?,
         // We get a new roll on each loop, even if we don't use it everytime
         roll: DiceRoll::from_int_modulo(ps.rng.next_u32()),
-    }
+    })
+}
+
+pub struct OutputPeripherals<OP: LedBank<5>> {
+    pub leds: OP,
 }
 
-pub struct OutputPeripherals<OP: OutputPin> {
-    pub leds: [OP; 5],
+pub fn apply_outputs<T: LedBank<5>>(
+    outputs: Outputs,
+    pins: &mut OutputPeripherals<T>,
+) -> Result<(), T::Error> {
+    let mask = outputs
+        .leds
+        .iter()
+        .rev()
+        .fold(0, |mask, on| mask << 1 | *on as Mask);
+    pins.leds.show(mask)
 }
 
-fn apply_outputs<T: OutputPin>(outputs: Outputs, pins: &mut OutputPeripherals<T>) -> () {
-    // no alloc zip magic
-    for (pin, state) in pins.leds.iter_mut().zip(outputs.leds.iter()) {
-        pin.set_state(PinState::from(*state)).unwrap()
+// stop turns all the leds off
+pub fn stop<T: LedBank<5>>(pins: &mut OutputPeripherals<T>) {
+    pins.leds.off().unwrap_or(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::assert_golden;
+
+    #[test]
+    fn test_traces() {
+        let p = Parameters {
+            nb_blinks: 3,
+            first_blink_duration: Duration::from_millis(100),
+            last_blink_duration: Duration::from_millis(300),
+        };
+        assert_golden(
+            "timing",
+            || State::ShowingResult(DiceRoll::from_int_modulo(6)),
+            |s, inputs| advance(&p, s, inputs),
+            include_str!("../traces/dice_timing.trace"),
+            include_str!("../traces/dice_timing.golden"),
+        )
     }
 }
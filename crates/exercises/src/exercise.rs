@@ -0,0 +1,338 @@
+//! This module contains the trait shared by every exercise and the generic
+//! loop that runs them.
+//!
+//! Every exercise is split the same way:
+//! - `read_inputs` reads the input peripherals into a plain `Inputs` struct
+//! - `advance` is the pure core logic. It builds a new `State` and the
+//!   `Outputs` from the previous state and the inputs
+//! - `apply_outputs` writes the `Outputs` to the output peripherals
+//!
+//! Only `advance` contains the logic of the exercise, which is why it is the
+//! one tested on the computer.
+//!
+//! The peripherals can fail (e.g. pins behind an i2c expander). The first
+//! error stops the exercise: `stop` puts the outputs in a safe state and the
+//! runner stays in a fault state.
+//!
+//! Between two loops the runner can put the mcu to sleep, as deep as the
+//! state of the exercise allows (see `sleep`), to save the battery.
+
+use ufmt::{uDisplay, uWrite};
+
+pub trait Exercise {
+    // The params of the exercise, the runner keeps a copy that can be tuned
+    // between two loops (see the console module)
+    type Parameters: Clone;
+    // The values read from the input peripherals on each loop
+    type Inputs;
+    // Everything the exercise remembers between two loops
+    type State;
+    // The values written to the output peripherals on each loop
+    type Outputs;
+    type InputPeripherals;
+    type OutputPeripherals;
+    // The error of the peripherals, usually a Fault
+    type Error;
+
+    // The state in which the exercise starts
+    fn init(p: &Self::Parameters) -> Self::State;
+
+    fn read_inputs(ps: &mut Self::InputPeripherals) -> Result<Self::Inputs, Self::Error>;
+
+    fn advance(
+        p: &Self::Parameters,
+        s: Self::State,
+        inputs: Self::Inputs,
+    ) -> (Self::State, Self::Outputs);
+
+    fn apply_outputs(
+        p: &Self::Parameters,
+        outputs: Self::Outputs,
+        ps: &mut Self::OutputPeripherals,
+    ) -> Result<(), Self::Error>;
+
+    // stop is called once after an error. It turns the outputs off and signals
+    // the error when the exercise has a way to do it (e.g. a serial port).
+    // It is best effort, its own errors are ignored.
+    fn stop(p: &Self::Parameters, error: &Self::Error, ps: &mut Self::OutputPeripherals);
+
+    // sleep is how deep the mcu can sleep after a loop in the state s. By
+    // default it doesn't, e.g. to poll an adc as fast as possible.
+    fn sleep(_p: &Self::Parameters, _s: &Self::State) -> Sleep {
+        Sleep::Busy
+    }
+}
+
+// Sleep is how the mcu waits for the next loop, from the lightest to the
+// deepest
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Sleep {
+    // No sleep, the next loop starts right away
+    Busy,
+    // Until the next interrupt: a tick of the chrono, a button or a byte on
+    // the serial port
+    Idle,
+    // Until a button is pressed. The chrono stops meanwhile, only for the
+    // states that wait for a button without looking at the time.
+    Deep,
+}
+
+// Idle puts the mcu to sleep, it returns once an interrupt woke it up
+pub trait Idle {
+    fn sleep(&mut self, depth: Sleep);
+}
+
+// NoIdle never sleeps, the loop runs as fast as possible
+pub struct NoIdle;
+
+impl Idle for NoIdle {
+    fn sleep(&mut self, _: Sleep) {}
+}
+
+// Fault is the error of an exercise: a peripheral failed while reading the
+// inputs or while writing the outputs. It keeps the error of the HAL.
+#[derive(Debug, PartialEq)]
+pub enum Fault<I, O> {
+    Input(I),
+    Output(O),
+}
+
+impl<I, O> uDisplay for Fault<I, O> {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error> {
+        match self {
+            Fault::Input(_) => f.write_str("fault: input"),
+            Fault::Output(_) => f.write_str("fault: output"),
+        }
+    }
+}
+
+// run is the main loop of every exercise, it never returns
+pub fn run<E: Exercise>(
+    p: &E::Parameters,
+    ips: &mut E::InputPeripherals,
+    ops: &mut E::OutputPeripherals,
+) -> ! {
+    Runner::<E>::new(p, ips, ops).run()
+}
+
+// run_idle is run with the mcu sleeping between the loops
+pub fn run_idle<E: Exercise, I: Idle>(
+    p: &E::Parameters,
+    ips: &mut E::InputPeripherals,
+    ops: &mut E::OutputPeripherals,
+    idle: &mut I,
+) -> ! {
+    Runner::<E>::new(p, ips, ops).run_idle(idle)
+}
+
+// Runner holds everything an exercise needs to run, so that the loop can be
+// driven one step at a time (e.g. from the tests with mock peripherals)
+pub struct Runner<'a, E: Exercise> {
+    params: E::Parameters,
+    ips: &'a mut E::InputPeripherals,
+    ops: &'a mut E::OutputPeripherals,
+    // The state is moved out during a step because advance consumes it.
+    // It is always present between two steps.
+    state: Option<E::State>,
+    // The error that stopped the exercise
+    fault: Option<E::Error>,
+}
+
+impl<'a, E: Exercise> Runner<'a, E> {
+    pub fn new(
+        p: &E::Parameters,
+        ips: &'a mut E::InputPeripherals,
+        ops: &'a mut E::OutputPeripherals,
+    ) -> Runner<'a, E> {
+        Runner {
+            params: p.clone(),
+            ips,
+            ops,
+            state: Some(E::init(p)),
+            fault: None,
+        }
+    }
+
+    // step runs a single read -> advance -> apply iteration of the loop.
+    // It does nothing once the exercise is stopped by a fault.
+    pub fn step(&mut self) {
+        if self.fault.is_some() {
+            return;
+        }
+        if let Err(e) = self.try_step() {
+            E::stop(&self.params, &e, self.ops);
+            self.fault = Some(e);
+        }
+    }
+
+    fn try_step(&mut self) -> Result<(), E::Error> {
+        let inputs = E::read_inputs(self.ips)?;
+        let state = self.state.take().unwrap();
+        // Generate a new state instead of mutating the current state
+        let (new_state, outputs) = E::advance(&self.params, state, inputs);
+        self.state = Some(new_state);
+        E::apply_outputs(&self.params, outputs, self.ops)
+    }
+
+    // run_for runs n iterations of the loop then gives the control back
+    pub fn run_for(&mut self, n: u32) {
+        for _ in 0..n {
+            self.step()
+        }
+    }
+
+    pub fn run(&mut self) -> ! {
+        loop {
+            self.step()
+        }
+    }
+
+    pub fn run_idle<I: Idle>(&mut self, idle: &mut I) -> ! {
+        loop {
+            self.step();
+            idle.sleep(self.sleep_depth())
+        }
+    }
+
+    // sleep_depth is how deep the mcu can sleep before the next step. Nothing
+    // happens anymore once the exercise is stopped.
+    pub fn sleep_depth(&self) -> Sleep {
+        match self.fault {
+            Some(_) => Sleep::Deep,
+            None => E::sleep(&self.params, self.state()),
+        }
+    }
+
+    pub fn state(&self) -> &E::State {
+        self.state.as_ref().unwrap()
+    }
+
+    pub fn params(&self) -> &E::Parameters {
+        &self.params
+    }
+
+    // params_mut changes the params, they are used from the next step
+    pub fn params_mut(&mut self) -> &mut E::Parameters {
+        &mut self.params
+    }
+
+    // fault is the error that stopped the exercise, if any
+    pub fn fault(&self) -> Option<&E::Error> {
+        self.fault.as_ref()
+    }
+
+    // The peripherals are borrowed by the runner, these give them back
+    // between two steps (e.g. to move a mock clock forward)
+    pub fn input_peripherals(&mut self) -> &mut E::InputPeripherals {
+        self.ips
+    }
+
+    pub fn output_peripherals(&mut self) -> &mut E::OutputPeripherals {
+        self.ops
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A minimal exercise that sums the values it reads and outputs the sum
+    struct Sum;
+
+    impl Exercise for Sum {
+        type Parameters = ();
+        type Inputs = u32;
+        type State = u32;
+        type Outputs = u32;
+        type InputPeripherals = u32;
+        type OutputPeripherals = [u32; 4];
+        // The input fails on u32::MAX
+        type Error = Fault<(), ()>;
+
+        fn init(_: &()) -> u32 {
+            0
+        }
+
+        fn read_inputs(ps: &mut u32) -> Result<u32, Fault<(), ()>> {
+            if *ps == u32::MAX {
+                return Err(Fault::Input(()));
+            }
+            *ps += 1;
+            Ok(*ps)
+        }
+
+        fn advance(_: &(), s: u32, inputs: u32) -> (u32, u32) {
+            (s + inputs, s + inputs)
+        }
+
+        fn apply_outputs(_: &(), outputs: u32, ps: &mut [u32; 4]) -> Result<(), Fault<(), ()>> {
+            ps[(outputs % 4) as usize] += 1;
+            Ok(())
+        }
+
+        fn stop(_: &(), _: &Fault<(), ()>, ps: &mut [u32; 4]) {
+            *ps = [0; 4];
+        }
+
+        // Sleep while the sum is even
+        fn sleep(_: &(), s: &u32) -> Sleep {
+            if s % 2 == 1 {
+                Sleep::Busy
+            } else {
+                Sleep::Idle
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_for() {
+        let mut ips = 0;
+        let mut ops = [0; 4];
+        let mut runner = Runner::<Sum>::new(&(), &mut ips, &mut ops);
+        runner.run_for(3);
+        // 1 + 2 + 3
+        assert_eq!(6, *runner.state(), "wrong state after 3 steps");
+        *runner.input_peripherals() = 10;
+        runner.step();
+        assert_eq!(17, *runner.state(), "wrong state after a manual step");
+        // outputs were 1, 3, 6 and 17
+        assert_eq!([0, 2, 1, 1], *runner.output_peripherals(), "wrong outputs");
+    }
+
+    #[test]
+    fn test_fault() {
+        let mut ips = 0;
+        let mut ops = [0; 4];
+        let mut runner = Runner::<Sum>::new(&(), &mut ips, &mut ops);
+        runner.step();
+        *runner.input_peripherals() = u32::MAX;
+        runner.step();
+        assert_eq!(Some(&Fault::Input(())), runner.fault(), "wrong fault");
+        assert_eq!(
+            [0; 4],
+            *runner.output_peripherals(),
+            "the outputs should be stopped"
+        );
+        // The runner stays stopped even if the input works again
+        *runner.input_peripherals() = 10;
+        runner.run_for(2);
+        assert_eq!(1, *runner.state(), "the state should not move");
+    }
+
+    #[test]
+    fn test_sleep_depth() {
+        let mut ips = 0;
+        let mut ops = [0; 4];
+        let mut runner = Runner::<Sum>::new(&(), &mut ips, &mut ops);
+        assert_eq!(Sleep::Idle, runner.sleep_depth(), "wrong depth at 0");
+        runner.step();
+        assert_eq!(Sleep::Busy, runner.sleep_depth(), "wrong depth at 1");
+        *runner.input_peripherals() = u32::MAX;
+        runner.step();
+        assert_eq!(
+            Sleep::Deep,
+            runner.sleep_depth(),
+            "a stopped exercise should sleep"
+        );
+    }
+}
@@ -0,0 +1,113 @@
+//! This module is the leds of the exercises, whatever their wiring: a pin per
+//! led, a chain of shift registers, a MAX7219... The exercises give the leds
+//! to light as a mask, one bit per led from the lowest bit.
+
+use core::fmt::Debug;
+
+use embedded_hal::digital::{OutputPin, PinState};
+use embedded_hal::pwm::SetDutyCycle;
+
+use crate::pattern::{Mask, MAX_LEDS};
+
+// LedBank is a row of N leds
+pub trait LedBank<const N: usize> {
+    type Error: Debug;
+
+    // show turns on the leds of the mask and off the others
+    fn show(&mut self, mask: Mask) -> Result<(), Self::Error>;
+
+    // dim sets the brightness of each led, from 0 (off) to 255. The banks
+    // that can't dim their leds only light those at full brightness.
+    fn dim(&mut self, levels: &[u8; N]) -> Result<(), Self::Error> {
+        self.show(full_leds(levels))
+    }
+
+    // off turns all the leds off
+    fn off(&mut self) -> Result<(), Self::Error> {
+        self.show(0)
+    }
+}
+
+// full_leds is the mask of the leds at full brightness
+pub fn full_leds<const N: usize>(levels: &[u8; N]) -> Mask {
+    const { assert!(N <= MAX_LEDS, "too many leds for the mask") };
+    levels
+        .iter()
+        .enumerate()
+        .filter(|(_, level)| **level == u8::MAX)
+        .fold(0, |mask, (idx, _)| mask | 1 << idx)
+}
+
+// A led per pin, the led idx is on pins[idx]
+impl<T: OutputPin, const N: usize> LedBank<N> for [T; N] {
+    type Error = T::Error;
+
+    fn show(&mut self, mask: Mask) -> Result<(), T::Error> {
+        const { assert!(N <= MAX_LEDS, "too many leds for the mask") };
+        for (idx, pin) in self.iter_mut().enumerate() {
+            pin.set_state(PinState::from(mask >> idx & 0x1 != 0))?
+        }
+        Ok(())
+    }
+}
+
+// Dimmed is a led per pwm, e.g. a software pwm on the uno
+pub struct Dimmed<T, const N: usize>(pub [T; N]);
+
+impl<T: SetDutyCycle, const N: usize> LedBank<N> for Dimmed<T, N> {
+    type Error = T::Error;
+
+    fn show(&mut self, mask: Mask) -> Result<(), T::Error> {
+        const { assert!(N <= MAX_LEDS, "too many leds for the mask") };
+        for (idx, pwm) in self.0.iter_mut().enumerate() {
+            if mask >> idx & 0x1 != 0 {
+                pwm.set_duty_cycle_fully_on()?
+            } else {
+                pwm.set_duty_cycle_fully_off()?
+            }
+        }
+        Ok(())
+    }
+
+    fn dim(&mut self, levels: &[u8; N]) -> Result<(), T::Error> {
+        for (level, pwm) in levels.iter().zip(self.0.iter_mut()) {
+            pwm.set_duty_cycle_fraction(*level as u16, 255)?
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_full_leds() {
+        struct TestCase {
+            levels: [u8; 5],
+            expected: Mask,
+        }
+        let cases = [
+            TestCase {
+                levels: [0; 5],
+                expected: 0,
+            },
+            TestCase {
+                levels: [255, 254, 0, 255, 1],
+                expected: 0b01001,
+            },
+            TestCase {
+                levels: [255; 5],
+                expected: 0b11111,
+            },
+        ];
+        for case in cases {
+            assert_eq!(
+                case.expected,
+                full_leds(&case.levels),
+                "wrong mask for {:?}",
+                case.levels
+            );
+        }
+    }
+}
@@ -1,11 +1,23 @@
 #![no_std]
 
 pub mod adc;
+pub mod button;
 pub mod chenillard;
 pub mod chrono;
+pub mod console;
 pub mod cowboy;
 pub mod debounce;
 pub mod dice;
+pub mod exercise;
+pub mod led_bank;
 pub mod low_pass;
+pub mod max7219;
+pub mod pattern;
+pub mod persist;
+pub mod scheduler;
+pub mod sequencer;
+pub mod shift_register;
+pub mod telemetry;
+pub mod trace;
 pub mod vumetre;
 pub mod wiper;
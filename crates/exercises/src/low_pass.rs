@@ -1,3 +1,4 @@
+use crate::chrono::Instant;
 use fixed::{
     traits::{Fixed, ToFixed},
     types::U1F15,
@@ -11,11 +12,12 @@ pub struct FixedLowPassState {
     // The current value of the average
     current_average: U1F15,
     // The current (latest) time at which the average was computed
-    current_ts: u32,
+    current_ts: Instant,
 }
 
 // FixedLowPassParams are the const params of the low pass
 // They're in a separate struct so they can stay in the flash and not consume RAM.
+#[derive(Clone)]
 pub struct FixedLowPassParams {
     // A value between 0 and 1 (U1F15::ONE) to decide of the average window size
     // A bigger lambda makes an average over a longer time (more noise filtering)
@@ -27,13 +29,18 @@ pub struct FixedLowPassParams {
 impl FixedLowPassState {
     pub fn new() -> FixedLowPassState {
         FixedLowPassState {
-            current_ts: 0,
+            current_ts: Instant::from_millis(0),
             current_average: U1F15::ZERO,
         }
     }
 
-    pub fn advance(self, p: &FixedLowPassParams, x: U1F15, timestamp: u32) -> FixedLowPassState {
-        let delta_t = timestamp - self.current_ts;
+    pub fn advance(
+        self,
+        p: &FixedLowPassParams,
+        x: U1F15,
+        timestamp: Instant,
+    ) -> FixedLowPassState {
+        let delta_t = timestamp.duration_since(self.current_ts).as_millis();
         let k = pow_int(U1F15::ONE - p.lambda, delta_t, U1F15::ONE);
         let new_average = k.wide_mul(self.current_average) + (U1F15::ONE - k).wide_mul(x);
         FixedLowPassState {
@@ -105,4 +112,22 @@ mod tests {
             assert_eq!(tc.expected, actual, "wrong exponentiation")
         }
     }
+
+    #[test]
+    fn test_rollover() {
+        let p = FixedLowPassParams {
+            lambda: U1F15::lit("1e-1"),
+        };
+        let run = |start: u32| {
+            FixedLowPassState::new()
+                .advance(&p, U1F15::ZERO, Instant::from_millis(start))
+                .advance(&p, U1F15::ONE, Instant::from_millis(start.wrapping_add(10)))
+                .current_val()
+        };
+        assert_eq!(
+            run(1_000),
+            run(u32::MAX - 4),
+            "the rollover should not change the average"
+        );
+    }
 }
@@ -0,0 +1,138 @@
+//! This module drives leds with a MAX7219, a driver for up to 64 leds: the
+//! leds are wired as a matrix of 8 digits of 8 segments, which the chip scans
+//! on its own. It only needs the current of a single resistor and no pin per
+//! led. The masks of the exercises only light the first MAX_LEDS leds, 16
+//! leds on 2 digits.
+//!
+//! The MAX7219 reads 16 bits words, a register then its value, shifted in as
+//! with a 74HC595: CLK to SHCP, DIN to DS and LOAD to STCP. Any bus of the
+//! shift_register module works.
+
+use crate::led_bank::LedBank;
+use crate::pattern::{Mask, MAX_LEDS};
+use crate::shift_register::Bus;
+
+// The registers of the chip, the digit d is at DIGIT_0 + d
+const DIGIT_0: u8 = 0x01;
+const DECODE_MODE: u8 = 0x09;
+const INTENSITY: u8 = 0x0a;
+const SCAN_LIMIT: u8 = 0x0b;
+const SHUTDOWN: u8 = 0x0c;
+const DISPLAY_TEST: u8 = 0x0f;
+
+// The leds are as bright as the chip allows at MAX_INTENSITY
+pub const MAX_INTENSITY: u8 = 15;
+
+// The digits lit by a mask
+const NB_DIGITS: usize = MAX_LEDS.div_ceil(8);
+
+// Max7219 lights the led idx on the segment idx % 8 of the digit idx / 8
+pub struct Max7219<B> {
+    bus: B,
+    intensity: u8,
+    digits: [u8; NB_DIGITS],
+    // False until the chip is set up, it starts shut down
+    ready: bool,
+}
+
+impl<B: Bus> Max7219<B> {
+    // new drives the chip on bus, with an intensity up to MAX_INTENSITY. The
+    // chip is set up on the first leds shown.
+    pub fn new(bus: B, intensity: u8) -> Max7219<B> {
+        Max7219 {
+            bus,
+            intensity: intensity.min(MAX_INTENSITY),
+            digits: [0; NB_DIGITS],
+            ready: false,
+        }
+    }
+
+    fn write(&mut self, register: u8, value: u8) -> Result<(), B::Error> {
+        // The register is shifted out first
+        self.bus.write(&[value, register])
+    }
+
+    fn setup(&mut self, nb_digits: usize) -> Result<(), B::Error> {
+        self.write(DISPLAY_TEST, 0)?;
+        // The segments are the leds, not the code of a figure
+        self.write(DECODE_MODE, 0)?;
+        self.write(SCAN_LIMIT, nb_digits.max(1) as u8 - 1)?;
+        self.write(INTENSITY, self.intensity)?;
+        for digit in 0..nb_digits {
+            self.write(DIGIT_0 + digit as u8, self.digits[digit])?;
+        }
+        self.write(SHUTDOWN, 1)
+    }
+}
+
+impl<B: Bus, const N: usize> LedBank<N> for Max7219<B> {
+    type Error = B::Error;
+
+    fn show(&mut self, mask: Mask) -> Result<(), B::Error> {
+        const { assert!(N <= MAX_LEDS, "too many leds for the mask") };
+        let nb_digits = N.div_ceil(8);
+        if !self.ready {
+            self.setup(nb_digits)?;
+            self.ready = true;
+        }
+        for digit in 0..nb_digits {
+            let leds = (N - 8 * digit).min(8);
+            let segments = (mask >> (8 * digit)) as u8 & (u8::MAX >> (8 - leds));
+            if segments != self.digits[digit] {
+                self.write(DIGIT_0 + digit as u8, segments)?;
+                self.digits[digit] = segments;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+
+    // Words records the words written, up to 16
+    #[derive(Default)]
+    struct Words {
+        words: [u16; 16],
+        len: usize,
+    }
+
+    impl Bus for Words {
+        type Error = Infallible;
+
+        fn write(&mut self, chips: &[u8]) -> Result<(), Infallible> {
+            assert_eq!(2, chips.len(), "a word is 2 bytes");
+            self.words[self.len] = u16::from_be_bytes([chips[1], chips[0]]);
+            self.len += 1;
+            Ok(())
+        }
+    }
+
+    impl Words {
+        fn take(&mut self) -> &[u16] {
+            let len = core::mem::take(&mut self.len);
+            &self.words[..len]
+        }
+    }
+
+    #[test]
+    fn test_show() {
+        let mut max = Max7219::new(Words::default(), 20);
+
+        LedBank::<12>::show(&mut max, 0xf0ff).unwrap();
+        assert_eq!(
+            [0x0f00, 0x0900, 0x0b01, 0x0a0f, 0x0100, 0x0200, 0x0c01, 0x01ff],
+            max.bus.take(),
+            "the chip should be set up then the leds shown"
+        );
+        // The leds past the 12th are ignored
+        LedBank::<12>::show(&mut max, 0x00ff).unwrap();
+        assert_eq!([0; 0], max.bus.take(), "nothing should change");
+        LedBank::<12>::show(&mut max, 0x0f0f).unwrap();
+        assert_eq!([0x010f, 0x020f], max.bus.take(), "wrong digits written");
+        LedBank::<12>::off(&mut max).unwrap();
+        assert_eq!([0x0100, 0x0200], max.bus.take(), "wrong digits written");
+    }
+}
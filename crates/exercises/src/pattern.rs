@@ -0,0 +1,451 @@
+//! This module contains the patterns of the chenillard: each one maps the
+//! phase, the position in its cycles, to the leds that are on.
+//!
+//! A pattern is a cycle of steps spread over the period of the chenillard,
+//! e.g. the N positions of a single led walking from the first of the N leds
+//! to the last. The patterns work with any number of leds up to MAX_LEDS.
+//!
+//! The leds can also leave a trail: a led that was on in the previous steps
+//! fades out, its brightness is halved at each step.
+
+use crate::chrono::{Duration, Instant};
+
+// Mask is the leds that are on, one bit per led from the lowest bit
+pub type Mask = u16;
+
+// The most leds of a pattern, e.g. a strip of 16 leds
+pub const MAX_LEDS: usize = Mask::BITS as usize;
+
+// The longest trail, the brightness is 0 after 8 halvings
+pub const MAX_TRAIL: u8 = 7;
+
+// Phase is the position in the cycles of a pattern, in 2^-32 of a cycle.
+// It is advanced loop by loop at the current speed, so the pattern goes on
+// from where it was when the speed changes. It wraps around after 2^32
+// cycles, a whole number of cycles, so the leds don't jump.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Phase(u64);
+
+impl Phase {
+    pub const ZERO: Phase = Phase(0);
+
+    const ONE_CYCLE: u64 = 1 << 32;
+
+    // at is the phase reached at now by a pattern going at a constant speed
+    // of a cycle per period since the start of the chrono
+    pub fn at(now: Instant, period: Duration) -> Phase {
+        let (ms, period_ms) = (now.as_millis() as u64, period.as_millis() as u64);
+        Phase((ms / period_ms) * Phase::ONE_CYCLE + (ms % period_ms) * Phase::ONE_CYCLE / period_ms)
+    }
+
+    // advance is the phase after elapsed at a speed of a cycle per period,
+    // times speed, backward if reverse. speed has 15 fractional bits, as a
+    // U1F15 level, and is below 2^20.
+    // The elapsed time is capped to a minute, a loop is never that long.
+    pub fn advance(self, elapsed: Duration, period: Duration, speed: u32, reverse: bool) -> Phase {
+        let ms = elapsed.as_millis().min(60_000) as u64;
+        // Less than 2^16 * 2^20 * 2^17, it doesn't overflow
+        let delta = ((ms * speed as u64) << 17) / period.as_millis() as u64;
+        if reverse {
+            Phase(self.0.wrapping_sub(delta))
+        } else {
+            Phase(self.0.wrapping_add(delta))
+        }
+    }
+
+    // steps is the number of whole steps of nb_steps per cycle
+    pub fn steps(self, nb_steps: u32) -> u64 {
+        let nb_steps = nb_steps as u64;
+        (self.0 >> 32) * nb_steps + (((self.0 & (Phase::ONE_CYCLE - 1)) * nb_steps) >> 32)
+    }
+}
+
+// Pattern maps the phase to the leds that are on, for N leds
+pub trait Pattern<const N: usize> {
+    // nb_steps is the number of steps of a cycle
+    fn nb_steps(&self) -> u32;
+
+    // mask is the leds that are on at a step counted since the phase 0, one
+    // bit per led
+    fn mask(&self, steps: u64) -> Mask;
+
+    // leds is the mask at phase
+    fn leds(&self, phase: Phase) -> Mask {
+        self.mask(phase.steps(self.nb_steps()))
+    }
+
+    // brightness is the level of each led at phase, from 0 to 255, with the
+    // leds of the trail previous steps fading out. The previous steps are the
+    // next ones when the pattern goes backward.
+    fn brightness(&self, phase: Phase, trail: u8, reverse: bool) -> [u8; N] {
+        const { assert!(N <= MAX_LEDS, "too many leds for the mask") };
+        let steps = phase.steps(self.nb_steps());
+        let mut levels = [0; N];
+        for age in 0..=trail.min(MAX_TRAIL) as u64 {
+            let step = if reverse {
+                steps.checked_add(age)
+            } else {
+                steps.checked_sub(age)
+            };
+            let Some(step) = step else {
+                break;
+            };
+            let mask = self.mask(step);
+            for (led_idx, level) in levels.iter_mut().enumerate() {
+                if mask >> led_idx & 0x1 != 0 {
+                    *level = (*level).max(255 >> age);
+                }
+            }
+        }
+        levels
+    }
+}
+
+// Builtin are the patterns of the chenillard, in the order a button cycles
+// through them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Builtin {
+    // A single led walking from the first led to the last
+    Walk,
+    // A single led going back and forth, as the car of Knight Rider
+    PingPong,
+    // The leds turn on one after the other, then off in the reverse order
+    Fill,
+    // The leds count in binary
+    Counter,
+    // Random leds flash
+    Sparkle,
+    // Two leds walk from both ends to the middle
+    Converge,
+}
+
+impl Builtin {
+    pub const ALL: [Builtin; 6] = [
+        Builtin::Walk,
+        Builtin::PingPong,
+        Builtin::Fill,
+        Builtin::Counter,
+        Builtin::Sparkle,
+        Builtin::Converge,
+    ];
+
+    pub fn from_index(idx: u8) -> Option<Builtin> {
+        Builtin::ALL.get(idx as usize).copied()
+    }
+
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+
+    // next is the pattern after self, back to the first after the last
+    pub fn next(self) -> Builtin {
+        Builtin::ALL[(self.index() as usize + 1) % Builtin::ALL.len()]
+    }
+}
+
+impl<const N: usize> Pattern<N> for Builtin {
+    fn nb_steps(&self) -> u32 {
+        let nb_leds = N as u32;
+        match self {
+            Builtin::Walk => nb_leds,
+            // The ends are not repeated, a single led doesn't move
+            Builtin::PingPong => (2 * nb_leds).saturating_sub(2).max(1),
+            // From 1 led to N then back to 0
+            Builtin::Fill => 2 * nb_leds,
+            Builtin::Counter => 1 << nb_leds,
+            Builtin::Sparkle => 2 * nb_leds,
+            // The middle led of an odd count is reached by both
+            Builtin::Converge => nb_leds.div_ceil(2),
+        }
+    }
+
+    fn mask(&self, steps: u64) -> Mask {
+        let nb_steps = Pattern::<N>::nb_steps(self);
+        let step = (steps % nb_steps as u64) as u32;
+        match self {
+            Builtin::Walk => 1 << step,
+            Builtin::PingPong => 1 << step.min(nb_steps - step),
+            Builtin::Fill => {
+                let nb_on = if step < N as u32 {
+                    step + 1
+                } else {
+                    nb_steps - 1 - step
+                };
+                first_leds(nb_on)
+            }
+            Builtin::Counter => step as Mask,
+            // The whole count of steps is hashed, so the cycles differ
+            Builtin::Sparkle => {
+                let h = hash(steps as u32);
+                // About a led out of 4
+                (h & h >> 16) as Mask & first_leds(N as u32)
+            }
+            Builtin::Converge => 1 << step | 1 << (N as u32 - 1 - step),
+        }
+    }
+}
+
+// first_leds is the mask of the nb_leds first leds
+pub(crate) fn first_leds(nb_leds: u32) -> Mask {
+    ((1u32 << nb_leds) - 1) as Mask
+}
+
+// hash scrambles the bits of x, enough to look random to the eye
+fn hash(x: u32) -> u32 {
+    let x = (x ^ x >> 16).wrapping_mul(0x045d_9f3b);
+    let x = (x ^ x >> 16).wrapping_mul(0x045d_9f3b);
+    x ^ x >> 16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leds() {
+        struct TestCase {
+            pattern: Builtin,
+            ms: u32,
+            expected: Mask,
+        }
+        let cases = [
+            TestCase {
+                pattern: Builtin::Walk,
+                ms: 300,
+                expected: 0b0000_0100,
+            },
+            TestCase {
+                pattern: Builtin::PingPong,
+                ms: 500,
+                expected: 0b1000_0000,
+            },
+            // On its way back
+            TestCase {
+                pattern: Builtin::PingPong,
+                ms: 800,
+                expected: 0b0000_1000,
+            },
+            TestCase {
+                pattern: Builtin::Fill,
+                ms: 200,
+                expected: 0b0000_1111,
+            },
+            TestCase {
+                pattern: Builtin::Fill,
+                ms: 900,
+                expected: 0b0000_0001,
+            },
+            TestCase {
+                pattern: Builtin::Counter,
+                ms: 500,
+                expected: 128,
+            },
+            TestCase {
+                pattern: Builtin::Converge,
+                ms: 250,
+                expected: 0b0100_0010,
+            },
+            // The next cycle is the same
+            TestCase {
+                pattern: Builtin::Converge,
+                ms: 1_750,
+                expected: 0b0001_1000,
+            },
+        ];
+        let period = Duration::from_millis(1_000);
+        for tc in cases {
+            let actual =
+                Pattern::<8>::leds(&tc.pattern, Phase::at(Instant::from_millis(tc.ms), period));
+            assert_eq!(
+                tc.expected, actual,
+                "wrong leds for {:?} at {}ms",
+                tc.pattern, tc.ms
+            );
+        }
+    }
+
+    #[test]
+    fn test_nb_leds() {
+        struct TestCase {
+            pattern: Builtin,
+            ms: u32,
+            // The leds of a 5 leds bargraph and of a 16 leds strip
+            expected: (Mask, Mask),
+        }
+        let cases = [
+            TestCase {
+                pattern: Builtin::Walk,
+                ms: 500,
+                expected: (0b0_0100, 0x0100),
+            },
+            TestCase {
+                pattern: Builtin::PingPong,
+                ms: 750,
+                expected: (0b0_0100, 0x0100),
+            },
+            TestCase {
+                pattern: Builtin::Fill,
+                ms: 450,
+                expected: (0b1_1111, 0x7fff),
+            },
+            // The 2 leds meet on the middle one
+            TestCase {
+                pattern: Builtin::Converge,
+                ms: 700,
+                expected: (0b0_0100, 0x0420),
+            },
+        ];
+        let period = Duration::from_millis(1_000);
+        for tc in cases {
+            let phase = Phase::at(Instant::from_millis(tc.ms), period);
+            let actual = (
+                Pattern::<5>::leds(&tc.pattern, phase),
+                Pattern::<16>::leds(&tc.pattern, phase),
+            );
+            assert_eq!(
+                tc.expected, actual,
+                "wrong leds for {:?} at {}ms",
+                tc.pattern, tc.ms
+            );
+        }
+    }
+
+    #[test]
+    fn test_sparkle() {
+        let period = Duration::from_millis(1_000);
+        let mut masks = [0; 64];
+        for (step, mask) in masks.iter_mut().enumerate() {
+            let now = Instant::from_millis(step as u32 * 1_000 / 16);
+            *mask = Pattern::<8>::leds(&Builtin::Sparkle, Phase::at(now, period));
+        }
+        let nb_on: u32 = masks.iter().map(|m| m.count_ones()).sum();
+        assert!(
+            (64..=192).contains(&nb_on),
+            "about 2 leds should be on, got {nb_on} over 64 steps"
+        );
+        assert_ne!(masks[..16], masks[16..32], "the cycles should differ");
+    }
+
+    #[test]
+    fn test_brightness() {
+        struct TestCase {
+            ms: u32,
+            trail: u8,
+            expected: [u8; 8],
+        }
+        let cases = [
+            TestCase {
+                ms: 300,
+                trail: 0,
+                expected: [0, 0, 255, 0, 0, 0, 0, 0],
+            },
+            // A comet walking to the last led
+            TestCase {
+                ms: 300,
+                trail: 2,
+                expected: [63, 127, 255, 0, 0, 0, 0, 0],
+            },
+            // The trail doesn't go before the start of the chrono
+            TestCase {
+                ms: 200,
+                trail: 3,
+                expected: [127, 255, 0, 0, 0, 0, 0, 0],
+            },
+            // The last leds of the previous cycle
+            TestCase {
+                ms: 1_000,
+                trail: 2,
+                expected: [255, 0, 0, 0, 0, 0, 63, 127],
+            },
+        ];
+        let period = Duration::from_millis(1_000);
+        for tc in cases {
+            let phase = Phase::at(Instant::from_millis(tc.ms), period);
+            let actual = Builtin::Walk.brightness(phase, tc.trail, false);
+            assert_eq!(
+                tc.expected, actual,
+                "wrong brightness at {}ms with a trail of {}",
+                tc.ms, tc.trail
+            );
+        }
+    }
+
+    #[test]
+    fn test_phase() {
+        struct TestCase {
+            name: &'static str,
+            ms: u32,
+            // The speed as U1F15 bits
+            speed: u32,
+            reverse: bool,
+            // The step of 8 reached
+            expected: u64,
+        }
+        // The steps go on from one case to the next
+        let cases = [
+            TestCase {
+                name: "half a cycle",
+                ms: 500,
+                speed: 1 << 15,
+                reverse: false,
+                expected: 4,
+            },
+            TestCase {
+                name: "half speed",
+                ms: 500,
+                speed: 1 << 14,
+                reverse: false,
+                expected: 6,
+            },
+            TestCase {
+                name: "stopped",
+                ms: 500,
+                speed: 0,
+                reverse: false,
+                expected: 6,
+            },
+            TestCase {
+                name: "backward",
+                ms: 250,
+                speed: 1 << 15,
+                reverse: true,
+                expected: 4,
+            },
+            TestCase {
+                name: "faster",
+                ms: 125,
+                speed: 1 << 17,
+                reverse: false,
+                expected: 8,
+            },
+        ];
+        let period = Duration::from_millis(1_000);
+        let mut phase = Phase::ZERO;
+        for tc in cases {
+            phase = phase.advance(Duration::from_millis(tc.ms), period, tc.speed, tc.reverse);
+            assert_eq!(tc.expected, phase.steps(8), "wrong step for {}", tc.name);
+        }
+
+        let now = Instant::from_millis(2_300);
+        let advanced = Phase::ZERO.advance(Duration::from_millis(2_300), period, 1 << 15, false);
+        assert_eq!(
+            Phase::at(now, period),
+            advanced,
+            "the speed should be constant"
+        );
+        // Going back from the start wraps to the end of a cycle
+        let back = Phase::ZERO.advance(Duration::from_millis(125), period, 1 << 15, true);
+        assert_eq!(7, back.steps(8) % 8, "wrong step before the start");
+    }
+
+    #[test]
+    fn test_next() {
+        let mut pattern = Builtin::Walk;
+        for _ in Builtin::ALL {
+            pattern = pattern.next();
+        }
+        assert_eq!(Builtin::Walk, pattern, "next should cycle");
+        assert_eq!(Some(Builtin::Fill), Builtin::from_index(2));
+        assert_eq!(None, Builtin::from_index(6));
+    }
+}
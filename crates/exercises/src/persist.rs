@@ -0,0 +1,227 @@
+//! This module saves the tunable params of an exercise in a small block of
+//! bytes, e.g. in the EEPROM of the uno, so a calibration survives a reset.
+//!
+//! The block is:
+//! - a magic number, to tell a block from an erased memory
+//! - the version of this format
+//! - a layout id, the crc16 of the names and types of the fields: a block
+//!   saved with other params (or another version of them) is not loaded
+//! - the length of the payload then the fields in the order of
+//!   Tunable::FIELDS, in little endian as in the telemetry
+//! - a crc16 of all the above
+//!
+//! A block that can't be loaded is reported as an error, the caller then uses
+//! the params compiled in the program.
+
+use crate::console::Tunable;
+use crate::telemetry::{crc16, crc16_update, MAX_PAYLOAD};
+
+pub const MAGIC: [u8; 2] = *b"R9";
+pub const VERSION: u8 = 1;
+
+// magic + version + layout id + payload length
+const HEADER: usize = 2 + 1 + 2 + 1;
+
+// The longest block, the params must fit in a telemetry payload
+pub const MAX_BLOCK: usize = HEADER + MAX_PAYLOAD + 2;
+
+// Storage is the memory the block is saved to, e.g. the EEPROM
+pub trait Storage {
+    type Error;
+
+    // read fills block with the bytes at the start of the storage
+    fn read(&mut self, block: &mut [u8]) -> Result<(), Self::Error>;
+
+    fn write(&mut self, block: &[u8]) -> Result<(), Self::Error>;
+}
+
+// NoStorage is the storage of the programs that can't save their params,
+// every access fails
+pub struct NoStorage;
+
+impl Storage for NoStorage {
+    type Error = ();
+
+    fn read(&mut self, _: &mut [u8]) -> Result<(), ()> {
+        Err(())
+    }
+
+    fn write(&mut self, _: &[u8]) -> Result<(), ()> {
+        Err(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LoadError {
+    // The storage could not be read
+    Storage,
+    // Nothing was saved, e.g. an erased EEPROM
+    Missing,
+    Version(u8),
+    // The block was saved with other params
+    Layout,
+    Crc,
+    // The saved values are refused by the params, see Tunable::is_valid
+    Invalid,
+}
+
+// layout_id identifies the fields of the params, by their names and types
+pub fn layout_id<P: Tunable>() -> u16 {
+    P::FIELDS.iter().fold(0xFFFF, |crc, (name, ty)| {
+        crc16_update(crc16_update(crc, name.as_bytes()), &[*ty as u8])
+    })
+}
+
+// encode writes the block of p and returns its length
+pub fn encode<P: Tunable>(p: &P, block: &mut [u8; MAX_BLOCK]) -> usize {
+    block[..2].copy_from_slice(&MAGIC);
+    block[2] = VERSION;
+    block[3..5].copy_from_slice(&layout_id::<P>().to_le_bytes());
+    let mut len = HEADER;
+    for (name, ty) in P::FIELDS {
+        // The fields that don't fit are left out, decode then fails on the
+        // length
+        let Some(value) = p.get(name) else { continue };
+        if len + ty.size() <= HEADER + MAX_PAYLOAD {
+            value.write_le(&mut block[len..]);
+            len += ty.size();
+        }
+    }
+    block[5] = (len - HEADER) as u8;
+    let crc = crc16(&block[..len]);
+    block[len..len + 2].copy_from_slice(&crc.to_le_bytes());
+    len + 2
+}
+
+// decode reads the fields of a block on top of defaults, for the fields that
+// are not saved
+pub fn decode<P: Tunable>(defaults: &P, block: &[u8]) -> Result<P, LoadError> {
+    if block.len() < HEADER || block[..2] != MAGIC {
+        return Err(LoadError::Missing);
+    }
+    if block[2] != VERSION {
+        return Err(LoadError::Version(block[2]));
+    }
+    let len = HEADER + block[5] as usize;
+    let crc = block.get(len..len + 2).ok_or(LoadError::Crc)?;
+    if crc16(&block[..len]) != u16::from_le_bytes([crc[0], crc[1]]) {
+        return Err(LoadError::Crc);
+    }
+    let expected_len: usize = P::FIELDS.iter().map(|(_, ty)| ty.size()).sum();
+    if u16::from_le_bytes([block[3], block[4]]) != layout_id::<P>() || len != HEADER + expected_len
+    {
+        return Err(LoadError::Layout);
+    }
+    let mut p = defaults.clone();
+    let mut offset = HEADER;
+    for (name, ty) in P::FIELDS {
+        p.set(name, ty.read(&block[offset..]))
+            .map_err(|_| LoadError::Invalid)?;
+        offset += ty.size();
+    }
+    if !p.is_valid() {
+        return Err(LoadError::Invalid);
+    }
+    Ok(p)
+}
+
+// load reads the params saved in the storage
+pub fn load<P: Tunable, S: Storage>(storage: &mut S, defaults: &P) -> Result<P, LoadError> {
+    let mut block = [0; MAX_BLOCK];
+    storage.read(&mut block).map_err(|_| LoadError::Storage)?;
+    decode(defaults, &block)
+}
+
+pub fn save<P: Tunable, S: Storage>(storage: &mut S, p: &P) -> Result<(), S::Error> {
+    let mut block = [0; MAX_BLOCK];
+    let len = encode(p, &mut block);
+    storage.write(&block[..len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chrono::Duration;
+    use crate::dice;
+
+    const DEFAULTS: dice::Parameters = dice::Parameters {
+        nb_blinks: 10,
+        first_blink_duration: Duration::from_millis(100),
+        last_blink_duration: Duration::from_millis(400),
+    };
+
+    fn saved() -> ([u8; MAX_BLOCK], usize) {
+        let mut p = DEFAULTS.clone();
+        p.nb_blinks = 3;
+        p.last_blink_duration = Duration::from_millis(1_000);
+        let mut block = [0xFF; MAX_BLOCK];
+        let len = encode(&p, &mut block);
+        (block, len)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (block, _) = saved();
+        let p = decode(&DEFAULTS, &block).unwrap();
+        assert_eq!(3, p.nb_blinks, "wrong nb_blinks");
+        assert_eq!(
+            Duration::from_millis(100),
+            p.first_blink_duration,
+            "wrong first blink"
+        );
+        assert_eq!(
+            Duration::from_millis(1_000),
+            p.last_blink_duration,
+            "wrong last blink"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        struct TestCase {
+            // The byte of the saved block to change and its new value
+            change: (usize, u8),
+            expected: LoadError,
+        }
+        let (_, len) = saved();
+        let cases = [
+            // An erased EEPROM
+            TestCase {
+                change: (0, 0xFF),
+                expected: LoadError::Missing,
+            },
+            TestCase {
+                change: (2, VERSION + 1),
+                expected: LoadError::Version(VERSION + 1),
+            },
+            TestCase {
+                change: (HEADER, 4),
+                expected: LoadError::Crc,
+            },
+            TestCase {
+                change: (len - 1, 0),
+                expected: LoadError::Crc,
+            },
+        ];
+        for tc in cases {
+            let (mut block, _) = saved();
+            block[tc.change.0] = tc.change.1;
+            let actual = decode(&DEFAULTS, &block).map(|p| p.nb_blinks);
+            assert_eq!(Err(tc.expected), actual, "wrong error");
+        }
+
+        // A block saved by other params, e.g. an older version of them
+        let mut block = [0; MAX_BLOCK];
+        let chenillard = crate::chenillard::Parameters {
+            period: Duration::from_millis(1_000),
+            pattern: crate::pattern::Builtin::Walk,
+            trail: 0,
+        };
+        encode(&chenillard, &mut block);
+        assert_eq!(
+            Err(LoadError::Layout),
+            decode(&DEFAULTS, &block).map(|p| p.nb_blinks),
+            "the layout should not match"
+        );
+    }
+}
@@ -0,0 +1,273 @@
+//! This module contains software timers driven by the instants of a Chrono,
+//! so the exercises don't have to do the deadline arithmetic themselves.
+//!
+//! The timers are plain values meant to be kept in the state of an exercise
+//! and polled with the current instant of its inputs, which keeps the
+//! advance functions pure.
+
+use crate::chrono::{Duration, Instant};
+
+// The longest duration a deadline can be set to, see Instant::checked_add
+const MAX_DURATION: Duration = Duration::from_millis(i32::MAX as u32);
+
+// Timer is a one-shot timer: it expires once its duration elapsed, and stays
+// expired until it is started again
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timer {
+    deadline: Option<Instant>,
+}
+
+impl Timer {
+    pub const fn stopped() -> Timer {
+        Timer { deadline: None }
+    }
+
+    // start makes a timer expiring d after now.
+    // Durations longer than ~24.8 days are cut to that.
+    pub fn start(now: Instant, d: Duration) -> Timer {
+        Timer {
+            deadline: now.checked_add(d.min(MAX_DURATION)),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.deadline.is_some()
+    }
+
+    pub fn expired(&self, now: Instant) -> bool {
+        self.deadline.is_some_and(|d| now.has_reached(d))
+    }
+
+    // remaining is the time left before the timer expires, zero when it has
+    // expired or is stopped
+    pub fn remaining(&self, now: Instant) -> Duration {
+        match self.deadline {
+            Some(d) if !now.has_reached(d) => d.duration_since(now),
+            _ => Duration::ZERO,
+        }
+    }
+
+    // poll is true only once when the timer expired, then it is stopped
+    pub fn poll(&mut self, now: Instant) -> bool {
+        let expired = self.expired(now);
+        if expired {
+            self.deadline = None;
+        }
+        expired
+    }
+}
+
+// Periodic fires every period, starting one period after it was created.
+// It keeps its phase when polled late, but the periods missed by more than
+// a whole period are skipped instead of firing several times in a row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Periodic {
+    next: Instant,
+    period: Duration,
+}
+
+impl Periodic {
+    pub fn new(now: Instant, period: Duration) -> Periodic {
+        let period = period.min(MAX_DURATION);
+        Periodic {
+            next: now.checked_add(period).unwrap_or(now),
+            period,
+        }
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    // poll is true when a period has elapsed since it last fired
+    pub fn poll(&mut self, now: Instant) -> bool {
+        if !now.has_reached(self.next) {
+            return false;
+        }
+        let next = self.next.checked_add(self.period).unwrap_or(now);
+        self.next = if now.has_reached(next) {
+            // Too late for the next period too, restart from now
+            now.checked_add(self.period).unwrap_or(now)
+        } else {
+            next
+        };
+        true
+    }
+}
+
+// Scheduler is a fixed size table of periodic tasks.
+// The tasks are usually a small enum that the exercise matches on, e.g.
+// "every 50 ms sample the ADC, every 500 ms print telemetry".
+pub struct Scheduler<T, const N: usize> {
+    tasks: [Option<(T, Periodic)>; N],
+}
+
+impl<T: Copy + PartialEq, const N: usize> Scheduler<T, N> {
+    pub const fn new() -> Scheduler<T, N> {
+        Scheduler { tasks: [None; N] }
+    }
+
+    // add schedules the task every period, starting from now.
+    // The task is given back when the table is full.
+    pub fn add(&mut self, task: T, now: Instant, period: Duration) -> Result<(), T> {
+        match self.tasks.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some((task, Periodic::new(now, period)));
+                Ok(())
+            }
+            None => Err(task),
+        }
+    }
+
+    // remove unschedules every occurrence of the task
+    pub fn remove(&mut self, task: T) {
+        for slot in self.tasks.iter_mut() {
+            if slot.is_some_and(|(t, _)| t == task) {
+                *slot = None;
+            }
+        }
+    }
+
+    // due iterates on the tasks to run now, in the order they were added.
+    // The tasks are only marked as run when iterated.
+    pub fn due(&mut self, now: Instant) -> impl Iterator<Item = T> + '_ {
+        self.tasks
+            .iter_mut()
+            .flatten()
+            .filter_map(move |(task, periodic)| periodic.poll(now).then_some(*task))
+    }
+}
+
+impl<T: Copy + PartialEq, const N: usize> Default for Scheduler<T, N> {
+    fn default() -> Self {
+        Scheduler::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(ms: u32) -> Instant {
+        Instant::from_millis(ms)
+    }
+
+    #[test]
+    fn test_timer() {
+        let mut timer = Timer::start(at(u32::MAX - 49), Duration::from_millis(100));
+        assert!(timer.is_running(), "the timer should run");
+        assert_eq!(
+            Duration::from_millis(51),
+            timer.remaining(at(u32::MAX)),
+            "wrong remaining time"
+        );
+        assert!(!timer.poll(at(49)), "the timer should not expire yet");
+        assert!(
+            timer.poll(at(50)),
+            "the timer should expire after the rollover"
+        );
+        assert!(!timer.poll(at(51)), "the timer should only fire once");
+        assert!(!timer.is_running(), "the timer should be stopped");
+        assert!(
+            !Timer::stopped().expired(at(1_000)),
+            "a stopped timer never expires"
+        );
+    }
+
+    #[test]
+    fn test_periodic() {
+        struct TestCase {
+            ms: u32,
+            expected: bool,
+        }
+        let cases = [
+            TestCase {
+                ms: 99,
+                expected: false,
+            },
+            TestCase {
+                ms: 100,
+                expected: true,
+            },
+            TestCase {
+                ms: 150,
+                expected: false,
+            },
+            // Late by 20ms, the phase is kept
+            TestCase {
+                ms: 220,
+                expected: true,
+            },
+            TestCase {
+                ms: 299,
+                expected: false,
+            },
+            TestCase {
+                ms: 300,
+                expected: true,
+            },
+            // The periods missed are skipped
+            TestCase {
+                ms: 1_000,
+                expected: true,
+            },
+            TestCase {
+                ms: 1_099,
+                expected: false,
+            },
+            TestCase {
+                ms: 1_100,
+                expected: true,
+            },
+        ];
+        let mut periodic = Periodic::new(at(0), Duration::from_millis(100));
+        for tc in cases {
+            assert_eq!(
+                tc.expected,
+                periodic.poll(at(tc.ms)),
+                "wrong fire at {}",
+                tc.ms
+            );
+        }
+    }
+
+    #[test]
+    fn test_scheduler() {
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        enum Task {
+            Sample,
+            Print,
+            Blink,
+        }
+        let mut scheduler = Scheduler::<Task, 2>::new();
+        assert_eq!(
+            Ok(()),
+            scheduler.add(Task::Sample, at(0), Duration::from_millis(50))
+        );
+        assert_eq!(
+            Ok(()),
+            scheduler.add(Task::Print, at(0), Duration::from_millis(500))
+        );
+        assert_eq!(
+            Err(Task::Blink),
+            scheduler.add(Task::Blink, at(0), Duration::from_millis(10)),
+            "the table should be full"
+        );
+        let mut samples = 0;
+        let mut prints = 0;
+        for ms in 1..=1_000 {
+            for task in scheduler.due(at(ms)) {
+                match task {
+                    Task::Sample => samples += 1,
+                    Task::Print => prints += 1,
+                    Task::Blink => unreachable!(),
+                }
+            }
+        }
+        assert_eq!((20, 2), (samples, prints), "wrong number of runs");
+        scheduler.remove(Task::Sample);
+        let mut due = scheduler.due(at(1_500));
+        assert_eq!(Some(Task::Print), due.next(), "the print should be due");
+        assert_eq!(None, due.next(), "only the print should be left");
+    }
+}
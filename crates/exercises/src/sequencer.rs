@@ -0,0 +1,754 @@
+//! This module runs light shows written as data: a program of a few bytes,
+//! e.g. in the flash of the uno, tells the leds of the chenillard to turn on,
+//! wait, shift and loop. A new show is a new program, not a new exercise.
+//!
+//! Each instruction is an opcode byte followed by its operands, the u16 are
+//! little endian:
+//! - 0x01 mask:u16, set: the leds of the mask are on, the others off
+//! - 0x02 ms:u16, wait: the leds stay as they are for ms
+//! - 0x03 n:u8, shift left: each led moves n leds up, the last ones are lost
+//! - 0x04 n:u8, shift right: each led moves n leds down
+//! - 0x05 n:u8, repeat: the instructions up to the matching end run n times
+//! - 0x06, end: the end of the last repeat
+//! - 0x07 address:u16, jump: the program goes on at address. Jumping out of
+//!   a repeat, before or after it, leaves it as if it was over.
+//!
+//! The show starts at the first loop of the exercise, and starts its waits
+//! over from a loop late by more than a wait, e.g. after a stall, instead of
+//! rushing through the missed ones.
+//!
+//! The program starts over when it runs past its last instruction. The
+//! programs are easier to write with assemble, e.g.
+//! `const SHOW: [u8; size(&OPS)] = assemble(&OPS);`
+
+use core::convert::Infallible;
+use core::marker::PhantomData;
+
+use crate::chenillard::{self, OutputPeripherals, Outputs};
+use crate::chrono::{Chrono, Duration, Instant};
+use crate::exercise::{self, Exercise, Fault, Sleep};
+use crate::led_bank::LedBank;
+use crate::pattern::{first_leds, Mask, MAX_LEDS};
+use crate::trace::{Tick, TraceInputs};
+
+// The most nested repeats
+pub const MAX_DEPTH: usize = 4;
+
+// The most instructions run in a loop of the exercise. A program that jumps
+// without waiting goes on at the next loop instead of blocking.
+pub const MAX_OPS_PER_LOOP: u8 = 32;
+
+// DEMO_OPS is a show for 8 leds: a led goes up and down, then all of them
+// blink 3 times
+pub const DEMO_OPS: [Op; 15] = [
+    Op::Set(0x01),
+    Op::Repeat(7),
+    Op::Wait(80),
+    Op::ShiftLeft(1),
+    Op::End,
+    Op::Repeat(7),
+    Op::Wait(80),
+    Op::ShiftRight(1),
+    Op::End,
+    Op::Repeat(3),
+    Op::Set(0xff),
+    Op::Wait(150),
+    Op::Set(0x00),
+    Op::Wait(150),
+    Op::End,
+];
+
+pub const DEMO: [u8; size(&DEMO_OPS)] = assemble(&DEMO_OPS);
+
+// Program is the bytecode of a light show
+pub trait Program {
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // byte is the byte at idx, which is less than len
+    fn byte(&self, idx: usize) -> u8;
+}
+
+impl Program for [u8] {
+    fn len(&self) -> usize {
+        <[u8]>::len(self)
+    }
+
+    fn byte(&self, idx: usize) -> u8 {
+        self[idx]
+    }
+}
+
+impl<P: Program + ?Sized> Program for &P {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn byte(&self, idx: usize) -> u8 {
+        (**self).byte(idx)
+    }
+}
+
+// Op is an instruction of a program
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Set(Mask),
+    Wait(u16),
+    ShiftLeft(u8),
+    ShiftRight(u8),
+    Repeat(u8),
+    End,
+    Jump(u16),
+}
+
+impl Op {
+    // nb_bytes is the number of bytes of the instruction
+    pub const fn nb_bytes(self) -> usize {
+        match self {
+            Op::Set(_) | Op::Wait(_) | Op::Jump(_) => 3,
+            Op::ShiftLeft(_) | Op::ShiftRight(_) | Op::Repeat(_) => 2,
+            Op::End => 1,
+        }
+    }
+
+    // encode is the bytes of the instruction, followed by padding up to 3
+    const fn encode(self) -> [u8; 3] {
+        let (opcode, operand) = match self {
+            Op::Set(mask) => (0x01, mask),
+            Op::Wait(ms) => (0x02, ms),
+            Op::ShiftLeft(n) => (0x03, n as u16),
+            Op::ShiftRight(n) => (0x04, n as u16),
+            Op::Repeat(n) => (0x05, n as u16),
+            Op::End => (0x06, 0),
+            Op::Jump(address) => (0x07, address),
+        };
+        let operand = operand.to_le_bytes();
+        [opcode, operand[0], operand[1]]
+    }
+}
+
+// size is the number of bytes of the program made of ops
+pub const fn size(ops: &[Op]) -> usize {
+    let mut size = 0;
+    let mut i = 0;
+    while i < ops.len() {
+        size += ops[i].nb_bytes();
+        i += 1;
+    }
+    size
+}
+
+// assemble is the bytecode of ops, N must be their size
+pub const fn assemble<const N: usize>(ops: &[Op]) -> [u8; N] {
+    assert!(size(ops) == N, "N is not the size of the program");
+    let mut bytes = [0; N];
+    let (mut i, mut address) = (0, 0);
+    while i < ops.len() {
+        let encoded = ops[i].encode();
+        let mut j = 0;
+        while j < ops[i].nb_bytes() {
+            bytes[address + j] = encoded[j];
+            j += 1;
+        }
+        address += ops[i].nb_bytes();
+        i += 1;
+    }
+    bytes
+}
+
+// ProgramError is an instruction that can't run, the program stops there
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProgramError {
+    // The address of the instruction
+    pub address: u16,
+    pub kind: ErrorKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    UnknownOpcode,
+    // The operands go past the end of the program
+    Truncated,
+    // A jump after the end of the program
+    OutOfRange,
+    // A repeat 0 times, which can't skip its instructions
+    RepeatZero,
+    // More than MAX_DEPTH nested repeats
+    TooDeep,
+    // An end without a repeat
+    Unbalanced,
+}
+
+// decode reads the instruction at address, it returns it with the address of
+// the next one
+pub fn decode<PR: Program + ?Sized>(program: &PR, address: u16) -> Result<(Op, u16), ProgramError> {
+    let error = |kind| ProgramError { address, kind };
+    let at = address as usize;
+    let opcode = program.byte(at);
+    let len = match opcode {
+        0x01 | 0x02 | 0x07 => 3,
+        0x03..=0x05 => 2,
+        0x06 => 1,
+        _ => return Err(error(ErrorKind::UnknownOpcode)),
+    };
+    if at + len > program.len() {
+        return Err(error(ErrorKind::Truncated));
+    }
+    let u8_operand = || program.byte(at + 1);
+    let u16_operand = || u16::from_le_bytes([program.byte(at + 1), program.byte(at + 2)]);
+    let op = match opcode {
+        0x01 => Op::Set(u16_operand()),
+        0x02 => Op::Wait(u16_operand()),
+        0x03 => Op::ShiftLeft(u8_operand()),
+        0x04 => Op::ShiftRight(u8_operand()),
+        0x05 => Op::Repeat(u8_operand()),
+        0x06 => Op::End,
+        _ => Op::Jump(u16_operand()),
+    };
+    Ok((op, address + len as u16))
+}
+
+// end_of is the address of the end matching a repeat whose first instruction
+// is at start
+fn end_of<PR: Program + ?Sized>(program: &PR, start: u16) -> u16 {
+    let (mut address, mut depth) = (start, 0);
+    while (address as usize) < program.len() {
+        match decode(program, address) {
+            Ok((Op::End, _)) if depth == 0 => return address,
+            Ok((Op::End, next)) => {
+                depth -= 1;
+                address = next
+            }
+            Ok((Op::Repeat(_), next)) => {
+                depth += 1;
+                address = next
+            }
+            Ok((_, next)) => address = next,
+            // The bad instruction stops the program once it is run
+            Err(_) => break,
+        }
+    }
+    program.len() as u16
+}
+
+pub fn run<C: Chrono, PR: Program + Clone, P: LedBank<N>, const N: usize>(
+    p: &Parameters<PR>,
+    ips: &mut InputPeripherals<C>,
+    ops: &mut OutputPeripherals<P, N>,
+) -> ! {
+    exercise::run::<Sequencer<C, PR, P, N>>(p, ips, ops)
+}
+
+// Sequencer plugs the functions of this module into the Exercise trait.
+// It drives the leds of the chenillard.
+pub struct Sequencer<C, PR, P, const N: usize = 8>(PhantomData<(C, PR, P)>);
+
+impl<C: Chrono, PR: Program + Clone, P: LedBank<N>, const N: usize> Exercise
+    for Sequencer<C, PR, P, N>
+{
+    type Parameters = Parameters<PR>;
+    type Inputs = Inputs;
+    type State = State;
+    type Outputs = Outputs<N>;
+    type InputPeripherals = InputPeripherals<C>;
+    type OutputPeripherals = OutputPeripherals<P, N>;
+    type Error = Fault<Infallible, P::Error>;
+
+    fn init(_: &Parameters<PR>) -> State {
+        State::new()
+    }
+
+    fn read_inputs(ps: &mut InputPeripherals<C>) -> Result<Inputs, Self::Error> {
+        Ok(read_inputs(ps))
+    }
+
+    fn advance(p: &Parameters<PR>, s: State, inputs: Inputs) -> (State, Outputs<N>) {
+        advance(p, s, inputs)
+    }
+
+    fn apply_outputs(
+        _: &Parameters<PR>,
+        outputs: Outputs<N>,
+        ps: &mut OutputPeripherals<P, N>,
+    ) -> Result<(), Self::Error> {
+        chenillard::apply_outputs(outputs, ps).map_err(Fault::Output)
+    }
+
+    fn stop(_: &Parameters<PR>, _: &Self::Error, ps: &mut OutputPeripherals<P, N>) {
+        chenillard::stop(ps)
+    }
+
+    // Nothing happens between two ticks of the chrono
+    fn sleep(_: &Parameters<PR>, _: &State) -> Sleep {
+        Sleep::Idle
+    }
+}
+
+pub struct Inputs {
+    pub now: Instant,
+}
+
+#[derive(Clone)]
+pub struct Parameters<PR> {
+    pub program: PR,
+}
+
+// Loop is a repeat being run
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Loop {
+    // The address of the first instruction repeated
+    start: u16,
+    // The address of the matching end, or the end of the program without one
+    end: u16,
+    // The number of times left, including the current one
+    left: u8,
+}
+
+pub struct State {
+    // The address of the next instruction
+    pub pc: u16,
+    pub mask: Mask,
+    // The end of the current wait. The waits follow each other from there,
+    // so a late loop doesn't delay the rest of the show.
+    pub until: Instant,
+    // The length of the current wait
+    wait: Duration,
+    // False until the first loop, which the show starts at
+    started: bool,
+    loops: [Loop; MAX_DEPTH],
+    depth: usize,
+    // Set once the program stopped, the leds are then off
+    pub error: Option<ProgramError>,
+}
+
+impl State {
+    // new is the state at the start of the program, which starts at the
+    // first loop
+    pub fn new() -> State {
+        State {
+            pc: 0,
+            mask: 0,
+            until: Instant::from_millis(0),
+            wait: Duration::ZERO,
+            started: false,
+            loops: [Loop::default(); MAX_DEPTH],
+            depth: 0,
+            error: None,
+        }
+    }
+
+    // step runs the instruction at pc
+    fn step<PR: Program + ?Sized, const N: usize>(
+        &mut self,
+        program: &PR,
+    ) -> Result<(), ProgramError> {
+        let address = self.pc;
+        let (op, next) = decode(program, address)?;
+        let error = |kind| ProgramError { address, kind };
+        let all = first_leds(N as u32);
+        self.pc = next;
+        match op {
+            Op::Set(mask) => self.mask = mask & all,
+            Op::Wait(ms) => {
+                self.wait = Duration::from_millis(ms as u32);
+                // A u16 of ms is always a valid deadline
+                self.until = self.until.checked_add(self.wait).unwrap_or(self.until)
+            }
+            Op::ShiftLeft(n) => {
+                self.mask = (self.mask as u32).checked_shl(n as u32).unwrap_or(0) as Mask & all
+            }
+            Op::ShiftRight(n) => self.mask = self.mask.checked_shr(n as u32).unwrap_or(0),
+            Op::Repeat(0) => return Err(error(ErrorKind::RepeatZero)),
+            Op::Repeat(n) => {
+                let slot = self.loops.get_mut(self.depth);
+                *slot.ok_or(error(ErrorKind::TooDeep))? = Loop {
+                    start: next,
+                    end: end_of(program, next),
+                    left: n,
+                };
+                self.depth += 1;
+            }
+            Op::End => {
+                let depth = self.depth.checked_sub(1);
+                let top = &mut self.loops[depth.ok_or(error(ErrorKind::Unbalanced))?];
+                top.left -= 1;
+                if top.left > 0 {
+                    self.pc = top.start;
+                } else {
+                    self.depth -= 1;
+                }
+            }
+            Op::Jump(to) if to as usize >= program.len() => {
+                return Err(error(ErrorKind::OutOfRange))
+            }
+            Op::Jump(to) => {
+                // The repeats that don't contain to are left
+                while self.depth > 0 {
+                    let top = &self.loops[self.depth - 1];
+                    if (top.start..=top.end).contains(&to) {
+                        break;
+                    }
+                    self.depth -= 1;
+                }
+                self.pc = to
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for State {
+    fn default() -> State {
+        State::new()
+    }
+}
+
+pub fn advance<PR: Program, const N: usize>(
+    p: &Parameters<PR>,
+    mut s: State,
+    inputs: Inputs,
+) -> (State, Outputs<N>) {
+    const { assert!(N <= MAX_LEDS, "too many leds for the mask") };
+    // A loop later than a whole wait, e.g. after a stall or in a program
+    // without waits, starts the waits over from now instead of rushing
+    // through the missed ones
+    let late = inputs.now.has_reached(s.until) && inputs.now.duration_since(s.until) > s.wait;
+    if !s.started || late {
+        s.until = inputs.now;
+        s.started = true;
+    }
+    for _ in 0..MAX_OPS_PER_LOOP {
+        if s.error.is_some() || p.program.is_empty() || !inputs.now.has_reached(s.until) {
+            break;
+        }
+        // Start over at the end, the repeats left open are dropped
+        if s.pc as usize >= p.program.len() {
+            s.pc = 0;
+            s.depth = 0;
+        }
+        if let Err(e) = s.step::<PR, N>(&p.program) {
+            s.error = Some(e);
+        }
+    }
+    let mut leds = [0; N];
+    if s.error.is_none() {
+        for (led_idx, level) in leds.iter_mut().enumerate() {
+            if s.mask >> led_idx & 0x1 != 0 {
+                *level = 255;
+            }
+        }
+    }
+    (s, Outputs { leds })
+}
+
+impl TraceInputs for Inputs {
+    fn from_tick(tick: &Tick) -> Inputs {
+        Inputs {
+            now: Instant::from_millis(tick.ms),
+        }
+    }
+
+    fn to_tick(&self) -> Tick {
+        Tick {
+            ms: self.now.as_millis(),
+            buttons: 0,
+            nb_buttons: 0,
+            level: None,
+            roll: None,
+        }
+    }
+}
+
+pub struct InputPeripherals<C: Chrono> {
+    pub chrono: C,
+}
+
+pub fn read_inputs<C: Chrono>(ps: &mut InputPeripherals<C>) -> Inputs {
+    Inputs {
+        now: ps.chrono.now(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A led walking up and back down, twice, then all the leds on
+    const OPS: [Op; 13] = [
+        Op::Set(0x01),
+        Op::Repeat(2),
+        Op::Repeat(7),
+        Op::Wait(100),
+        Op::ShiftLeft(1),
+        Op::End,
+        Op::Repeat(7),
+        Op::Wait(100),
+        Op::ShiftRight(1),
+        Op::End,
+        Op::End,
+        Op::Set(0xff),
+        Op::Wait(200),
+    ];
+    const SHOW: [u8; size(&OPS)] = assemble(&OPS);
+
+    #[test]
+    fn test_assemble() {
+        assert_eq!(28, SHOW.len());
+        assert_eq!([0x01, 0x01, 0x00, 0x05, 0x02], SHOW[..5]);
+        let mut address = 0;
+        for op in OPS {
+            let (decoded, next) = decode(&SHOW[..], address).unwrap();
+            assert_eq!(op, decoded, "wrong op at {address}");
+            address = next;
+        }
+        assert_eq!(SHOW.len() as u16, address);
+    }
+
+    #[test]
+    fn test_show() {
+        struct TestCase {
+            ms: u32,
+            expected: Mask,
+        }
+        let cases = [
+            TestCase {
+                ms: 0,
+                expected: 0x01,
+            },
+            TestCase {
+                ms: 350,
+                expected: 0x08,
+            },
+            TestCase {
+                ms: 700,
+                expected: 0x80,
+            },
+            TestCase {
+                ms: 1_050,
+                expected: 0x10,
+            },
+            // The second time
+            TestCase {
+                ms: 1_650,
+                expected: 0x04,
+            },
+            TestCase {
+                ms: 2_250,
+                expected: 0x40,
+            },
+            TestCase {
+                ms: 2_850,
+                expected: 0xff,
+            },
+            // Then it starts over
+            TestCase {
+                ms: 3_000,
+                expected: 0x01,
+            },
+        ];
+        let p = Parameters { program: &SHOW[..] };
+        let mut s = State::new();
+        // A loop every 50ms, the show would start over from a stall
+        for ms in (0..=3_000).step_by(50) {
+            let outputs: Outputs<8>;
+            (s, outputs) = advance(
+                &p,
+                s,
+                Inputs {
+                    now: Instant::from_millis(ms),
+                },
+            );
+            let Some(tc) = cases.iter().find(|tc| tc.ms == ms) else {
+                continue;
+            };
+            assert_eq!(tc.expected, s.mask, "wrong leds at {}ms", tc.ms);
+            assert_eq!(
+                tc.expected & 0x1 != 0,
+                outputs.leds[0] == 255,
+                "wrong first led at {}ms",
+                tc.ms
+            );
+        }
+    }
+
+    #[test]
+    fn test_resync() {
+        struct TestCase {
+            ms: u32,
+            expected_mask: Mask,
+            expected_until: u32,
+        }
+        const OPS: [Op; 4] = [
+            Op::Set(0x01),
+            Op::Wait(100),
+            Op::ShiftLeft(1),
+            Op::Wait(100),
+        ];
+        const BLINK: [u8; size(&OPS)] = assemble(&OPS);
+        let cases = [
+            // The show starts at the first loop
+            TestCase {
+                ms: 5_000,
+                expected_mask: 0x01,
+                expected_until: 5_100,
+            },
+            // A late loop keeps the pace
+            TestCase {
+                ms: 5_150,
+                expected_mask: 0x02,
+                expected_until: 5_200,
+            },
+            // A stall starts over from now
+            TestCase {
+                ms: 9_050,
+                expected_mask: 0x01,
+                expected_until: 9_150,
+            },
+        ];
+        let p = Parameters {
+            program: &BLINK[..],
+        };
+        let mut s = State::new();
+        for tc in cases {
+            (s, _) = advance::<_, 8>(
+                &p,
+                s,
+                Inputs {
+                    now: Instant::from_millis(tc.ms),
+                },
+            );
+            assert_eq!(tc.expected_mask, s.mask, "wrong leds at {}ms", tc.ms);
+            assert_eq!(
+                Instant::from_millis(tc.expected_until),
+                s.until,
+                "wrong end of wait at {}ms",
+                tc.ms
+            );
+        }
+
+        // A program without waits still runs half a counter range later
+        let p = Parameters {
+            program: &[0x03, 0x01][..],
+        };
+        let mut s = State::new();
+        for ms in [0, i32::MAX as u32, 3 << 30] {
+            s.mask = 0x01;
+            (s, _) = advance::<_, 8>(
+                &p,
+                s,
+                Inputs {
+                    now: Instant::from_millis(ms),
+                },
+            );
+            assert_eq!(0, s.mask, "the program should run at {ms}ms");
+        }
+    }
+
+    #[test]
+    fn test_jump_forward() {
+        // The jump leaves the inner repeat, each outer repeat shifts once
+        const OPS: [Op; 8] = [
+            Op::Set(0x01),
+            Op::Repeat(3),
+            Op::Repeat(2),
+            Op::Jump(11),
+            Op::End,
+            Op::ShiftLeft(1),
+            Op::Wait(100),
+            Op::End,
+        ];
+        const JUMP: [u8; size(&OPS)] = assemble(&OPS);
+        let p = Parameters { program: &JUMP[..] };
+        let mut s = State::new();
+        for (ms, expected) in [(0, 0x02), (100, 0x04), (200, 0x08), (300, 0x02)] {
+            (s, _) = advance::<_, 8>(
+                &p,
+                s,
+                Inputs {
+                    now: Instant::from_millis(ms),
+                },
+            );
+            assert_eq!(None, s.error, "the program should run at {ms}ms");
+            assert_eq!(expected, s.mask, "wrong leds at {ms}ms");
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        struct TestCase {
+            name: &'static str,
+            program: &'static [u8],
+            expected: Option<ProgramError>,
+        }
+        let error = |address, kind| Some(ProgramError { address, kind });
+        let cases = [
+            TestCase {
+                name: "unknown opcode",
+                program: &[0x01, 0xff, 0x00, 0x42],
+                expected: error(3, ErrorKind::UnknownOpcode),
+            },
+            TestCase {
+                name: "truncated",
+                program: &[0x02, 0x10],
+                expected: error(0, ErrorKind::Truncated),
+            },
+            TestCase {
+                name: "out of range",
+                program: &[0x07, 0x03, 0x00],
+                expected: error(0, ErrorKind::OutOfRange),
+            },
+            TestCase {
+                name: "repeat 0",
+                program: &[0x05, 0x00, 0x06],
+                expected: error(0, ErrorKind::RepeatZero),
+            },
+            TestCase {
+                name: "too deep",
+                program: &[0x05, 0x02, 0x05, 0x02, 0x05, 0x02, 0x05, 0x02, 0x05, 0x02],
+                expected: error(8, ErrorKind::TooDeep),
+            },
+            TestCase {
+                name: "unbalanced",
+                program: &[0x06],
+                expected: error(0, ErrorKind::Unbalanced),
+            },
+            // Each jump back leaves the repeat, it doesn't nest a new one
+            TestCase {
+                name: "jump out of a repeat",
+                program: &[
+                    0x05, 0x02, 0x01, 0x01, 0x00, 0x02, 0x00, 0x00, 0x07, 0x00, 0x00,
+                ],
+                expected: None,
+            },
+            // A busy loop goes on at the next loop of the exercise
+            TestCase {
+                name: "busy loop",
+                program: &[0x03, 0x01, 0x07, 0x00, 0x00],
+                expected: None,
+            },
+        ];
+        for tc in cases {
+            let p = Parameters {
+                program: tc.program,
+            };
+            let (s, outputs) = advance::<_, 8>(
+                &p,
+                State::new(),
+                Inputs {
+                    now: Instant::from_millis(0),
+                },
+            );
+            assert_eq!(tc.expected, s.error, "wrong error for {}", tc.name);
+            if s.error.is_some() {
+                assert_eq!(
+                    [0; 8], outputs.leds,
+                    "the leds should be off for {}",
+                    tc.name
+                );
+            }
+        }
+    }
+}
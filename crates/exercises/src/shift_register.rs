@@ -0,0 +1,218 @@
+//! This module drives leds through 74HC595 shift registers: the state of 8
+//! leds is shifted out bit by bit over 3 pins (or the SPI bus), instead of
+//! taking 8 pins of the uno. Several 74HC595 can be daisy-chained for more
+//! leds, the Q7' output of a chip to the DS input of the next one.
+//!
+//! The chain is a LedBank for the OutputPeripherals of an exercise, and its
+//! outputs can also be used one by one as output pins. The chain is shifted
+//! out when a led changes, the other leds don't flicker meanwhile.
+
+use core::cell::RefCell;
+use core::fmt::Debug;
+
+use embedded_hal::digital::{self, OutputPin, PinState};
+use embedded_hal::pwm::{self, SetDutyCycle};
+use embedded_hal::spi::SpiBus;
+
+use crate::led_bank::LedBank;
+use crate::pattern::{Mask, MAX_LEDS};
+
+// Bus shifts the outputs out to the chain then latches them
+pub trait Bus {
+    type Error: Debug;
+
+    // write sends the outputs of each chip, chips[0] is the chip wired to the
+    // mcu. The bit i of a byte is the output Qi of the chip.
+    fn write(&mut self, chips: &[u8]) -> Result<(), Self::Error>;
+}
+
+// BitBang shifts the bits out with 3 output pins: data to DS, clock to SHCP
+// and latch to STCP
+pub struct BitBang<P> {
+    pub data: P,
+    pub clock: P,
+    pub latch: P,
+}
+
+impl<P: OutputPin> Bus for BitBang<P> {
+    type Error = P::Error;
+
+    fn write(&mut self, chips: &[u8]) -> Result<(), P::Error> {
+        // The first bit shifted in ends up in Q7 of the last chip
+        for byte in chips.iter().rev() {
+            for bit in (0..8).rev() {
+                self.data
+                    .set_state(PinState::from(byte >> bit & 0x1 != 0))?;
+                self.clock.set_high()?;
+                self.clock.set_low()?;
+            }
+        }
+        self.latch.set_high()?;
+        self.latch.set_low()
+    }
+}
+
+// Spi shifts the bytes out with the SPI bus of the mcu, MSB first: MOSI to
+// DS and SCK to SHCP. The latch pin goes to STCP.
+pub struct Spi<S, L> {
+    pub spi: S,
+    pub latch: L,
+}
+
+// SpiError tells which of the SPI bus and the latch pin failed
+#[derive(Debug, PartialEq)]
+pub enum SpiError<S, L> {
+    Spi(S),
+    Latch(L),
+}
+
+impl<S: SpiBus, L: OutputPin> Bus for Spi<S, L> {
+    type Error = SpiError<S::Error, L::Error>;
+
+    fn write(&mut self, chips: &[u8]) -> Result<(), Self::Error> {
+        for byte in chips.iter().rev() {
+            self.spi.write(&[*byte]).map_err(SpiError::Spi)?;
+        }
+        // The last bits must be out before the latch
+        self.spi.flush().map_err(SpiError::Spi)?;
+        self.latch.set_high().map_err(SpiError::Latch)?;
+        self.latch.set_low().map_err(SpiError::Latch)
+    }
+}
+
+// ShiftRegister is a chain of CHIPS 74HC595
+pub struct ShiftRegister<B, const CHIPS: usize = 1> {
+    chain: RefCell<Chain<B, CHIPS>>,
+}
+
+struct Chain<B, const CHIPS: usize> {
+    bus: B,
+    outputs: [u8; CHIPS],
+    // False until the outputs were written once, the chips start in an
+    // unknown state
+    written: bool,
+}
+
+impl<B: Bus, const CHIPS: usize> ShiftRegister<B, CHIPS> {
+    pub fn new(bus: B) -> ShiftRegister<B, CHIPS> {
+        ShiftRegister {
+            chain: RefCell::new(Chain {
+                bus,
+                outputs: [0; CHIPS],
+                written: false,
+            }),
+        }
+    }
+
+    // pins are the first N outputs of the chain, N is up to 8 * CHIPS
+    pub fn pins<const N: usize>(&self) -> [ShiftPin<'_, B, CHIPS>; N] {
+        const { assert!(N <= 8 * CHIPS, "the chain has less outputs") };
+        core::array::from_fn(|idx| ShiftPin {
+            register: self,
+            idx,
+        })
+    }
+
+    // outputs are the outputs of each chip, as last written
+    pub fn outputs(&self) -> [u8; CHIPS] {
+        self.chain.borrow().outputs
+    }
+
+    fn set(&self, idx: usize, on: bool) -> Result<(), B::Error> {
+        let (chip, mask) = (idx / 8, 1 << (idx % 8));
+        self.update(|outputs| {
+            if on {
+                outputs[chip] |= mask
+            } else {
+                outputs[chip] &= !mask
+            }
+        })
+    }
+
+    // update changes the outputs, the chain is only shifted out if they
+    // changed
+    fn update(&self, change: impl FnOnce(&mut [u8; CHIPS])) -> Result<(), B::Error> {
+        let mut chain = self.chain.borrow_mut();
+        let mut outputs = chain.outputs;
+        change(&mut outputs);
+        if chain.written && outputs == chain.outputs {
+            return Ok(());
+        }
+        chain.outputs = outputs;
+        chain.bus.write(&outputs)?;
+        chain.written = true;
+        Ok(())
+    }
+}
+
+// The chain is also a bank of its first N leds, which are all shifted out at
+// once
+impl<B: Bus, const CHIPS: usize, const N: usize> LedBank<N> for ShiftRegister<B, CHIPS> {
+    type Error = B::Error;
+
+    fn show(&mut self, mask: Mask) -> Result<(), B::Error> {
+        const { assert!(N <= 8 * CHIPS, "the chain has less outputs") };
+        const { assert!(N <= MAX_LEDS, "too many leds for the mask") };
+        self.update(|outputs| {
+            for idx in 0..N {
+                let bit = 1 << (idx % 8);
+                if mask >> idx & 0x1 != 0 {
+                    outputs[idx / 8] |= bit
+                } else {
+                    outputs[idx / 8] &= !bit
+                }
+            }
+        })
+    }
+}
+
+// ShiftPin is an output of the chain. It can also be used as a pwm that is
+// only on at full duty, e.g. the head of the chenillard without its trail.
+pub struct ShiftPin<'a, B, const CHIPS: usize> {
+    register: &'a ShiftRegister<B, CHIPS>,
+    idx: usize,
+}
+
+// ShiftError is the error of the bus, which the pins report as theirs
+#[derive(Debug, PartialEq)]
+pub struct ShiftError<E>(pub E);
+
+impl<E: Debug> digital::Error for ShiftError<E> {
+    fn kind(&self) -> digital::ErrorKind {
+        digital::ErrorKind::Other
+    }
+}
+
+impl<E: Debug> pwm::Error for ShiftError<E> {
+    fn kind(&self) -> pwm::ErrorKind {
+        pwm::ErrorKind::Other
+    }
+}
+
+impl<B: Bus, const CHIPS: usize> digital::ErrorType for ShiftPin<'_, B, CHIPS> {
+    type Error = ShiftError<B::Error>;
+}
+
+impl<B: Bus, const CHIPS: usize> OutputPin for ShiftPin<'_, B, CHIPS> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.register.set(self.idx, false).map_err(ShiftError)
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.register.set(self.idx, true).map_err(ShiftError)
+    }
+}
+
+impl<B: Bus, const CHIPS: usize> pwm::ErrorType for ShiftPin<'_, B, CHIPS> {
+    type Error = ShiftError<B::Error>;
+}
+
+impl<B: Bus, const CHIPS: usize> SetDutyCycle for ShiftPin<'_, B, CHIPS> {
+    fn max_duty_cycle(&self) -> u16 {
+        1
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        self.register.set(self.idx, duty > 0).map_err(ShiftError)
+    }
+}
@@ -0,0 +1,417 @@
+//! This module contains a compact binary protocol to send the outputs of the
+//! exercises on a serial port, and to decode them on a computer.
+//!
+//! A message is a kind byte followed by its fields in little endian, then a
+//! CRC-16 of both. The whole is COBS encoded, so it contains no 0 byte, and
+//! ends with a 0 byte. A decoder that lost some bytes drops the frame with a
+//! bad CRC and starts again on the next 0.
+//!
+//! The fields of each kind of message are described by a Schema, so the
+//! decoder doesn't need to know the exercises.
+
+use crate::exercise::Fault;
+use crate::{debounce, vumetre, wiper};
+use embedded_hal_nb::serial;
+use fixed::types::{I3F13, U1F15};
+
+// The longest payload of a message, all the fields included
+pub const MAX_PAYLOAD: usize = 32;
+
+// kind + payload + crc
+const MAX_MESSAGE: usize = 1 + MAX_PAYLOAD + 2;
+
+// COBS adds a byte every 254 bytes, so a single one for a short message
+pub const MAX_FRAME: usize = MAX_MESSAGE + 1;
+
+// The schemas of all the messages sent by the exercises
+pub const SCHEMAS: [Schema; 4] = [debounce::SCHEMA, vumetre::SCHEMA, wiper::SCHEMA, FAULT];
+
+// The fault message is sent by the exercises when they stop on a fault
+pub const FAULT: Schema = Schema {
+    kind: 0xFF,
+    name: "fault",
+    fields: &[("output", FieldType::Bool)],
+};
+
+// Telemetry is implemented by the outputs sent on the serial port
+pub trait Telemetry {
+    const SCHEMA: Schema;
+
+    // write_fields pushes the fields in the order of the schema
+    fn write_fields(&self, payload: &mut Payload);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Schema {
+    pub kind: u8,
+    pub name: &'static str,
+    pub fields: &'static [(&'static str, FieldType)],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FieldType {
+    Bool,
+    U8,
+    U16,
+    U32,
+    U1F15,
+    I3F13,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U1F15(U1F15),
+    I3F13(I3F13),
+}
+
+// Display is used by the decoders on the computer
+impl core::fmt::Display for Value {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Value::Bool(v) => write!(f, "{}", *v as u8),
+            Value::U8(v) => write!(f, "{v}"),
+            Value::U16(v) => write!(f, "{v}"),
+            Value::U32(v) => write!(f, "{v}"),
+            Value::U1F15(v) => write!(f, "{v}"),
+            Value::I3F13(v) => write!(f, "{v}"),
+        }
+    }
+}
+
+impl Value {
+    pub const fn field_type(&self) -> FieldType {
+        match self {
+            Value::Bool(_) => FieldType::Bool,
+            Value::U8(_) => FieldType::U8,
+            Value::U16(_) => FieldType::U16,
+            Value::U32(_) => FieldType::U32,
+            Value::U1F15(_) => FieldType::U1F15,
+            Value::I3F13(_) => FieldType::I3F13,
+        }
+    }
+
+    // write_le writes the value in little endian at the start of out, which
+    // must be long enough for its type
+    pub(crate) fn write_le(self, out: &mut [u8]) {
+        match self {
+            Value::Bool(b) => out[0] = b as u8,
+            Value::U8(v) => out[0] = v,
+            Value::U16(v) => out[..2].copy_from_slice(&v.to_le_bytes()),
+            Value::U32(v) => out[..4].copy_from_slice(&v.to_le_bytes()),
+            Value::U1F15(v) => out[..2].copy_from_slice(&v.to_bits().to_le_bytes()),
+            Value::I3F13(v) => out[..2].copy_from_slice(&v.to_bits().to_le_bytes()),
+        }
+    }
+}
+
+impl FieldType {
+    pub const fn size(self) -> usize {
+        match self {
+            FieldType::Bool | FieldType::U8 => 1,
+            FieldType::U16 | FieldType::U1F15 | FieldType::I3F13 => 2,
+            FieldType::U32 => 4,
+        }
+    }
+
+    // read decodes a value written by Value::write_le
+    pub(crate) fn read(self, bytes: &[u8]) -> Value {
+        let u16_le = || u16::from_le_bytes([bytes[0], bytes[1]]);
+        match self {
+            FieldType::Bool => Value::Bool(bytes[0] != 0),
+            FieldType::U8 => Value::U8(bytes[0]),
+            FieldType::U16 => Value::U16(u16_le()),
+            FieldType::U32 => {
+                Value::U32(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            }
+            FieldType::U1F15 => Value::U1F15(U1F15::from_bits(u16_le())),
+            FieldType::I3F13 => Value::I3F13(I3F13::from_bits(u16_le() as i16)),
+        }
+    }
+}
+
+impl Schema {
+    pub const fn payload_len(&self) -> usize {
+        let mut len = 0;
+        let mut i = 0;
+        while i < self.fields.len() {
+            len += self.fields[i].1.size();
+            i += 1;
+        }
+        len
+    }
+}
+
+// Payload is the buffer a message is written to before being framed
+pub struct Payload {
+    bytes: [u8; MAX_MESSAGE],
+    len: usize,
+}
+
+impl Payload {
+    fn new(kind: u8) -> Payload {
+        let mut bytes = [0; MAX_MESSAGE];
+        bytes[0] = kind;
+        Payload { bytes, len: 1 }
+    }
+
+    // push appends a field. The fields that don't fit are dropped, the
+    // decoder then rejects the message as too short for its schema.
+    pub fn push(&mut self, value: Value) {
+        let size = value.field_type().size();
+        if self.len + size <= 1 + MAX_PAYLOAD {
+            value.write_le(&mut self.bytes[self.len..]);
+            self.len += size;
+        }
+    }
+
+    // frame appends the crc and COBS encodes the message, without the final 0
+    fn frame(mut self, out: &mut [u8; MAX_FRAME]) -> usize {
+        let crc = crc16(&self.bytes[..self.len]);
+        self.bytes[self.len..self.len + 2].copy_from_slice(&crc.to_le_bytes());
+        cobs_encode(&self.bytes[..self.len + 2], out)
+    }
+}
+
+// send writes a framed message, blocking until all the bytes are written
+pub fn send<W: serial::Write<u8>, T: Telemetry>(w: &mut W, message: &T) -> Result<(), W::Error> {
+    let mut payload = Payload::new(T::SCHEMA.kind);
+    message.write_fields(&mut payload);
+    let mut frame = [0; MAX_FRAME];
+    let len = payload.frame(&mut frame);
+    for b in &frame[..len] {
+        nb::block!(w.write(*b))?;
+    }
+    nb::block!(w.write(0))
+}
+
+// The fault message only tells which side failed, the HAL errors can't be
+// sent generically
+impl<I, O> Telemetry for Fault<I, O> {
+    const SCHEMA: Schema = FAULT;
+
+    fn write_fields(&self, payload: &mut Payload) {
+        payload.push(Value::Bool(matches!(self, Fault::Output(_))));
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DecodeError {
+    // The frame is not valid COBS or too long
+    Framing,
+    Crc,
+    UnknownKind(u8),
+    // The payload size doesn't match the schema of its kind
+    Length,
+}
+
+// Message is a decoded frame
+pub struct Message<'a> {
+    pub schema: &'a Schema,
+    payload: [u8; MAX_PAYLOAD],
+}
+
+impl Message<'_> {
+    // fields are the names and values of the fields, in the schema order
+    pub fn fields(&self) -> impl Iterator<Item = (&'static str, Value)> + '_ {
+        let mut offset = 0;
+        self.schema.fields.iter().map(move |(name, ty)| {
+            let value = ty.read(&self.payload[offset..]);
+            offset += ty.size();
+            (*name, value)
+        })
+    }
+}
+
+// decode checks and decodes a frame, without its final 0, with the schema
+// of its kind
+pub fn decode<'a>(frame: &[u8], schemas: &'a [Schema]) -> Result<Message<'a>, DecodeError> {
+    let mut bytes = [0; MAX_MESSAGE];
+    let len = cobs_decode(frame, &mut bytes).ok_or(DecodeError::Framing)?;
+    if len < 3 {
+        return Err(DecodeError::Framing);
+    }
+    let (message, crc) = bytes[..len].split_at(len - 2);
+    if crc16(message) != u16::from_le_bytes([crc[0], crc[1]]) {
+        return Err(DecodeError::Crc);
+    }
+    let schema = schemas
+        .iter()
+        .find(|s| s.kind == message[0])
+        .ok_or(DecodeError::UnknownKind(message[0]))?;
+    let fields = &message[1..];
+    if fields.len() != schema.payload_len() {
+        return Err(DecodeError::Length);
+    }
+    let mut payload = [0; MAX_PAYLOAD];
+    payload[..fields.len()].copy_from_slice(fields);
+    Ok(Message { schema, payload })
+}
+
+// crc16 is the CRC-16/CCITT-FALSE: polynomial 0x1021, initial value 0xFFFF
+pub fn crc16(bytes: &[u8]) -> u16 {
+    crc16_update(0xFFFF, bytes)
+}
+
+// crc16_update continues a crc16 with more bytes, to compute it in parts
+pub fn crc16_update(mut crc: u16, bytes: &[u8]) -> u16 {
+    for b in bytes {
+        crc ^= (*b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+// cobs_encode replaces the 0 bytes by the distance to the next one.
+// out must be at least one byte longer than bytes, plus one every 254 bytes.
+fn cobs_encode(bytes: &[u8], out: &mut [u8]) -> usize {
+    let mut code_idx = 0;
+    let mut code = 1;
+    let mut len = 1;
+    for b in bytes {
+        if *b != 0 {
+            out[len] = *b;
+            len += 1;
+            code += 1;
+        }
+        if *b == 0 || code == 0xFF {
+            out[code_idx] = code;
+            code_idx = len;
+            len += 1;
+            code = 1;
+        }
+    }
+    out[code_idx] = code;
+    len
+}
+
+// cobs_decode is the reverse of cobs_encode, it is None for an invalid frame
+// or if out is too short
+fn cobs_decode(frame: &[u8], out: &mut [u8]) -> Option<usize> {
+    let mut i = 0;
+    let mut len = 0;
+    while i < frame.len() {
+        let code = frame[i] as usize;
+        let block = frame.get(i + 1..i + code)?;
+        if code == 0 || block.contains(&0) {
+            return None;
+        }
+        out.get_mut(len..len + block.len())?.copy_from_slice(block);
+        len += block.len();
+        i += code;
+        // A full block has no 0 after it, nor the last one
+        if code < 0xFF && i < frame.len() {
+            *out.get_mut(len)? = 0;
+            len += 1;
+        }
+    }
+    Some(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Sink collects the frames in a fixed buffer, the tests are no_std
+    struct Sink {
+        bytes: [u8; 64],
+        len: usize,
+    }
+
+    impl serial::ErrorType for Sink {
+        type Error = core::convert::Infallible;
+    }
+
+    impl serial::Write<u8> for Sink {
+        fn write(&mut self, b: u8) -> nb::Result<(), Self::Error> {
+            self.bytes[self.len] = b;
+            self.len += 1;
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_crc16() {
+        assert_eq!(0x29B1, crc16(b"123456789"), "wrong check value");
+    }
+
+    #[test]
+    fn test_cobs() {
+        struct TestCase {
+            bytes: &'static [u8],
+            expected: &'static [u8],
+        }
+        let cases = [
+            TestCase {
+                bytes: &[0],
+                expected: &[1, 1],
+            },
+            TestCase {
+                bytes: &[0x11, 0, 0x22, 0x33],
+                expected: &[2, 0x11, 3, 0x22, 0x33],
+            },
+            TestCase {
+                bytes: &[0x11, 0x22, 0],
+                expected: &[3, 0x11, 0x22, 1],
+            },
+        ];
+        for tc in cases {
+            let mut out = [0; 8];
+            let len = cobs_encode(tc.bytes, &mut out);
+            assert_eq!(tc.expected, &out[..len], "wrong encoding");
+            let mut decoded = [0; 8];
+            let len = cobs_decode(&out[..len], &mut decoded);
+            assert_eq!(Some(tc.bytes.len()), len, "wrong decoded length");
+            assert_eq!(tc.bytes, &decoded[..tc.bytes.len()], "wrong decoding");
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let outputs = wiper::Outputs {
+            position: I3F13::lit("-1.5"),
+            button: true,
+        };
+        let mut sink = Sink {
+            bytes: [0; 64],
+            len: 0,
+        };
+        send(&mut sink, &outputs).unwrap();
+        send(&mut sink, &outputs).unwrap();
+        let stream = &sink.bytes[..sink.len];
+        assert_eq!(0, stream[stream.len() - 1], "a frame ends with a 0");
+        let mut frames = stream.split(|b| *b == 0);
+        let message = decode(frames.next().unwrap(), &SCHEMAS).unwrap();
+        assert_eq!("wiper", message.schema.name, "wrong kind");
+        let mut fields = message.fields();
+        assert_eq!(
+            Some(("position", Value::I3F13(I3F13::lit("-1.5")))),
+            fields.next()
+        );
+        assert_eq!(Some(("button", Value::Bool(true))), fields.next());
+        assert_eq!(None, fields.next());
+
+        // A dropped byte is caught by the crc
+        let frame = frames.next().unwrap();
+        let mut damaged = [0; MAX_FRAME];
+        damaged[..2].copy_from_slice(&frame[..2]);
+        damaged[2..frame.len() - 1].copy_from_slice(&frame[3..]);
+        assert!(
+            decode(&damaged[..frame.len() - 1], &SCHEMAS).is_err(),
+            "a damaged frame should be dropped"
+        );
+    }
+}
@@ -0,0 +1,463 @@
+//! This module contains a text format to record the inputs of an exercise,
+//! and a replay engine to feed a recorded trace through the `advance`
+//! function of an exercise.
+//!
+//! A trace has one line per loop iteration (tick):
+//! ```text
+//! # comments and empty lines are ignored
+//! <ms> <buttons> <level> [<roll>]
+//! 1000 01 -
+//! 1008 11 16384
+//! 1016 00 - 2891
+//! ```
+//! - `ms` is the chrono time of the tick
+//! - `buttons` has one char per button, in the order of the fields of the
+//!   exercise inputs: `1` when pressed, `0` when released. It is `-` when the
+//!   exercise has no button.
+//! - `level` is the raw bits of the `U1F15` ADC level, or `-` when the ADC
+//!   had no new value (or the exercise has no ADC)
+//! - `roll` is the random number drawn by the exercise on the tick, only
+//!   written by the exercises that draw one so that a replay draws the same
+//!
+//! A trace can be recorded from the uno over serial with `Record`, or be
+//! written by hand. Replaying it writes one `<ms> <outputs>` line per tick,
+//! which can be compared to a golden file with `check`.
+
+use core::marker::PhantomData;
+
+use fixed::types::U1F15;
+use ufmt::{uDisplay, uWrite, uwrite};
+
+use crate::exercise::Exercise;
+
+// The maximum number of buttons in a tick
+pub const MAX_BUTTONS: u8 = 8;
+
+#[derive(Debug, PartialEq)]
+pub struct Tick {
+    pub ms: u32,
+    // Each bit is the state of a button, true when pressed
+    pub buttons: u8,
+    // The number of buttons of the exercise
+    pub nb_buttons: u8,
+    pub level: Option<U1F15>,
+    // The random number of the tick, None when the exercise draws none
+    pub roll: Option<u32>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError;
+
+impl Tick {
+    pub fn button(&self, idx: u8) -> bool {
+        self.buttons >> idx & 0x1 != 0
+    }
+
+    // parse reads a line of a trace. It returns None for comments and empty
+    // lines.
+    pub fn parse(line: &str) -> Result<Option<Tick>, ParseError> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+        let mut fields = line.split_whitespace();
+        let (Some(ms), Some(buttons), Some(level), roll, None) = (
+            fields.next(),
+            fields.next(),
+            fields.next(),
+            fields.next(),
+            fields.next(),
+        ) else {
+            return Err(ParseError);
+        };
+        let ms = ms.parse().map_err(|_| ParseError)?;
+        let (buttons, nb_buttons) = match buttons {
+            "-" => (0, 0),
+            b if b.len() > MAX_BUTTONS as usize => return Err(ParseError),
+            b => {
+                let mut mask = 0;
+                for (idx, c) in b.chars().enumerate() {
+                    match c {
+                        '0' => {}
+                        '1' => mask |= 1 << idx,
+                        _ => return Err(ParseError),
+                    }
+                }
+                (mask, b.len() as u8)
+            }
+        };
+        let level = match level {
+            "-" => None,
+            l => Some(U1F15::from_bits(l.parse().map_err(|_| ParseError)?)),
+        };
+        let roll = match roll {
+            None => None,
+            Some(r) => Some(r.parse().map_err(|_| ParseError)?),
+        };
+        Ok(Some(Tick {
+            ms,
+            buttons,
+            nb_buttons,
+            level,
+            roll,
+        }))
+    }
+}
+
+impl uDisplay for Tick {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        uwrite!(f, "{} ", self.ms)?;
+        if self.nb_buttons == 0 {
+            f.write_str("-")?;
+        } else {
+            write_bits(f, (0..self.nb_buttons).map(|idx| self.button(idx)))?;
+        }
+        match self.level {
+            Some(level) => uwrite!(f, " {}", level.to_bits())?,
+            None => f.write_str(" -")?,
+        }
+        match self.roll {
+            Some(roll) => uwrite!(f, " {}", roll),
+            None => Ok(()),
+        }
+    }
+}
+
+// write_bits writes a list of bools as 0 and 1 chars, e.g. the leds of an
+// exercise outputs
+pub fn write_bits<W: uWrite + ?Sized>(
+    f: &mut ufmt::Formatter<'_, W>,
+    bits: impl Iterator<Item = bool>,
+) -> Result<(), W::Error> {
+    for bit in bits {
+        f.write_str(if bit { "1" } else { "0" })?;
+    }
+    Ok(())
+}
+
+// TraceInputs converts the inputs of an exercise from and to a tick
+pub trait TraceInputs: Sized {
+    fn from_tick(tick: &Tick) -> Self;
+    fn to_tick(&self) -> Tick;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ReplayError<E> {
+    // The trace line (starting at 1) could not be parsed
+    Parse(usize),
+    Write(E),
+}
+
+// replay feeds every tick of the trace through advance and writes a
+// `<ms> <outputs>` line for each of them.
+// It returns the state after the last tick.
+pub fn replay<S, I, O, W>(
+    state: S,
+    mut advance: impl FnMut(S, I) -> (S, O),
+    trace: &str,
+    w: &mut W,
+) -> Result<S, ReplayError<W::Error>>
+where
+    I: TraceInputs,
+    O: uDisplay,
+    W: uWrite + ?Sized,
+{
+    let mut state = state;
+    for (idx, line) in trace.lines().enumerate() {
+        let Some(tick) = Tick::parse(line).map_err(|_| ReplayError::Parse(idx + 1))? else {
+            continue;
+        };
+        let (new_state, outputs) = advance(state, I::from_tick(&tick));
+        state = new_state;
+        uwrite!(w, "{} {}\n", tick.ms, outputs).map_err(ReplayError::Write)?;
+    }
+    Ok(state)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CheckError {
+    // The trace line (starting at 1) could not be parsed
+    Parse(usize),
+    // The golden line (starting at 1) is different from the replayed outputs
+    Mismatch(usize),
+    // The golden file has more lines than the trace has ticks
+    MissingOutputs,
+}
+
+// check replays the trace and compares the outputs to the golden file,
+// line by line
+pub fn check<S, I, O>(
+    state: S,
+    advance: impl FnMut(S, I) -> (S, O),
+    trace: &str,
+    golden: &str,
+) -> Result<(), CheckError>
+where
+    I: TraceInputs,
+    O: uDisplay,
+{
+    let mut golden = GoldenWriter {
+        lines: golden.lines(),
+        line_idx: 0,
+        remaining: None,
+    };
+    replay(state, advance, trace, &mut golden).map_err(|e| match e {
+        ReplayError::Parse(line) => CheckError::Parse(line),
+        ReplayError::Write(line) => CheckError::Mismatch(line),
+    })?;
+    match golden.lines.next() {
+        Some(_) => Err(CheckError::MissingOutputs),
+        None => Ok(()),
+    }
+}
+
+// GoldenWriter compares what is written to it with the golden lines, so
+// that nothing has to be allocated
+struct GoldenWriter<'a> {
+    lines: core::str::Lines<'a>,
+    line_idx: usize,
+    // What has not been written yet of the current golden line
+    remaining: Option<&'a str>,
+}
+
+impl uWrite for GoldenWriter<'_> {
+    // The golden line (starting at 1) that doesn't match
+    type Error = usize;
+
+    fn write_str(&mut self, s: &str) -> Result<(), usize> {
+        let mut s = s;
+        while !s.is_empty() {
+            let remaining = match self.remaining {
+                Some(r) => r,
+                None => {
+                    self.line_idx += 1;
+                    self.lines.next().ok_or(self.line_idx)?
+                }
+            };
+            match s.split_once('\n') {
+                Some((end, rest)) => {
+                    if remaining != end {
+                        return Err(self.line_idx);
+                    }
+                    self.remaining = None;
+                    s = rest;
+                }
+                None => {
+                    let rest = remaining.strip_prefix(s).ok_or(self.line_idx)?;
+                    self.remaining = Some(rest);
+                    s = "";
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// assert_golden checks a trace against its golden file, and shows the whole
+// replayed outputs when they don't match
+#[cfg(test)]
+pub fn assert_golden<S, I, O>(
+    name: &str,
+    state: impl Fn() -> S,
+    advance: impl Fn(S, I) -> (S, O),
+    trace: &str,
+    golden: &str,
+) where
+    I: TraceInputs,
+    O: uDisplay,
+{
+    extern crate std;
+    if let Err(e) = check(state(), &advance, trace, golden) {
+        let mut actual = std::string::String::new();
+        replay(state(), &advance, trace, &mut actual).unwrap_or_else(|_| state());
+        panic!("{name}: {e:?}, the replayed outputs are:\n{actual}");
+    }
+}
+
+// Record wraps an exercise to print every tick it reads on a serial port,
+// e.g. to record a trace on the uno
+pub struct Record<E, W>(PhantomData<(E, W)>);
+
+// Recorder holds the input peripherals of the recorded exercise and the
+// serial port the ticks are written to
+pub struct Recorder<IPS, W> {
+    pub inputs: IPS,
+    pub serial: W,
+}
+
+impl<E: Exercise, W: uWrite> Exercise for Record<E, W>
+where
+    E::Inputs: TraceInputs,
+{
+    type Parameters = E::Parameters;
+    type Inputs = E::Inputs;
+    type State = E::State;
+    type Outputs = E::Outputs;
+    type InputPeripherals = Recorder<E::InputPeripherals, W>;
+    type OutputPeripherals = E::OutputPeripherals;
+    type Error = E::Error;
+
+    fn init(p: &E::Parameters) -> E::State {
+        E::init(p)
+    }
+
+    fn read_inputs(ps: &mut Self::InputPeripherals) -> Result<E::Inputs, E::Error> {
+        let inputs = E::read_inputs(&mut ps.inputs)?;
+        // The recording is only a debug help, a lost tick does not stop the
+        // exercise
+        uwrite!(&mut ps.serial, "{}\n", inputs.to_tick()).unwrap_or(());
+        Ok(inputs)
+    }
+
+    fn advance(p: &E::Parameters, s: E::State, inputs: E::Inputs) -> (E::State, E::Outputs) {
+        E::advance(p, s, inputs)
+    }
+
+    fn apply_outputs(
+        p: &E::Parameters,
+        outputs: E::Outputs,
+        ps: &mut E::OutputPeripherals,
+    ) -> Result<(), E::Error> {
+        E::apply_outputs(p, outputs, ps)
+    }
+
+    fn stop(p: &E::Parameters, error: &E::Error, ps: &mut E::OutputPeripherals) {
+        E::stop(p, error, ps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::String;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        struct TestCase {
+            line: &'static str,
+            expected: Result<Option<Tick>, ParseError>,
+        }
+        let cases = [
+            TestCase {
+                line: "# comment",
+                expected: Ok(None),
+            },
+            TestCase {
+                line: "   ",
+                expected: Ok(None),
+            },
+            TestCase {
+                line: "1000 01 -",
+                expected: Ok(Some(Tick {
+                    ms: 1000,
+                    buttons: 0b10,
+                    nb_buttons: 2,
+                    level: None,
+                    roll: None,
+                })),
+            },
+            TestCase {
+                line: "12\t- 16384",
+                expected: Ok(Some(Tick {
+                    ms: 12,
+                    buttons: 0,
+                    nb_buttons: 0,
+                    level: Some(U1F15::from_bits(16384)),
+                    roll: None,
+                })),
+            },
+            TestCase {
+                line: "12 - 16384 3",
+                expected: Ok(Some(Tick {
+                    ms: 12,
+                    buttons: 0,
+                    nb_buttons: 0,
+                    level: Some(U1F15::from_bits(16384)),
+                    roll: Some(3),
+                })),
+            },
+            TestCase {
+                line: "12 - 16384 3 4",
+                expected: Err(ParseError),
+            },
+            TestCase {
+                line: "12 - - -3",
+                expected: Err(ParseError),
+            },
+            TestCase {
+                line: "12 2 -",
+                expected: Err(ParseError),
+            },
+            TestCase {
+                line: "-12 1 -",
+                expected: Err(ParseError),
+            },
+        ];
+        for tc in cases {
+            let actual = Tick::parse(tc.line);
+            assert_eq!(tc.expected, actual, "wrong tick for {:?}", tc.line)
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        for line in ["1008 101 16384", "1016 - - 2891"] {
+            let tick = Tick::parse(line).unwrap().unwrap();
+            let mut actual = String::new();
+            uwrite!(&mut actual, "{}", tick).unwrap();
+            assert_eq!(line, actual, "wrong tick line")
+        }
+    }
+
+    // A minimal exercise core: count outputs the number of ticks during
+    // which the first button was pressed
+    struct Pressed(bool);
+
+    impl TraceInputs for Pressed {
+        fn from_tick(tick: &Tick) -> Pressed {
+            Pressed(tick.button(0))
+        }
+
+        fn to_tick(&self) -> Tick {
+            Tick {
+                ms: 0,
+                buttons: self.0 as u8,
+                nb_buttons: 1,
+                level: None,
+                roll: None,
+            }
+        }
+    }
+
+    fn count(s: u32, inputs: Pressed) -> (u32, u32) {
+        (s + inputs.0 as u32, s + inputs.0 as u32)
+    }
+
+    #[test]
+    fn test_check() {
+        let trace = "0 0 -\n# comment\n1 1 -\n2 1 -\n";
+        assert_eq!(Ok(()), check(0, count, trace, "0 0\n1 1\n2 2\n"));
+        assert_eq!(
+            Err(CheckError::Mismatch(2)),
+            check(0, count, trace, "0 0\n1 0\n2 2\n")
+        );
+        assert_eq!(
+            Err(CheckError::Mismatch(4)),
+            check(0, count, "0 0 -\n1 1 -\n2 1 -\n3 0 -", "0 0\n1 1\n2 2\n")
+        );
+        assert_eq!(
+            Err(CheckError::MissingOutputs),
+            check(0, count, trace, "0 0\n1 1\n2 2\n3 2\n")
+        );
+        assert_eq!(
+            Err(CheckError::Parse(2)),
+            check(0, count, "0 0 -\n1 x -\n", "0 0\n1 1\n")
+        );
+    }
+}
@@ -1,22 +1,61 @@
 use crate::adc::Adc;
-use embedded_hal::digital::{OutputPin, PinState};
-use fixed::types::{U1F15, U4F12};
+use crate::exercise::{self, Exercise, Fault};
+use crate::led_bank::LedBank;
+use crate::pattern::{Mask, MAX_LEDS};
+use crate::telemetry::{self, FieldType, Payload, Schema, Telemetry, Value};
+use crate::trace::{write_bits, Tick, TraceInputs};
+use core::marker::PhantomData;
+use embedded_hal_nb::serial;
+use fixed::types::{U17F15, U1F15};
+use ufmt::{uDisplay, uWrite, uwrite};
 
-// Number of leds + 1
-const NINE: U4F12 = U4F12::lit("9");
-
-pub fn run<PIN, A: Adc<PIN>, P: OutputPin, S: ufmt::uWrite>(
+pub fn run<PIN, A: Adc<PIN>, P: LedBank<N>, S: serial::Write<u8>, const N: usize>(
     ips: &mut InputPeripherals<PIN, A>,
-    ops: &mut OutputPeripherals<P, S>,
+    ops: &mut OutputPeripherals<P, S, N>,
 ) -> ! {
-    let mut state = State {
-        current_level: U1F15::ZERO,
-    };
-    loop {
-        let inputs = read_inputs(ips);
-        let (new_state, outputs) = advance(state, inputs);
-        state = new_state;
-        apply_outputs(outputs, ops)
+    exercise::run::<Vumetre<PIN, A, P, S, N>>(&(), ips, ops)
+}
+
+// Vumetre plugs the functions of this module into the Exercise trait.
+// N is the number of leds, up to pattern::MAX_LEDS.
+pub struct Vumetre<PIN, A, P, S, const N: usize = 8>(PhantomData<(PIN, A, P, S)>);
+
+impl<PIN, A: Adc<PIN>, P: LedBank<N>, S: serial::Write<u8>, const N: usize> Exercise
+    for Vumetre<PIN, A, P, S, N>
+{
+    // The vumetre has no parameters
+    type Parameters = ();
+    type Inputs = Inputs;
+    type State = State;
+    type Outputs = Outputs<N>;
+    type InputPeripherals = InputPeripherals<PIN, A>;
+    type OutputPeripherals = OutputPeripherals<P, S, N>;
+    type Error = Fault<A::Error, OutputError<P::Error, S::Error>>;
+
+    fn init(_: &()) -> State {
+        State {
+            current_level: U1F15::ZERO,
+        }
+    }
+
+    fn read_inputs(ps: &mut InputPeripherals<PIN, A>) -> Result<Inputs, Self::Error> {
+        read_inputs(ps).map_err(Fault::Input)
+    }
+
+    fn advance(_: &(), s: State, inputs: Inputs) -> (State, Outputs<N>) {
+        advance(s, inputs)
+    }
+
+    fn apply_outputs(
+        _: &(),
+        outputs: Outputs<N>,
+        ps: &mut OutputPeripherals<P, S, N>,
+    ) -> Result<(), Self::Error> {
+        apply_outputs(outputs, ps).map_err(Fault::Output)
+    }
+
+    fn stop(_: &(), error: &Self::Error, ps: &mut OutputPeripherals<P, S, N>) {
+        stop(error, ps)
     }
 }
 
@@ -25,7 +64,7 @@ pub struct Inputs {
     pub level: Option<U1F15>,
 }
 
-pub struct Outputs {
+pub struct Outputs<const N: usize = 8> {
     // The normalized between 0 and 1 value of the ADC
     // For serial port debug
     pub adc_value: U1F15,
@@ -34,7 +73,7 @@ pub struct Outputs {
     pub nb_lebs: u8,
     // Each bit is the state of a led
     // This is 2^nb_leds - 1
-    pub leds: u8,
+    pub leds: Mask,
 }
 
 pub struct State {
@@ -43,11 +82,16 @@ pub struct State {
     pub current_level: U1F15,
 }
 
-pub fn advance(s: State, inputs: Inputs) -> (State, Outputs) {
+pub fn advance<const N: usize>(s: State, inputs: Inputs) -> (State, Outputs<N>) {
+    const { assert!(N <= MAX_LEDS, "too many leds for the mask") };
     // Use the new value, or the old one if we don't have a new value
     let current = inputs.level.unwrap_or(s.current_level);
-    let nb_leds: u8 = current.wide_mul(NINE).to_num();
-    let leds = 1_u8.checked_shl(nb_leds as u32).map_or(u8::MAX, |n| n - 1);
+    // The level is split in N + 1 ranges, from no led to all of them.
+    // Above 1 all the leds are on.
+    let nb_leds = (U17F15::from_num(current) * (N as u32 + 1))
+        .to_num::<u32>()
+        .min(N as u32) as u8;
+    let leds = ((1_u32 << nb_leds) - 1) as Mask;
     (
         State {
             current_level: current,
@@ -60,38 +104,95 @@ pub fn advance(s: State, inputs: Inputs) -> (State, Outputs) {
     )
 }
 
+impl TraceInputs for Inputs {
+    fn from_tick(tick: &Tick) -> Inputs {
+        Inputs { level: tick.level }
+    }
+
+    // The vumetre has no chrono, its ticks have no time
+    fn to_tick(&self) -> Tick {
+        Tick {
+            ms: 0,
+            buttons: 0,
+            nb_buttons: 0,
+            level: self.level,
+            roll: None,
+        }
+    }
+}
+
+impl<const N: usize> uDisplay for Outputs<N> {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error> {
+        f.write_str("leds=")?;
+        write_bits(f, (0..N).map(|led_idx| self.leds >> led_idx & 0x1 != 0))?;
+        uwrite!(f, " adc_value={}", self.adc_value.to_bits())
+    }
+}
+
+pub const SCHEMA: Schema = Schema {
+    kind: 0x02,
+    name: "vumetre",
+    fields: &[
+        ("adc_value", FieldType::U1F15),
+        ("nb_leds", FieldType::U8),
+        ("leds", FieldType::U16),
+    ],
+};
+
+impl<const N: usize> Telemetry for Outputs<N> {
+    const SCHEMA: Schema = SCHEMA;
+
+    fn write_fields(&self, payload: &mut Payload) {
+        payload.push(Value::U1F15(self.adc_value));
+        payload.push(Value::U8(self.nb_lebs));
+        payload.push(Value::U16(self.leds));
+    }
+}
+
 pub struct InputPeripherals<PIN, A: crate::adc::Adc<PIN>> {
     pub adc: A,
     pub pin: PIN,
 }
 
-fn read_inputs<PIN, A: crate::adc::Adc<PIN>>(ps: &mut InputPeripherals<PIN, A>) -> Inputs {
-    Inputs {
-        level: ps.adc.read_nonblocking(&ps.pin).ok(),
-    }
+pub fn read_inputs<PIN, A: crate::adc::Adc<PIN>>(
+    ps: &mut InputPeripherals<PIN, A>,
+) -> Result<Inputs, A::Error> {
+    let level = match ps.adc.read_nonblocking(&ps.pin) {
+        Ok(level) => Some(level),
+        // The adc is still converting, the last level will be used
+        Err(nb::Error::WouldBlock) => None,
+        Err(nb::Error::Other(e)) => return Err(e),
+    };
+    Ok(Inputs { level })
 }
 
-pub struct OutputPeripherals<T: OutputPin, S> {
-    pub ps: [T; 8],
+pub struct OutputPeripherals<T: LedBank<N>, S, const N: usize = 8> {
+    pub ps: T,
     pub serial: S,
 }
 
-fn apply_outputs<T: OutputPin, S: ufmt::uWrite>(
-    outputs: Outputs,
-    pins: &mut OutputPeripherals<T, S>,
-) -> () {
-    for (led_idx, pin) in pins.ps.iter_mut().enumerate() {
-        pin.set_state(PinState::from(outputs.leds >> led_idx & 0x1 != 0))
-            .unwrap()
-    }
-    ufmt::uwrite!(
-        &mut pins.serial,
-        "nb_leds={}\tled_byte={}\tadc_value={}\n",
-        outputs.nb_lebs,
-        outputs.leds,
-        outputs.adc_value.to_bits(),
-    )
-    .unwrap_or(());
+// OutputError tells which of the output peripherals failed
+#[derive(Debug, PartialEq)]
+pub enum OutputError<P, S> {
+    Leds(P),
+    Serial(S),
+}
+
+pub fn apply_outputs<T: LedBank<N>, S: serial::Write<u8>, const N: usize>(
+    outputs: Outputs<N>,
+    pins: &mut OutputPeripherals<T, S, N>,
+) -> Result<(), OutputError<T::Error, S::Error>> {
+    pins.ps.show(outputs.leds).map_err(OutputError::Leds)?;
+    telemetry::send(&mut pins.serial, &outputs).map_err(OutputError::Serial)
+}
+
+// stop turns all the leds off and sends the fault
+pub fn stop<T: LedBank<N>, S: serial::Write<u8>, E: Telemetry, const N: usize>(
+    error: &E,
+    pins: &mut OutputPeripherals<T, S, N>,
+) {
+    pins.ps.off().unwrap_or(());
+    telemetry::send(&mut pins.serial, error).unwrap_or(())
 }
 
 #[cfg(test)]
@@ -102,7 +203,7 @@ mod tests {
     fn test_outputs() {
         struct TestCase {
             level: U1F15,
-            expected: u8,
+            expected: Mask,
         }
         let cases = [
             TestCase {
@@ -111,7 +212,7 @@ mod tests {
             },
             TestCase {
                 level: U1F15::ONE,
-                expected: u8::MAX,
+                expected: 0xff,
             },
             TestCase {
                 level: U1F15::lit("7e-1"),
@@ -125,8 +226,26 @@ mod tests {
             let inputs = Inputs {
                 level: Some(tc.level),
             };
-            let (_, outputs) = advance(s, inputs);
+            let (_, outputs) = advance::<8>(s, inputs);
             assert_eq!(tc.expected, outputs.leds, "wrong led pattern")
         }
     }
+
+    #[test]
+    fn test_nb_leds() {
+        let advance_16 = |level| {
+            let s = State {
+                current_level: U1F15::ZERO,
+            };
+            advance::<16>(s, Inputs { level: Some(level) }).1.leds
+        };
+        assert_eq!(
+            0xffff,
+            advance_16(U1F15::ONE),
+            "all the 16 leds should be on"
+        );
+        assert_eq!(0x00ff, advance_16(U1F15::lit("5e-1")), "half the leds");
+        // Above 1 it doesn't overflow
+        assert_eq!(0xffff, advance_16(U1F15::MAX));
+    }
 }
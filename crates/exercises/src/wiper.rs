@@ -1,45 +1,91 @@
+use core::marker::PhantomData;
+
 use embedded_hal::{digital::InputPin, pwm::SetDutyCycle};
+use embedded_hal_nb::serial;
 use fixed::{
     traits::{FromFixed, ToFixed},
     types::{I16F0, I3F13, U1F15},
 };
+use ufmt::{uDisplay, uWrite, uwrite};
 
-use crate::chrono::Chrono;
+use crate::chrono::{Chrono, Instant};
+use crate::console::{Tunable, TuneError};
+use crate::exercise::{self, Exercise, Fault};
+use crate::telemetry::{self, FieldType, Payload, Schema, Telemetry, Value};
+use crate::trace::{Tick, TraceInputs};
 
 #[inline]
-pub fn run<C: Chrono, IP: InputPin, S: embedded_hal::pwm::SetDutyCycle, SERIAL: ufmt::uWrite>(
+pub fn run<
+    C: Chrono,
+    IP: InputPin,
+    S: embedded_hal::pwm::SetDutyCycle,
+    SERIAL: serial::Write<u8>,
+>(
     params: &Parameters,
     ips: &mut InputPeripherals<C, IP>,
     ops: &mut OutputPeripherals<S, SERIAL>,
 ) -> ! {
-    let mut state = State {
-        position: params.min_position,
-        ts: 0,
-    };
-    loop {
-        let inputs = read_inputs(ips);
-        // Generate a new state of the game instead of mutating the current state
-        let (new_state, outputs) = update_state(params, state, inputs);
-        state = new_state;
-        apply_outputs(params, outputs, ops)
+    exercise::run::<Wiper<C, IP, S, SERIAL>>(params, ips, ops)
+}
+
+// Wiper plugs the functions of this module into the Exercise trait
+pub struct Wiper<C, IP, S, SERIAL>(PhantomData<(C, IP, S, SERIAL)>);
+
+impl<C: Chrono, IP: InputPin, S: SetDutyCycle, SERIAL: serial::Write<u8>> Exercise
+    for Wiper<C, IP, S, SERIAL>
+{
+    type Parameters = Parameters;
+    type Inputs = Inputs;
+    type State = State;
+    type Outputs = Outputs;
+    type InputPeripherals = InputPeripherals<C, IP>;
+    type OutputPeripherals = OutputPeripherals<S, SERIAL>;
+    type Error = Fault<IP::Error, OutputError<S::Error, SERIAL::Error>>;
+
+    fn init(p: &Parameters) -> State {
+        State {
+            position: p.min_position,
+            ts: Instant::from_millis(0),
+        }
+    }
+
+    fn read_inputs(ps: &mut InputPeripherals<C, IP>) -> Result<Inputs, Self::Error> {
+        read_inputs(ps).map_err(Fault::Input)
+    }
+
+    fn advance(p: &Parameters, s: State, inputs: Inputs) -> (State, Outputs) {
+        update_state(p, s, inputs)
+    }
+
+    fn apply_outputs(
+        p: &Parameters,
+        outputs: Outputs,
+        ps: &mut OutputPeripherals<S, SERIAL>,
+    ) -> Result<(), Self::Error> {
+        apply_outputs(p, outputs, ps).map_err(Fault::Output)
+    }
+
+    fn stop(_: &Parameters, error: &Self::Error, ps: &mut OutputPeripherals<S, SERIAL>) {
+        stop(error, ps)
     }
 }
 
-struct Inputs {
-    ms: u32,
-    button_pressed: bool,
+pub struct Inputs {
+    pub now: Instant,
+    pub button_pressed: bool,
 }
 
-struct Outputs {
-    position: I3F13,
-    button: bool,
+pub struct Outputs {
+    pub position: I3F13,
+    pub button: bool,
 }
 
-struct State {
-    position: I3F13,
-    ts: u32,
+pub struct State {
+    pub position: I3F13,
+    pub ts: Instant,
 }
 
+#[derive(Clone)]
 pub struct Parameters {
     pub absolute_speed: U1F15,
     pub min_position: I3F13,
@@ -49,15 +95,24 @@ pub struct Parameters {
     pub pwm_period_us: u32,
 }
 
-fn update_state(p: &Parameters, s: State, input: Inputs) -> (State, Outputs) {
+pub fn update_state(p: &Parameters, s: State, input: Inputs) -> (State, Outputs) {
     let direction: i16 = if input.button_pressed { 1 } else { -1 };
     let speed = I3F13::from_fixed(p.absolute_speed) * direction;
-    let position_delta = speed.wide_mul(I16F0::from_bits(input.ms as i16 - s.ts as i16)) / 1000;
-    let new_position = s.position + position_delta.to_fixed::<I3F13>();
+    // The wiper goes from one end to the other in a few seconds, so a longer
+    // gap between two loops can be cut to fit the fixed point arithmetic
+    let dt = input
+        .now
+        .duration_since(s.ts)
+        .as_millis()
+        .min(i16::MAX as u32);
+    let position_delta = speed.wide_mul(I16F0::from_bits(dt as i16)) / 1000;
+    let new_position = s
+        .position
+        .saturating_add(position_delta.saturating_to_fixed::<I3F13>());
     let new_position = I3F13::min(p.max_position, I3F13::max(p.min_position, new_position));
     let new_state = State {
         position: new_position,
-        ts: input.ms,
+        ts: input.now,
     };
     let outputs = Outputs {
         position: new_position,
@@ -66,28 +121,126 @@ fn update_state(p: &Parameters, s: State, input: Inputs) -> (State, Outputs) {
     (new_state, outputs)
 }
 
+impl TraceInputs for Inputs {
+    fn from_tick(tick: &Tick) -> Inputs {
+        Inputs {
+            now: Instant::from_millis(tick.ms),
+            button_pressed: tick.button(0),
+        }
+    }
+
+    fn to_tick(&self) -> Tick {
+        Tick {
+            ms: self.now.as_millis(),
+            buttons: self.button_pressed as u8,
+            nb_buttons: 1,
+            level: None,
+            roll: None,
+        }
+    }
+}
+
+impl uDisplay for Outputs {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error> {
+        uwrite!(
+            f,
+            "position={} button={}",
+            self.position.to_bits(),
+            self.button
+        )
+    }
+}
+
+pub const SCHEMA: Schema = Schema {
+    kind: 0x03,
+    name: "wiper",
+    fields: &[("position", FieldType::I3F13), ("button", FieldType::Bool)],
+};
+
+impl Telemetry for Outputs {
+    const SCHEMA: Schema = SCHEMA;
+
+    fn write_fields(&self, payload: &mut Payload) {
+        payload.push(Value::I3F13(self.position));
+        payload.push(Value::Bool(self.button));
+    }
+}
+
+impl Tunable for Parameters {
+    const FIELDS: &'static [(&'static str, FieldType)] = &[
+        ("absolute_speed", FieldType::U1F15),
+        ("min_position", FieldType::I3F13),
+        ("min_position_us", FieldType::U16),
+        ("max_position", FieldType::I3F13),
+        ("max_position_us", FieldType::U16),
+        ("pwm_period_us", FieldType::U32),
+    ];
+
+    fn get(&self, field: &str) -> Option<Value> {
+        match field {
+            "absolute_speed" => Some(Value::U1F15(self.absolute_speed)),
+            "min_position" => Some(Value::I3F13(self.min_position)),
+            "min_position_us" => Some(Value::U16(self.min_position_us)),
+            "max_position" => Some(Value::I3F13(self.max_position)),
+            "max_position_us" => Some(Value::U16(self.max_position_us)),
+            "pwm_period_us" => Some(Value::U32(self.pwm_period_us)),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, field: &str, value: Value) -> Result<(), TuneError> {
+        match (field, value) {
+            ("absolute_speed", Value::U1F15(v)) => self.absolute_speed = v,
+            ("min_position", Value::I3F13(v)) => self.min_position = v,
+            ("min_position_us", Value::U16(v)) => self.min_position_us = v,
+            ("max_position", Value::I3F13(v)) => self.max_position = v,
+            ("max_position_us", Value::U16(v)) => self.max_position_us = v,
+            ("pwm_period_us", Value::U32(v)) => self.pwm_period_us = v,
+            _ => return Err(TuneError::UnknownField),
+        }
+        Ok(())
+    }
+
+    // apply_outputs divides by both ranges and by the period, and the pulse
+    // must fit in the period
+    fn is_valid(&self) -> bool {
+        self.min_position < self.max_position
+            && self.min_position_us < self.max_position_us
+            && self.max_position_us as u32 <= self.pwm_period_us
+    }
+}
+
 pub struct InputPeripherals<T: Chrono, IP: InputPin> {
     pub chrono: T,
     pub button: IP,
 }
 
-fn read_inputs<T: Chrono, IP: InputPin>(ps: &mut InputPeripherals<T, IP>) -> Inputs {
-    Inputs {
-        ms: ps.chrono.millis(),
-        button_pressed: ps.button.is_low().unwrap(),
-    }
+pub fn read_inputs<T: Chrono, IP: InputPin>(
+    ps: &mut InputPeripherals<T, IP>,
+) -> Result<Inputs, IP::Error> {
+    Ok(Inputs {
+        now: ps.chrono.now(),
+        button_pressed: ps.button.is_low()?,
+    })
 }
 
-pub struct OutputPeripherals<S: embedded_hal::pwm::SetDutyCycle, SERIAL: ufmt::uWrite> {
+pub struct OutputPeripherals<S: embedded_hal::pwm::SetDutyCycle, SERIAL: serial::Write<u8>> {
     pub servo: S,
     pub serial: SERIAL,
 }
 
-fn apply_outputs<S: SetDutyCycle, SERIAL: ufmt::uWrite>(
+// OutputError tells which of the output peripherals failed
+#[derive(Debug, PartialEq)]
+pub enum OutputError<S, SERIAL> {
+    Servo(S),
+    Serial(SERIAL),
+}
+
+pub fn apply_outputs<S: SetDutyCycle, SERIAL: serial::Write<u8>>(
     p: &Parameters,
     outputs: Outputs,
     op: &mut OutputPeripherals<S, SERIAL>,
-) -> () {
+) -> Result<(), OutputError<S::Error, SERIAL::Error>> {
     let max_duty = op.servo.max_duty_cycle();
 
     let position_range = p.max_position - p.min_position;
@@ -101,17 +254,86 @@ fn apply_outputs<S: SetDutyCycle, SERIAL: ufmt::uWrite>(
 
     let duty: u16 = ((duty_us as u32) * (max_duty as u32) / (p.pwm_period_us as u32)) as u16;
 
-    ufmt::uwrite!(
-        &mut op.serial,
-        "button={}\tmax_duty={}\tduty={}\tposition_range={}\tduty_range_us={}\tt={}\tduty_us={}\n",
-        outputs.button,
-        max_duty,
-        duty,
-        position_range.to_bits(),
-        duty_range_us,
-        t,
-        duty_us,
-    )
-    .unwrap_or_default();
-    op.servo.set_duty_cycle(duty).unwrap_or(())
+    telemetry::send(&mut op.serial, &outputs).map_err(OutputError::Serial)?;
+    op.servo.set_duty_cycle(duty).map_err(OutputError::Servo)
+}
+
+// stop cuts the pulses so the servo stops holding its position, and sends
+// the fault
+pub fn stop<S: SetDutyCycle, SERIAL: serial::Write<u8>, E: Telemetry>(
+    error: &E,
+    op: &mut OutputPeripherals<S, SERIAL>,
+) {
+    op.servo.set_duty_cycle_fully_off().unwrap_or(());
+    telemetry::send(&mut op.serial, error).unwrap_or(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_state() {
+        struct TestCase {
+            ts: u32,
+            ms: u32,
+            button_pressed: bool,
+            expected: I3F13,
+        }
+        let p = Parameters {
+            absolute_speed: U1F15::lit("0.7853981633"),
+            min_position: I3F13::lit("-1.57079632679"),
+            min_position_us: 500,
+            max_position: I3F13::lit("1.57079632679"),
+            max_position_us: 2500,
+            pwm_period_us: 20_000,
+        };
+        let cases = [
+            // A quarter turn in 2s
+            TestCase {
+                ts: 1_000,
+                ms: 3_000,
+                button_pressed: true,
+                expected: I3F13::ZERO,
+            },
+            // Across a clock rollover
+            TestCase {
+                ts: u32::MAX - 999,
+                ms: 1_000,
+                button_pressed: true,
+                expected: I3F13::ZERO,
+            },
+            // A gap too long for an i16 goes to the end
+            TestCase {
+                ts: 0,
+                ms: 40_000,
+                button_pressed: true,
+                expected: p.max_position,
+            },
+            // The button is released, it stays at the min
+            TestCase {
+                ts: 0,
+                ms: 40_000,
+                button_pressed: false,
+                expected: p.min_position,
+            },
+        ];
+        for tc in cases {
+            let s = State {
+                position: p.min_position,
+                ts: Instant::from_millis(tc.ts),
+            };
+            let inputs = Inputs {
+                now: Instant::from_millis(tc.ms),
+                button_pressed: tc.button_pressed,
+            };
+            let (s, _) = update_state(&p, s, inputs);
+            assert!(
+                (s.position - tc.expected).abs() < I3F13::lit("1e-2"),
+                "wrong position {} instead of {}",
+                s.position,
+                tc.expected
+            );
+        }
+    }
 }
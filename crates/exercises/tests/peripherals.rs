@@ -0,0 +1,651 @@
+//! These tests run the whole read -> advance -> apply loop of the exercises
+//! against the mock peripherals of the testkit.
+
+use embedded_hal::digital::PinState;
+use exercises::button::Polled;
+use exercises::chrono::{Duration, Instant};
+use exercises::console::Console;
+use exercises::exercise::{Fault, Runner};
+use exercises::led_bank::Dimmed;
+use exercises::pattern::Builtin;
+use exercises::persist::NoStorage;
+use exercises::sequencer::{self, Op};
+use exercises::shift_register::{BitBang, ShiftRegister};
+use exercises::telemetry::{self, Value, SCHEMAS};
+use exercises::trace::{self, Record, Recorder};
+use exercises::{chenillard, cowboy, debounce, dice, vumetre, wiper};
+use exercises_testkit::{
+    Disconnected, ManualChrono, MockAdc, MockShiftRegister, RecordingLeds, RecordingPin,
+    RecordingPwm, ScriptedPin, SerialSink,
+};
+use fixed::types::{I3F13, U1F15};
+use rand::{rngs::SmallRng, SeedableRng};
+
+fn lit(pins: &[RecordingPin]) -> Vec<bool> {
+    pins.iter().map(RecordingPin::is_set_high).collect()
+}
+
+fn duties(pwms: &[RecordingPwm]) -> Vec<u16> {
+    pwms.iter().map(|pwm| pwm.duty().unwrap_or(0)).collect()
+}
+
+// values decodes the last telemetry frame sent on the serial
+fn last_values(serial: &SerialSink) -> Vec<Value> {
+    let frame = serial.frames().pop().expect("no frame sent");
+    let message = telemetry::decode(&frame, &SCHEMAS).expect("invalid frame");
+    message.fields().map(|(_, value)| value).collect()
+}
+
+#[test]
+fn test_chenillard() {
+    let chrono = ManualChrono::new(300);
+    let leds: [RecordingPwm; 8] = std::array::from_fn(|_| RecordingPwm::new(255));
+    let button = ScriptedPin::released();
+    let adc = MockAdc::new(U1F15::ONE);
+    let mut ips = chenillard::InputPeripherals {
+        chrono: chrono.clone(),
+        button: button.clone(),
+        faster: ScriptedPin::released(),
+        slower: ScriptedPin::released(),
+        reverse: ScriptedPin::released(),
+        adc: adc.clone(),
+        pin: (),
+    };
+    let mut ops = chenillard::OutputPeripherals {
+        ps: Dimmed(leds.clone()),
+    };
+    let p = chenillard::Parameters {
+        period: Duration::from_millis(1000),
+        pattern: Builtin::Walk,
+        trail: 1,
+    };
+    let mut runner = Runner::<chenillard::Chenillard<_, _, _, _, _>>::new(&p, &mut ips, &mut ops);
+
+    runner.step();
+    // The led before the current one fades out
+    let mut expected = [0; 8];
+    expected[1] = 127;
+    expected[2] = 255;
+    assert_eq!(expected.to_vec(), duties(&leds), "wrong leds at 300ms");
+
+    chrono.set(950);
+    runner.step();
+    let mut expected = [0; 8];
+    expected[6] = 127;
+    expected[7] = 255;
+    assert_eq!(expected.to_vec(), duties(&leds), "wrong leds at 950ms");
+
+    // A press goes to the ping pong, back from the last led
+    chrono.set(1_600);
+    button.push([PinState::Low]);
+    runner.step();
+    let mut expected = [0; 8];
+    expected[6] = 255;
+    expected[7] = 127;
+    assert_eq!(expected.to_vec(), duties(&leds), "wrong leds at 1600ms");
+
+    // The potentiometer at half slows the leds down from where they are
+    adc.set_level(U1F15::lit("0.5"));
+    runner.step();
+    chrono.set(2_100);
+    runner.step();
+    let mut expected = [0; 8];
+    expected[3] = 255;
+    expected[4] = 127;
+    assert_eq!(expected.to_vec(), duties(&leds), "wrong leds at 2100ms");
+}
+
+#[test]
+fn test_sequencer() {
+    const OPS: [Op; 4] = [
+        Op::Set(0b101),
+        Op::Wait(100),
+        Op::ShiftLeft(1),
+        Op::Wait(100),
+    ];
+    const SHOW: [u8; sequencer::size(&OPS)] = sequencer::assemble(&OPS);
+    let chrono = ManualChrono::new(0);
+    let leds = RecordingLeds::<8>::new();
+    let mut ips = sequencer::InputPeripherals {
+        chrono: chrono.clone(),
+    };
+    let mut ops = chenillard::OutputPeripherals { ps: leds.clone() };
+    let p = sequencer::Parameters { program: &SHOW[..] };
+    let mut runner = Runner::<sequencer::Sequencer<_, _, _>>::new(&p, &mut ips, &mut ops);
+
+    runner.step();
+    assert_eq!(0b101, leds.mask(), "wrong leds at 0ms");
+    chrono.set(150);
+    runner.step();
+    assert_eq!(0b1010, leds.mask(), "wrong leds at 150ms");
+    // The program starts over
+    chrono.set(200);
+    runner.step();
+    assert_eq!(0b101, leds.mask(), "wrong leds at 200ms");
+    assert_eq!(
+        vec![0b101, 0b1010, 0b101],
+        leds.history(),
+        "the leds should be written on each loop"
+    );
+}
+
+#[test]
+fn test_cowboy_false_start() {
+    let chrono = ManualChrono::new(0);
+    let (left_button, right_button) = (ScriptedPin::released(), ScriptedPin::released());
+    let (left_led, right_led) = (RecordingPin::new(), RecordingPin::new());
+    let mut ips = cowboy::InputPeripherals {
+        chrono: chrono.clone(),
+        left_button: Polled(left_button.clone()),
+        right_button: Polled(right_button.clone()),
+        rng: SmallRng::seed_from_u64(974),
+    };
+    let mut ops = cowboy::OutputPeripherals {
+        left_led: left_led.clone(),
+        right_led: right_led.clone(),
+        serial: SerialSink::new(),
+    };
+    let p = cowboy::Parameters {
+        nb_blinks: 3,
+        blink_duration: Duration::from_millis(3_000),
+        show_winner_duration: Duration::from_millis(5_000),
+        min_hold: Duration::ZERO,
+        max_hold: Duration::ZERO,
+    };
+    let mut runner = Runner::<cowboy::Cowboy<_, _, _, _>>::new(&p, &mut ips, &mut ops);
+
+    runner.step();
+    // The left player presses during the blinking for a single loop
+    chrono.set(1_700);
+    left_button.push([PinState::Low, PinState::High]);
+    runner.run_for(2);
+    assert!(
+        matches!(
+            runner.state(),
+            cowboy::State {
+                round: cowboy::Round::ShowingWinner(t, false, true),
+                ..
+            } if *t == Instant::from_millis(1_700)
+        ),
+        "the right player should win"
+    );
+    assert_eq!(
+        (Some(PinState::Low), Some(PinState::High)),
+        (left_led.state(), right_led.state()),
+        "only the right led should be on"
+    );
+}
+
+#[test]
+fn test_cowboy_reaction() {
+    let chrono = ManualChrono::new(0);
+    let (left_button, right_button) = (ScriptedPin::released(), ScriptedPin::released());
+    let serial = SerialSink::new();
+    let mut ips = cowboy::InputPeripherals {
+        chrono: chrono.clone(),
+        left_button: Polled(left_button.clone()),
+        right_button: Polled(right_button.clone()),
+        rng: SmallRng::seed_from_u64(974),
+    };
+    let mut ops = cowboy::OutputPeripherals {
+        left_led: RecordingPin::new(),
+        right_led: RecordingPin::new(),
+        serial: serial.clone(),
+    };
+    let p = cowboy::Parameters {
+        nb_blinks: 3,
+        blink_duration: Duration::from_millis(3_000),
+        show_winner_duration: Duration::from_millis(5_000),
+        min_hold: Duration::ZERO,
+        max_hold: Duration::ZERO,
+    };
+    let mut runner = Runner::<cowboy::Cowboy<_, _, _, _>>::new(&p, &mut ips, &mut ops);
+
+    // The leds stop blinking at 3_010, the right player wins and the left
+    // one is still timed when they press later
+    for (ms, left, right) in [
+        (0, PinState::High, PinState::High),
+        (3_010, PinState::High, PinState::High),
+        (3_260, PinState::High, PinState::Low),
+        (3_400, PinState::Low, PinState::High),
+    ] {
+        chrono.set(ms);
+        left_button.set(left);
+        right_button.set(right);
+        runner.step();
+    }
+    assert!(
+        serial.lines().is_empty(),
+        "no summary before the round ends"
+    );
+    chrono.set(8_500);
+    left_button.release();
+    runner.step();
+    assert_eq!(
+        vec![
+            "# left: last=390ms best=390ms avg=390ms false_starts=0 \
+             | right: last=250ms best=250ms avg=250ms false_starts=0"
+        ],
+        serial.lines(),
+        "wrong summary"
+    );
+
+    // The left player starts too early in the next round
+    chrono.set(9_000);
+    left_button.press();
+    runner.step();
+    chrono.set(14_100);
+    left_button.release();
+    runner.step();
+    assert_eq!(
+        "# left: last=- best=390ms avg=390ms false_starts=1 \
+         | right: last=- best=250ms avg=250ms false_starts=0",
+        serial.lines()[1],
+        "wrong summary after the false start"
+    );
+}
+
+#[test]
+fn test_cowboy_record() {
+    let chrono = ManualChrono::new(0);
+    let (left_button, right_button) = (ScriptedPin::released(), ScriptedPin::released());
+    let trace = SerialSink::new();
+    let mut ips = Recorder {
+        inputs: cowboy::InputPeripherals {
+            chrono: chrono.clone(),
+            left_button: Polled(left_button.clone()),
+            right_button: Polled(right_button.clone()),
+            rng: SmallRng::seed_from_u64(974),
+        },
+        serial: trace.clone(),
+    };
+    let mut ops = cowboy::OutputPeripherals {
+        left_led: RecordingPin::new(),
+        right_led: RecordingPin::new(),
+        serial: SerialSink::new(),
+    };
+    let p = cowboy::Parameters {
+        nb_blinks: 3,
+        blink_duration: Duration::from_millis(3_000),
+        show_winner_duration: Duration::from_millis(5_000),
+        min_hold: Duration::from_millis(500),
+        max_hold: Duration::from_millis(2_500),
+    };
+    let mut runner = Runner::<Record<cowboy::Cowboy<_, _, _, _>, _>>::new(&p, &mut ips, &mut ops);
+
+    // Two rounds, the players press at fixed times whatever the hold
+    for ms in (0..20_000).step_by(100) {
+        chrono.set(ms);
+        let round_ms = ms % 10_000;
+        left_button.set(PinState::from(!(4_200..4_400).contains(&round_ms)));
+        right_button.set(PinState::from(!(4_700..4_900).contains(&round_ms)));
+        runner.step();
+    }
+
+    let (mut outputs, state) = (String::new(), runner.state());
+    let replayed = trace::replay(
+        cowboy::State::new(Instant::from_millis(0)),
+        |s, inputs| cowboy::advance(&p, s, inputs),
+        &trace.output(),
+        &mut outputs,
+    )
+    .expect("the recorded trace should replay");
+    assert_eq!(
+        (state.signal, state.players),
+        (replayed.signal, replayed.players),
+        "the replay should draw the same holds"
+    );
+}
+
+#[test]
+fn test_cowboy_fault() {
+    let chrono = ManualChrono::new(0);
+    let (left_led, right_led) = (RecordingPin::new(), RecordingPin::new());
+    let mut ips = cowboy::InputPeripherals {
+        chrono: chrono.clone(),
+        left_button: Polled(ScriptedPin::released()),
+        right_button: Polled(ScriptedPin::released()),
+        rng: SmallRng::seed_from_u64(974),
+    };
+    let mut ops = cowboy::OutputPeripherals {
+        left_led: left_led.clone(),
+        right_led: right_led.clone(),
+        serial: SerialSink::new(),
+    };
+    let p = cowboy::Parameters {
+        nb_blinks: 3,
+        blink_duration: Duration::from_millis(3_000),
+        show_winner_duration: Duration::from_millis(5_000),
+        min_hold: Duration::ZERO,
+        max_hold: Duration::ZERO,
+    };
+    let mut runner = Runner::<cowboy::Cowboy<_, _, _, _>>::new(&p, &mut ips, &mut ops);
+
+    // The leds are on in the second half of a blink
+    chrono.set(900);
+    runner.step();
+    assert!(left_led.is_set_high(), "the left led should blink");
+    right_led.disconnect();
+    runner.step();
+    assert_eq!(
+        Some(&Fault::Output(cowboy::OutputError::Leds(Disconnected))),
+        runner.fault(),
+        "the right led should fault"
+    );
+    assert_eq!(
+        Some(PinState::Low),
+        left_led.state(),
+        "the left led should be turned off"
+    );
+    let nb_writes = left_led.history().len();
+    runner.run_for(3);
+    assert_eq!(
+        nb_writes,
+        left_led.history().len(),
+        "the exercise should be stopped"
+    );
+}
+
+#[test]
+fn test_debounce() {
+    let chrono = ManualChrono::new(0);
+    let left_button = ScriptedPin::released();
+    let serial = SerialSink::new();
+    let mut ips = debounce::InputPeripherals {
+        chrono: chrono.clone(),
+        left_button: left_button.clone(),
+        right_button: ScriptedPin::released(),
+    };
+    let mut ops = debounce::OutputPeripherals {
+        serial: serial.clone(),
+    };
+    let p = debounce::Parameters {
+        low_pass_params: exercises::low_pass::FixedLowPassParams {
+            lambda: U1F15::lit("1e-2"),
+        },
+        threshold: U1F15::lit("95e-2"),
+    };
+    let mut runner = Runner::<debounce::Debounce<_, _, _>>::new(&p, &mut ips, &mut ops);
+
+    runner.step();
+    assert_eq!(1, serial.frames().len(), "a frame should be sent per loop");
+    assert_eq!(
+        vec![Value::U1F15(U1F15::ZERO), Value::U32(0), Value::U32(0)],
+        last_values(&serial),
+        "wrong telemetry"
+    );
+    // A 10ms glitch is filtered out
+    left_button.press();
+    chrono.set(10);
+    runner.step();
+    left_button.release();
+    chrono.set(20);
+    runner.step();
+    // A long press is counted once
+    left_button.press();
+    for ms in (30..1_000).step_by(10) {
+        chrono.set(ms);
+        runner.step();
+    }
+    let values = last_values(&serial);
+    assert_eq!(
+        [Value::U32(1), Value::U32(0)],
+        values[1..],
+        "wrong presses: {values:?}"
+    );
+}
+
+#[test]
+fn test_debounce_console() {
+    let serial = SerialSink::new();
+    let mut ips = debounce::InputPeripherals {
+        chrono: ManualChrono::new(0),
+        left_button: ScriptedPin::released(),
+        right_button: ScriptedPin::released(),
+    };
+    let mut ops = debounce::OutputPeripherals {
+        serial: serial.clone(),
+    };
+    let p = debounce::Parameters {
+        low_pass_params: exercises::low_pass::FixedLowPassParams {
+            lambda: U1F15::lit("1e-2"),
+        },
+        threshold: U1F15::lit("95e-2"),
+    };
+    let mut runner = Runner::<debounce::Debounce<_, _, _>>::new(&p, &mut ips, &mut ops);
+    let mut console = Console::new();
+
+    // The command is typed in two parts, between two loops
+    serial.send("set thresh");
+    console.poll(&mut serial.clone(), runner.params_mut(), &mut NoStorage);
+    runner.step();
+    serial.send("old 0.5\n");
+    console.poll(&mut serial.clone(), runner.params_mut(), &mut NoStorage);
+    runner.step();
+    assert_eq!(
+        U1F15::lit("0.5"),
+        runner.params().threshold,
+        "the threshold should be tuned"
+    );
+    assert!(
+        serial.output().contains("ok\n"),
+        "the command should be acknowledged"
+    );
+    // The telemetry frame after the reply is intact
+    assert_eq!(
+        vec![Value::U1F15(U1F15::ZERO), Value::U32(0), Value::U32(0)],
+        last_values(&serial),
+        "wrong telemetry"
+    );
+}
+
+#[test]
+fn test_dice() {
+    let chrono = ManualChrono::new(0);
+    let button = ScriptedPin::released();
+    let leds: [RecordingPin; 5] = Default::default();
+    let mut ips = dice::InputPeripherals {
+        chrono: chrono.clone(),
+        button: button.clone(),
+        rng: SmallRng::seed_from_u64(974),
+    };
+    let mut ops = dice::OutputPeripherals { leds: leds.clone() };
+    let p = dice::Parameters {
+        nb_blinks: 3,
+        first_blink_duration: Duration::from_millis(100),
+        last_blink_duration: Duration::from_millis(300),
+    };
+    let mut runner = Runner::<dice::Dice<_, _, _>>::new(&p, &mut ips, &mut ops);
+
+    runner.step();
+    assert!(
+        matches!(runner.state(), dice::State::ShowingResult(_)),
+        "the dice should wait for the button"
+    );
+    chrono.set(10);
+    button.push([PinState::Low, PinState::High]);
+    runner.step();
+    assert!(
+        matches!(
+            runner.state(),
+            dice::State::Blinking(0, _, t)
+                if t.remaining(Instant::from_millis(10)) == Duration::from_millis(100)
+        ),
+        "the dice should start blinking"
+    );
+    for ms in [111, 300, 600, 1_000] {
+        chrono.set(ms);
+        runner.step();
+    }
+    let dice::State::ShowingResult(roll) = runner.state() else {
+        panic!("the dice should show its result");
+    };
+    // A 6 can't be shown with 5 leds, they are all turned off
+    let expected = match roll.to_u8() {
+        6 => 0,
+        n => n as usize,
+    };
+    let nb_lit = lit(&leds).into_iter().filter(|on| *on).count();
+    assert_eq!(expected, nb_lit, "wrong leds for {}", roll.to_u8());
+}
+
+#[test]
+fn test_dice_console() {
+    let chrono = ManualChrono::new(0);
+    let button = ScriptedPin::released();
+    let serial = SerialSink::new();
+    let mut ips = dice::InputPeripherals {
+        chrono: chrono.clone(),
+        button: button.clone(),
+        rng: SmallRng::seed_from_u64(974),
+    };
+    let mut ops = dice::OutputPeripherals {
+        leds: RecordingLeds::<5>::new(),
+    };
+    let p = dice::Parameters {
+        nb_blinks: 3,
+        first_blink_duration: Duration::from_millis(1),
+        last_blink_duration: Duration::from_millis(1),
+    };
+    let mut runner = Runner::<dice::Dice<_, _, _>>::new(&p, &mut ips, &mut ops);
+    let mut console = Console::new();
+
+    serial.send("set nb_blinks 255\n");
+    console.poll(&mut serial.clone(), runner.params_mut(), &mut NoStorage);
+    assert_eq!(255, runner.params().nb_blinks, "nb_blinks should be tuned");
+    button.push([PinState::Low, PinState::High]);
+    runner.step();
+
+    // The last blink is the 255th, then the result is shown
+    for blink in 1..=255 {
+        chrono.set(10 * blink);
+        runner.step();
+        assert!(
+            matches!(runner.state(), dice::State::Blinking(n, _, _) if *n as u32 == blink),
+            "the dice should be at the blink {blink}"
+        );
+    }
+    chrono.set(2_560);
+    runner.step();
+    assert!(
+        matches!(runner.state(), dice::State::ShowingResult(_)),
+        "the dice should show its result after the last blink"
+    );
+}
+
+#[test]
+fn test_vumetre() {
+    let adc = MockAdc::new(U1F15::lit("7e-1"));
+    let leds: [RecordingPin; 8] = Default::default();
+    let serial = SerialSink::new();
+    let mut ips = vumetre::InputPeripherals {
+        adc: adc.clone(),
+        pin: (),
+    };
+    let mut ops = vumetre::OutputPeripherals {
+        ps: leds.clone(),
+        serial: serial.clone(),
+    };
+    let mut runner = Runner::<vumetre::Vumetre<_, _, _, _>>::new(&(), &mut ips, &mut ops);
+
+    runner.step();
+    let expected = vec![true, true, true, true, true, true, false, false];
+    assert_eq!(expected, lit(&leds), "wrong leds");
+    // The last level is kept while the adc is busy
+    adc.set_level(U1F15::ZERO);
+    adc.block(2);
+    runner.run_for(2);
+    assert_eq!(expected, lit(&leds), "the leds should not move");
+    runner.step();
+    assert_eq!(vec![false; 8], lit(&leds), "the leds should be off");
+    assert_eq!(4, adc.reads(), "the adc should be read on each loop");
+    assert_eq!(
+        vec![Value::U1F15(U1F15::ZERO), Value::U8(0), Value::U16(0)],
+        last_values(&serial),
+        "wrong telemetry"
+    );
+}
+
+#[test]
+fn test_vumetre_shift_register() {
+    let adc = MockAdc::new(U1F15::lit("7e-1"));
+    // 16 leds on 2 daisy-chained chips
+    let chain = MockShiftRegister::new(2);
+    let [data, clock, latch] = chain.pins();
+    let register = ShiftRegister::<_, 2>::new(BitBang { data, clock, latch });
+    let mut ips = vumetre::InputPeripherals {
+        adc: adc.clone(),
+        pin: (),
+    };
+    let mut ops = vumetre::OutputPeripherals {
+        ps: register.pins::<16>(),
+        serial: SerialSink::new(),
+    };
+    let mut runner = Runner::<vumetre::Vumetre<_, _, _, _, 16>>::new(&(), &mut ips, &mut ops);
+
+    runner.step();
+    let expected: Vec<bool> = (0..16).map(|idx| idx < 11).collect();
+    assert_eq!(expected, chain.outputs(), "wrong leds");
+    assert_eq!([0xff, 0x07], register.outputs(), "wrong shadow outputs");
+    // The chain is only shifted out when a led changes
+    let nb_latches = chain.nb_latches();
+    runner.step();
+    assert_eq!(
+        nb_latches,
+        chain.nb_latches(),
+        "the chain should not be written"
+    );
+    adc.set_level(U1F15::ZERO);
+    runner.step();
+    assert_eq!(vec![false; 16], chain.outputs(), "the leds should be off");
+}
+
+#[test]
+fn test_wiper() {
+    let chrono = ManualChrono::new(0);
+    let button = ScriptedPin::released();
+    // 1us per duty step, on a 20ms period
+    let servo = RecordingPwm::new(20_000);
+    let serial = SerialSink::new();
+    let mut ips = wiper::InputPeripherals {
+        chrono: chrono.clone(),
+        button: button.clone(),
+    };
+    let mut ops = wiper::OutputPeripherals {
+        servo: servo.clone(),
+        serial: serial.clone(),
+    };
+    let p = wiper::Parameters {
+        absolute_speed: U1F15::lit("0.7853981633"),
+        min_position: I3F13::lit("-1.57079632679"),
+        min_position_us: 500,
+        max_position: I3F13::lit("1.57079632679"),
+        max_position_us: 2500,
+        pwm_period_us: 20_000,
+    };
+    let mut runner = Runner::<wiper::Wiper<_, _, _, _>>::new(&p, &mut ips, &mut ops);
+
+    runner.step();
+    assert_eq!(Some(500), servo.duty(), "the wiper should start at the min");
+    // Half a turn takes 4s, so it reaches the middle after 2s
+    button.press();
+    for ms in (100..=2_000).step_by(100) {
+        chrono.set(ms);
+        runner.step();
+    }
+    let duty = servo.duty().unwrap();
+    assert!(
+        duty.abs_diff(1_500) < 10,
+        "wrong duty in the middle: {duty}"
+    );
+    let duties = servo.duties();
+    assert!(
+        duties.windows(2).all(|w| w[0] <= w[1]),
+        "the wiper should only move forward: {duties:?}"
+    );
+    assert_eq!(
+        servo.duties().len(),
+        serial.frames().len(),
+        "a frame should be sent per loop"
+    );
+}
@@ -0,0 +1,302 @@
+//! Runs the exercises on a computer, in a terminal.
+//!
+//! The peripherals of the uno are replaced by the keyboard and the screen,
+//! the exercises themselves are the same code as on the arduino.
+//!
+//! Usage: `cargo run -p simulator -- <exercise>`
+
+mod peripherals;
+mod screen;
+
+use std::io;
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use exercises::button::Polled;
+use exercises::chrono::Duration;
+use exercises::exercise::Runner;
+use exercises::led_bank::Dimmed;
+use exercises::low_pass::FixedLowPassParams;
+use fixed::types::{I3F13, U1F15};
+use rand::{rngs::SmallRng, SeedableRng};
+
+use peripherals::{Keyboard, Led, Serial, Servo, SimChrono};
+use screen::Terminal;
+
+// The parameters are the same as the ones of the uno binaries
+
+const CHENILLARD: exercises::chenillard::Parameters = exercises::chenillard::Parameters {
+    period: Duration::from_millis(1000),
+    pattern: exercises::pattern::Builtin::Walk,
+    trail: 3,
+};
+
+const COWBOY: exercises::cowboy::Parameters = exercises::cowboy::Parameters {
+    nb_blinks: 3,
+    blink_duration: Duration::from_millis(3_000),
+    show_winner_duration: Duration::from_millis(5_000),
+    // The start signal comes 0.5 to 2.5s after the blinking
+    min_hold: Duration::from_millis(500),
+    max_hold: Duration::from_millis(2_500),
+};
+
+const DEBOUNCE: exercises::debounce::Parameters = exercises::debounce::Parameters {
+    low_pass_params: FixedLowPassParams {
+        lambda: U1F15::lit("1e-2"),
+    },
+    threshold: U1F15::lit("95e-2"),
+};
+
+const DICE: exercises::dice::Parameters = exercises::dice::Parameters {
+    first_blink_duration: Duration::from_millis(100),
+    last_blink_duration: Duration::from_millis(400),
+    nb_blinks: 10,
+};
+
+// 50Hz => 20_000us
+const PWM_PERIOD_US: u16 = 20_000;
+
+const WIPER: exercises::wiper::Parameters = exercises::wiper::Parameters {
+    absolute_speed: U1F15::lit("0.7853981633"),
+    min_position: I3F13::lit("-1.57079632679"),
+    min_position_us: 500,
+    max_position: I3F13::lit("1.57079632679"),
+    max_position_us: 2500,
+    pwm_period_us: PWM_PERIOD_US as u32,
+};
+
+const EXERCISES: [&str; 7] = [
+    "chenillard",
+    "cowboy",
+    "debounce",
+    "dice",
+    "sequencer",
+    "vumetre",
+    "wiper",
+];
+
+fn main() -> ExitCode {
+    let name = std::env::args().nth(1).unwrap_or_default();
+    if !EXERCISES.contains(&name.as_str()) {
+        eprintln!("usage: simulator <{}>", EXERCISES.join("|"));
+        return ExitCode::FAILURE;
+    }
+    let result = Terminal::start().and_then(|mut terminal| match name.as_str() {
+        "chenillard" => chenillard(&mut terminal),
+        "cowboy" => cowboy(&mut terminal),
+        "debounce" => debounce(&mut terminal),
+        "dice" => dice(&mut terminal),
+        "sequencer" => sequencer(&mut terminal),
+        "vumetre" => vumetre(&mut terminal),
+        _ => wiper(&mut terminal),
+    });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("terminal error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn chenillard(terminal: &mut Terminal) -> io::Result<()> {
+    let leds: [Led; 8] = Default::default();
+    let keyboard = Keyboard::default();
+    let slider = keyboard.slider();
+    // Start at full speed
+    slider.set_level(U1F15::ONE);
+    let mut ips = exercises::chenillard::InputPeripherals {
+        chrono: SimChrono::new(),
+        button: keyboard.button(' '),
+        faster: keyboard.button('f'),
+        slower: keyboard.button('s'),
+        reverse: keyboard.button('r'),
+        adc: keyboard.slider(),
+        pin: (),
+    };
+    let mut ops = exercises::chenillard::OutputPeripherals {
+        ps: Dimmed(leds.clone()),
+    };
+    let mut runner = Runner::<exercises::chenillard::Chenillard<_, _, _, _, _>>::new(
+        &CHENILLARD,
+        &mut ips,
+        &mut ops,
+    );
+    terminal.simulate(
+        &mut runner,
+        &keyboard,
+        "chenillard: press space for the next pattern\n\
+         f and s for faster and slower, r to reverse, up and down arrows for the speed",
+        || {
+            format!(
+                "speed: {}\n{}",
+                screen::gauge(slider.level().to_num(), 0.0, 1.0),
+                screen::levels(&leds.each_ref().map(Led::level))
+            )
+        },
+    )
+}
+
+// seed changes the rolls of each run
+fn seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+fn cowboy(terminal: &mut Terminal) -> io::Result<()> {
+    let keyboard = Keyboard::default();
+    let (left_led, right_led) = (Led::default(), Led::default());
+    let mut ips = exercises::cowboy::InputPeripherals {
+        chrono: SimChrono::new(),
+        left_button: Polled(keyboard.button('a')),
+        right_button: Polled(keyboard.button('l')),
+        rng: SmallRng::seed_from_u64(seed()),
+    };
+    let serial = Serial::default();
+    let mut ops = exercises::cowboy::OutputPeripherals {
+        left_led: left_led.clone(),
+        right_led: right_led.clone(),
+        serial: serial.clone(),
+    };
+    let mut runner =
+        Runner::<exercises::cowboy::Cowboy<_, _, _, _>>::new(&COWBOY, &mut ips, &mut ops);
+    terminal.simulate(
+        &mut runner,
+        &keyboard,
+        "cowboy: the left player presses a, the right player presses l\n\
+         wait for the leds to turn off, then be the fastest",
+        || {
+            format!(
+                "{}\n{}",
+                screen::leds(&[left_led.is_on(), right_led.is_on()]),
+                serial.last_message()
+            )
+        },
+    )
+}
+
+fn debounce(terminal: &mut Terminal) -> io::Result<()> {
+    let keyboard = Keyboard::default();
+    let serial = Serial::default();
+    let mut ips = exercises::debounce::InputPeripherals {
+        chrono: SimChrono::new(),
+        left_button: keyboard.button('a'),
+        right_button: keyboard.button('l'),
+    };
+    let mut ops = exercises::debounce::OutputPeripherals {
+        serial: serial.clone(),
+    };
+    let mut runner =
+        Runner::<exercises::debounce::Debounce<_, _, _>>::new(&DEBOUNCE, &mut ips, &mut ops);
+    terminal.simulate(
+        &mut runner,
+        &keyboard,
+        "debounce: press a (debounced left button) and l (raw right button)",
+        || format!("serial: {}", serial.last_message()),
+    )
+}
+
+fn dice(terminal: &mut Terminal) -> io::Result<()> {
+    let keyboard = Keyboard::default();
+    let leds: [Led; 5] = Default::default();
+    let mut ips = exercises::dice::InputPeripherals {
+        chrono: SimChrono::new(),
+        button: keyboard.button(' '),
+        rng: SmallRng::seed_from_u64(seed()),
+    };
+    let mut ops = exercises::dice::OutputPeripherals { leds: leds.clone() };
+    let mut runner = Runner::<exercises::dice::Dice<_, _, _>>::new(&DICE, &mut ips, &mut ops);
+    terminal.simulate(
+        &mut runner,
+        &keyboard,
+        "dice: press space to cast the dice",
+        || screen::dice(&leds.each_ref().map(Led::is_on)),
+    )
+}
+
+fn sequencer(terminal: &mut Terminal) -> io::Result<()> {
+    let leds: [Led; 8] = Default::default();
+    let keyboard = Keyboard::default();
+    let mut ips = exercises::sequencer::InputPeripherals {
+        chrono: SimChrono::new(),
+    };
+    let mut ops = exercises::chenillard::OutputPeripherals {
+        ps: Dimmed(leds.clone()),
+    };
+    let p = exercises::sequencer::Parameters {
+        program: &exercises::sequencer::DEMO[..],
+    };
+    let mut runner =
+        Runner::<exercises::sequencer::Sequencer<_, _, _>>::new(&p, &mut ips, &mut ops);
+    terminal.simulate(
+        &mut runner,
+        &keyboard,
+        "sequencer: the demo light show",
+        || screen::leds(&leds.each_ref().map(Led::is_on)),
+    )
+}
+
+fn vumetre(terminal: &mut Terminal) -> io::Result<()> {
+    let keyboard = Keyboard::default();
+    let leds: [Led; 8] = Default::default();
+    let serial = Serial::default();
+    let slider = keyboard.slider();
+    let mut ips = exercises::vumetre::InputPeripherals {
+        adc: keyboard.slider(),
+        pin: (),
+    };
+    let mut ops = exercises::vumetre::OutputPeripherals {
+        ps: leds.clone(),
+        serial: serial.clone(),
+    };
+    let mut runner =
+        Runner::<exercises::vumetre::Vumetre<_, _, _, _>>::new(&(), &mut ips, &mut ops);
+    terminal.simulate(
+        &mut runner,
+        &keyboard,
+        "vumetre: move the potentiometer with the up and down arrows",
+        || {
+            format!(
+                "potentiometer: {}\n{}\nserial: {}",
+                screen::gauge(slider.level().to_num(), 0.0, 1.0),
+                screen::leds(&leds.each_ref().map(Led::is_on)),
+                serial.last_message()
+            )
+        },
+    )
+}
+
+fn wiper(terminal: &mut Terminal) -> io::Result<()> {
+    let keyboard = Keyboard::default();
+    let servo = Servo::new(PWM_PERIOD_US);
+    let serial = Serial::default();
+    let mut ips = exercises::wiper::InputPeripherals {
+        chrono: SimChrono::new(),
+        button: keyboard.button(' '),
+    };
+    let mut ops = exercises::wiper::OutputPeripherals {
+        servo: servo.clone(),
+        serial: serial.clone(),
+    };
+    let mut runner = Runner::<exercises::wiper::Wiper<_, _, _, _>>::new(&WIPER, &mut ips, &mut ops);
+    terminal.simulate(
+        &mut runner,
+        &keyboard,
+        "wiper: hold space to move the wiper to the end, release it to bring it back",
+        || {
+            // Convert the servo pulse back to an angle in degrees
+            let t = (servo.pulse_us() as f32 - WIPER.min_position_us as f32)
+                / (WIPER.max_position_us - WIPER.min_position_us) as f32;
+            let angle = WIPER.min_position.to_num::<f32>()
+                + t * (WIPER.max_position - WIPER.min_position).to_num::<f32>();
+            let degrees = angle.to_degrees();
+            format!(
+                "angle: {} {:.0} deg\nserial: {}",
+                screen::gauge(degrees, -90.0, 90.0),
+                degrees,
+                serial.last_message()
+            )
+        },
+    )
+}
@@ -0,0 +1,312 @@
+//! This module contains std implementations of the peripherals used by the
+//! exercises. The simulated peripherals are cheap handles on a shared state:
+//! a clone is given to the exercise and another one is kept to draw the
+//! screen or to forward the key presses.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use embedded_hal::digital::{ErrorType, InputPin, OutputPin};
+use embedded_hal::pwm::SetDutyCycle;
+use exercises::telemetry;
+use fixed::types::U1F15;
+
+// The chrono counts the millis since its creation (or last reset) using the
+// clock of the computer
+pub struct SimChrono {
+    start: Cell<Instant>,
+}
+
+impl SimChrono {
+    pub fn new() -> SimChrono {
+        SimChrono {
+            start: Cell::new(Instant::now()),
+        }
+    }
+}
+
+impl exercises::chrono::Chrono for SimChrono {
+    fn now(&self) -> exercises::chrono::Instant {
+        // Truncating makes it roll over like the mcu counter
+        exercises::chrono::Instant::from_millis(self.start.get().elapsed().as_millis() as u32)
+    }
+
+    fn micros(&self) -> exercises::chrono::Instant<exercises::chrono::Micros> {
+        exercises::chrono::Instant::from_micros(self.start.get().elapsed().as_micros() as u32)
+    }
+
+    fn reset(&self) {
+        self.start.set(Instant::now())
+    }
+}
+
+// Most terminals only report key presses, not key releases. When holding a
+// key, the terminal repeats the press after a delay. A key is considered held
+// if it was pressed less than HOLD_DURATION ago, which must be longer than
+// the usual repeat delay.
+const HOLD_DURATION: Duration = Duration::from_millis(600);
+
+// The step of the slider on each arrow key press
+const SLIDER_STEP: U1F15 = U1F15::lit("0.03125");
+
+#[derive(Clone, Default)]
+pub struct Keyboard {
+    state: Rc<RefCell<KeyboardState>>,
+}
+
+#[derive(Default)]
+struct KeyboardState {
+    // The time of the last press (or repeat) of each key currently held
+    pressed: HashMap<KeyCode, Instant>,
+    // True once the terminal reported a key release: releases are trusted
+    // instead of HOLD_DURATION from then on
+    reports_releases: bool,
+    // The slider position, moved with the up and down arrows
+    level: U1F15,
+}
+
+impl Keyboard {
+    // button returns an input pin that is low (pressed) while the key is held
+    pub fn button(&self, key: char) -> KeyButton {
+        KeyButton {
+            keyboard: self.clone(),
+            key: KeyCode::Char(key),
+        }
+    }
+
+    // slider returns an adc controlled by the up and down arrows
+    pub fn slider(&self) -> Slider {
+        Slider {
+            keyboard: self.clone(),
+        }
+    }
+
+    pub fn handle(&self, event: KeyEvent) {
+        let mut state = self.state.borrow_mut();
+        let code = match event.code {
+            // Keep the buttons working with caps lock on
+            KeyCode::Char(c) => KeyCode::Char(c.to_ascii_lowercase()),
+            code => code,
+        };
+        match event.kind {
+            KeyEventKind::Release => {
+                state.reports_releases = true;
+                state.pressed.remove(&code);
+                return;
+            }
+            KeyEventKind::Press | KeyEventKind::Repeat => {
+                state.pressed.insert(code, Instant::now());
+            }
+        }
+        match code {
+            KeyCode::Up => state.level = state.level.saturating_add(SLIDER_STEP).min(U1F15::ONE),
+            KeyCode::Down => state.level = state.level.saturating_sub(SLIDER_STEP),
+            _ => {}
+        }
+    }
+
+    fn is_pressed(&self, key: KeyCode) -> bool {
+        let state = self.state.borrow();
+        match state.pressed.get(&key) {
+            Some(_) if state.reports_releases => true,
+            Some(t) => t.elapsed() < HOLD_DURATION,
+            None => false,
+        }
+    }
+}
+
+pub struct KeyButton {
+    keyboard: Keyboard,
+    key: KeyCode,
+}
+
+impl ErrorType for KeyButton {
+    type Error = Infallible;
+}
+
+// The buttons are wired with a pull up on the uno, so a pressed key is low
+impl InputPin for KeyButton {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.keyboard.is_pressed(self.key))
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(self.keyboard.is_pressed(self.key))
+    }
+}
+
+pub struct Slider {
+    keyboard: Keyboard,
+}
+
+impl Slider {
+    pub fn level(&self) -> U1F15 {
+        self.keyboard.state.borrow().level
+    }
+
+    // set_level moves the slider, e.g. to start at the max
+    pub fn set_level(&self, level: U1F15) {
+        self.keyboard.state.borrow_mut().level = level;
+    }
+}
+
+// The slider is always ready, it never returns WouldBlock
+impl exercises::adc::Adc<()> for Slider {
+    type Error = Infallible;
+
+    fn read_nonblocking(&mut self, _: &()) -> nb::Result<U1F15, Infallible> {
+        Ok(self.level())
+    }
+}
+
+// Led is a led that can also be dimmed, its brightness goes from 0 to 255
+#[derive(Clone, Default)]
+pub struct Led {
+    level: Rc<Cell<u8>>,
+}
+
+impl Led {
+    pub fn is_on(&self) -> bool {
+        self.level.get() > 0
+    }
+
+    pub fn level(&self) -> u8 {
+        self.level.get()
+    }
+}
+
+impl ErrorType for Led {
+    type Error = Infallible;
+}
+
+impl OutputPin for Led {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.level.set(0);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.level.set(u8::MAX);
+        Ok(())
+    }
+}
+
+impl embedded_hal::pwm::ErrorType for Led {
+    type Error = Infallible;
+}
+
+impl SetDutyCycle for Led {
+    fn max_duty_cycle(&self) -> u16 {
+        u8::MAX as u16
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Infallible> {
+        self.level.set(duty as u8);
+        Ok(())
+    }
+}
+
+// The servo pwm has a resolution of 1us, like the uno timer 1 with a
+// prescaler of 8
+#[derive(Clone)]
+pub struct Servo {
+    max_duty: u16,
+    duty: Rc<Cell<u16>>,
+}
+
+impl Servo {
+    pub fn new(period_us: u16) -> Servo {
+        Servo {
+            max_duty: period_us,
+            duty: Rc::new(Cell::new(0)),
+        }
+    }
+
+    // The duration of the pulse sent to the servo
+    pub fn pulse_us(&self) -> u16 {
+        self.duty.get()
+    }
+}
+
+impl embedded_hal::pwm::ErrorType for Servo {
+    type Error = Infallible;
+}
+
+impl SetDutyCycle for Servo {
+    fn max_duty_cycle(&self) -> u16 {
+        self.max_duty
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Infallible> {
+        self.duty.set(duty);
+        Ok(())
+    }
+}
+
+// The serial decodes the telemetry frames and keeps the last message, or
+// the last line of the text written to it
+#[derive(Clone, Default)]
+pub struct Serial {
+    state: Rc<RefCell<SerialState>>,
+}
+
+#[derive(Default)]
+struct SerialState {
+    current_frame: Vec<u8>,
+    current_line: String,
+    last_message: String,
+}
+
+impl Serial {
+    pub fn last_message(&self) -> String {
+        self.state.borrow().last_message.clone()
+    }
+}
+
+impl embedded_hal_nb::serial::ErrorType for Serial {
+    type Error = Infallible;
+}
+
+impl embedded_hal_nb::serial::Write<u8> for Serial {
+    fn write(&mut self, b: u8) -> nb::Result<(), Infallible> {
+        let mut state = self.state.borrow_mut();
+        if b != 0 {
+            state.current_frame.push(b);
+            return Ok(());
+        }
+        let frame = std::mem::take(&mut state.current_frame);
+        state.last_message = match telemetry::decode(&frame, &telemetry::SCHEMAS) {
+            Ok(message) => message
+                .fields()
+                .fold(message.schema.name.to_string(), |line, (name, value)| {
+                    format!("{line} {name}={value}")
+                }),
+            Err(e) => format!("invalid frame: {e:?}"),
+        };
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+impl ufmt::uWrite for Serial {
+    type Error = Infallible;
+
+    fn write_str(&mut self, s: &str) -> Result<(), Infallible> {
+        let mut state = self.state.borrow_mut();
+        for c in s.chars() {
+            if c == '\n' {
+                state.last_message = std::mem::take(&mut state.current_line);
+            } else {
+                state.current_line.push(c);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+//! This module contains the terminal handling: the simulation loop that
+//! forwards the keys to the exercise, and the drawing of the outputs.
+
+use std::io::{self, Stdout, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossterm::event::{
+    self, Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::{cursor, execute, queue, style, terminal};
+use exercises::exercise::{Exercise, Runner};
+
+use crate::peripherals::Keyboard;
+
+// The screen is redrawn at about 30 frames per second
+const FRAME_DURATION: Duration = Duration::from_millis(33);
+
+// Terminal puts the terminal in raw mode on creation and restores it when
+// dropped, even on error
+pub struct Terminal {
+    out: Stdout,
+    enhanced_keyboard: bool,
+}
+
+impl Terminal {
+    pub fn start() -> io::Result<Terminal> {
+        let mut out = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        // Ask for key release events when the terminal supports it
+        let enhanced_keyboard = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if enhanced_keyboard {
+            execute!(
+                out,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        Ok(Terminal {
+            out,
+            enhanced_keyboard,
+        })
+    }
+
+    // simulate runs the exercise until q, escape or ctrl-c is pressed.
+    // The help is shown above the outputs drawn by draw.
+    pub fn simulate<E: Exercise>(
+        &mut self,
+        runner: &mut Runner<E>,
+        keyboard: &Keyboard,
+        help: &str,
+        mut draw: impl FnMut() -> String,
+    ) -> io::Result<()> {
+        let mut last_frame: Option<Instant> = None;
+        loop {
+            while event::poll(Duration::ZERO)? {
+                if let Event::Key(key) = event::read()? {
+                    let quit = match key.code {
+                        KeyCode::Char('q') | KeyCode::Esc => true,
+                        KeyCode::Char('c') => key.modifiers.contains(KeyModifiers::CONTROL),
+                        _ => false,
+                    };
+                    if quit && key.kind == KeyEventKind::Press {
+                        return Ok(());
+                    }
+                    keyboard.handle(key);
+                }
+            }
+            runner.step();
+            if last_frame.is_none_or(|t| t.elapsed() >= FRAME_DURATION) {
+                last_frame = Some(Instant::now());
+                self.draw(help, &draw())?;
+            }
+            // The uno loop is not much faster than that
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn draw(&mut self, help: &str, outputs: &str) -> io::Result<()> {
+        queue!(
+            self.out,
+            cursor::MoveTo(0, 0),
+            terminal::Clear(terminal::ClearType::All)
+        )?;
+        // Raw mode needs explicit carriage returns
+        for line in help.lines().chain(["", "press q to quit", ""]) {
+            queue!(self.out, style::Print(line), style::Print("\r\n"))?;
+        }
+        for line in outputs.lines() {
+            queue!(self.out, style::Print(line), style::Print("\r\n"))?;
+        }
+        self.out.flush()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if self.enhanced_keyboard {
+            let _ = execute!(self.out, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(self.out, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// leds draws a row of leds
+pub fn leds(states: &[bool]) -> String {
+    states
+        .iter()
+        .map(|on| if *on { "●" } else { "○" })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// levels draws a row of dimmed leds, the more filled the brighter
+pub fn levels(levels: &[u8]) -> String {
+    const SHADES: [&str; 5] = ["○", "◔", "◑", "◕", "●"];
+    levels
+        .iter()
+        .map(|level| SHADES[(*level as usize * (SHADES.len() - 1)).div_ceil(255)])
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// dice draws the 5 leds of the dice in their cross layout
+pub fn dice(states: &[bool; 5]) -> String {
+    let led = |i: usize| if states[i] { "●" } else { "○" };
+    format!(
+        "{}   {}\n  {}\n{}   {}",
+        led(0),
+        led(1),
+        led(2),
+        led(3),
+        led(4)
+    )
+}
+
+// gauge draws a value of [min; max] as a cursor on a horizontal line
+pub fn gauge(value: f32, min: f32, max: f32) -> String {
+    const WIDTH: usize = 37;
+    let t = ((value - min) / (max - min)).clamp(0.0, 1.0);
+    let position = (t * (WIDTH - 1) as f32).round() as usize;
+    (0..WIDTH)
+        .map(|i| if i == position { '|' } else { '-' })
+        .collect()
+}
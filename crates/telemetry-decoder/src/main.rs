@@ -0,0 +1,158 @@
+//! Decodes the telemetry frames sent by the exercises into CSV.
+//!
+//! Capture the serial port into a file (e.g. `cat /dev/ttyACM0 > capture`)
+//! then decode it, or decode the serial port directly:
+//!
+//! Usage: `cargo run -p telemetry-decoder -- [capture]`
+//!
+//! The stream is read from stdin without a file. A header line is printed
+//! before the first message, then again each time the kind of the messages
+//! changes. The invalid frames (e.g. the partial frame at the start of a
+//! capture) are dropped and counted on stderr.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::process::ExitCode;
+
+use exercises::telemetry::{self, Schema, SCHEMAS};
+
+// Decoder splits the stream on the 0 bytes and writes the frames as CSV
+#[derive(Default)]
+struct Decoder {
+    current_frame: Vec<u8>,
+    // The kind of the last message, to know when to print a header
+    last_kind: Option<u8>,
+    dropped: usize,
+}
+
+impl Decoder {
+    fn feed(&mut self, bytes: &[u8], out: &mut impl Write) -> io::Result<()> {
+        for b in bytes {
+            if *b != 0 {
+                self.current_frame.push(*b);
+                continue;
+            }
+            let frame = std::mem::take(&mut self.current_frame);
+            let message = match telemetry::decode(&frame, &SCHEMAS) {
+                Ok(message) => message,
+                Err(_) => {
+                    self.dropped += 1;
+                    continue;
+                }
+            };
+            if self.last_kind != Some(message.schema.kind) {
+                write_header(message.schema, out)?;
+                self.last_kind = Some(message.schema.kind);
+            }
+            write!(out, "{}", message.schema.name)?;
+            for (_, value) in message.fields() {
+                write!(out, ",{value}")?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_header(schema: &Schema, out: &mut impl Write) -> io::Result<()> {
+    write!(out, "kind")?;
+    for (name, _) in schema.fields {
+        write!(out, ",{name}")?;
+    }
+    writeln!(out)
+}
+
+fn decode(input: &mut impl Read) -> io::Result<usize> {
+    let mut decoder = Decoder::default();
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut buf = [0; 1024];
+    loop {
+        let n = input.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        decoder.feed(&buf[..n], &mut out)?;
+        // Show the messages as they come when reading a serial port
+        out.flush()?;
+    }
+    Ok(decoder.dropped)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.as_slice() {
+        [] => decode(&mut io::stdin().lock()),
+        [path] => File::open(path).and_then(|mut f| decode(&mut f)),
+        _ => {
+            eprintln!("usage: telemetry-decoder [capture]");
+            return ExitCode::FAILURE;
+        }
+    };
+    match result {
+        Ok(0) => ExitCode::SUCCESS,
+        Ok(dropped) => {
+            eprintln!("{dropped} invalid frames dropped");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exercises::{vumetre, wiper};
+    use fixed::types::{I3F13, U1F15};
+
+    // Stream is a serial port writing to a Vec
+    struct Stream(Vec<u8>);
+
+    impl embedded_hal_nb::serial::ErrorType for Stream {
+        type Error = std::convert::Infallible;
+    }
+
+    impl embedded_hal_nb::serial::Write<u8> for Stream {
+        fn write(&mut self, b: u8) -> nb::Result<(), Self::Error> {
+            self.0.push(b);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_feed() {
+        let mut stream = Stream(Vec::new());
+        let position = wiper::Outputs {
+            position: I3F13::ZERO,
+            button: true,
+        };
+        telemetry::send(&mut stream, &position).unwrap();
+        telemetry::send(&mut stream, &position).unwrap();
+        let level = vumetre::Outputs::<8> {
+            adc_value: U1F15::lit("0.5"),
+            nb_lebs: 4,
+            leds: 0x0F,
+        };
+        telemetry::send(&mut stream, &level).unwrap();
+        // The capture starts in the middle of a frame and loses a byte
+        let bytes = &stream.0[3..];
+        let mut decoder = Decoder::default();
+        let mut out = Vec::new();
+        // Split the stream to check a frame can span two reads
+        let (first, second) = bytes.split_at(bytes.len() / 2);
+        decoder.feed(first, &mut out).unwrap();
+        decoder.feed(second, &mut out).unwrap();
+        let expected = "kind,position,button\n\
+                        wiper,0,1\n\
+                        kind,adc_value,nb_leds,leds\n\
+                        vumetre,0.5,4,15\n";
+        assert_eq!(expected, String::from_utf8(out).unwrap(), "wrong csv");
+        assert_eq!(1, decoder.dropped, "the partial frame should be dropped");
+    }
+}
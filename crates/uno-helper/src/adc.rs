@@ -0,0 +1,29 @@
+//! This module reads the adc of the uno as an exercises::adc::Adc, e.g. for
+//! the potentiometer of the vumetre or the speed of the chenillard.
+
+use arduino_hal::{adc::AdcChannel, hal::Atmega};
+use fixed::{traits::ToFixed, types::U10F6};
+
+// The uno adc values go up to 1023 (10-bit ADC).
+const ADC_MAX: U10F6 = U10F6::lit("1023");
+
+pub struct AdcWrapper(pub arduino_hal::adc::Adc);
+
+// Implements Adc trait we declared in this repo to convert adc values in [0, 1023]
+// to a "device independant" value on [0, 1], using a fixed point number
+// The read from the ADC is non blocking. If the ADC is not ready, we get a "WouldBlock"
+// error token
+impl<PIN> exercises::adc::Adc<PIN> for AdcWrapper
+where
+    PIN: AdcChannel<Atmega, arduino_hal::pac::ADC>,
+{
+    type Error = core::convert::Infallible;
+
+    fn read_nonblocking(
+        &mut self,
+        pin: &PIN,
+    ) -> nb::Result<fixed::types::U1F15, core::convert::Infallible> {
+        let res = self.0.read_nonblocking(pin);
+        res.map(|x| (U10F6::from_num(x).wide_div(ADC_MAX)).to_fixed())
+    }
+}
@@ -0,0 +1,51 @@
+//! This module stores the params of the exercises in the EEPROM of the uno,
+//! with the block format of exercises::persist.
+
+use exercises::persist::Storage;
+
+pub struct EepromStorage {
+    eeprom: arduino_hal::Eeprom,
+    // The address of the block, the rest of the EEPROM is free for other uses
+    address: u16,
+}
+
+impl EepromStorage {
+    pub fn new(eeprom: arduino_hal::Eeprom) -> EepromStorage {
+        EepromStorage::at(eeprom, 0)
+    }
+
+    pub fn at(eeprom: arduino_hal::Eeprom, address: u16) -> EepromStorage {
+        EepromStorage { eeprom, address }
+    }
+
+    // The address of each byte of a block, None if it doesn't fit
+    fn addresses(&self, len: usize) -> Option<core::ops::Range<u16>> {
+        let end = self.address.checked_add(u16::try_from(len).ok()?)?;
+        (end <= self.eeprom.capacity()).then_some(self.address..end)
+    }
+}
+
+// The only error is a block that doesn't fit in the EEPROM
+impl Storage for EepromStorage {
+    type Error = ();
+
+    fn read(&mut self, block: &mut [u8]) -> Result<(), ()> {
+        let addresses = self.addresses(block.len()).ok_or(())?;
+        for (b, address) in block.iter_mut().zip(addresses) {
+            *b = self.eeprom.read_byte(address);
+        }
+        Ok(())
+    }
+
+    // Only the bytes that changed are written, an EEPROM cell wears out after
+    // ~100_000 writes
+    fn write(&mut self, block: &[u8]) -> Result<(), ()> {
+        let addresses = self.addresses(block.len()).ok_or(())?;
+        for (b, address) in block.iter().zip(addresses) {
+            if self.eeprom.read_byte(address) != *b {
+                self.eeprom.write_byte(address, *b);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,22 @@
+//! This module reads the programs of the sequencer from the flash of the uno.
+//! A static is copied to the 2kB of RAM at the start, unless it is declared
+//! with avr_progmem::progmem!: it then stays in the 32kB of flash and is read
+//! byte by byte.
+
+use avr_progmem::wrapper::ProgMem;
+use exercises::sequencer::Program;
+
+// FlashProgram is a program declared with progmem!, e.g.
+// progmem! { static progmem SHOW: [u8; DEMO.len()] = DEMO; }
+#[derive(Clone, Copy)]
+pub struct FlashProgram<const N: usize>(pub &'static ProgMem<[u8; N]>);
+
+impl<const N: usize> Program for FlashProgram<N> {
+    fn len(&self) -> usize {
+        N
+    }
+
+    fn byte(&self, idx: usize) -> u8 {
+        self.0.load_at(idx)
+    }
+}
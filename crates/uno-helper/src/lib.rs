@@ -1,5 +1,12 @@
 #![no_std]
 #![feature(abi_avr_interrupt)]
 
+pub mod adc;
+pub mod eeprom;
+pub mod flash;
+pub mod pcint;
 pub mod precise_pwm;
+pub mod serial;
+pub mod sleep;
+pub mod soft_pwm;
 pub mod timer_0;
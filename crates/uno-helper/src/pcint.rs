@@ -0,0 +1,178 @@
+//! This module reads push buttons with the pin change interrupts: every edge
+//! is timestamped with the millis of the timer 0 by the interrupt, then
+//! queued until the loop reads the button (see exercises::button). A press
+//! is timed to the tick of the timer 0 whatever the duration of the loop, and
+//! a press shorter than a loop is not missed.
+//!
+//! The pin change interrupts also wake the uno up from its deep sleep, see the
+//! sleep module.
+//!
+//! You need to enable interrupts for the edges to be recorded
+
+use core::cell::{Cell, RefCell};
+use core::convert::Infallible;
+
+use arduino_hal::port::{mode, Pin};
+use avr_device::interrupt::{CriticalSection, Mutex};
+use embedded_hal::digital::{ErrorType, InputPin};
+use exercises::button::{Button, Edge, Presses};
+use exercises::chrono::Instant;
+use uno_lib::ring_buffer::RingBuffer;
+
+use crate::timer_0;
+
+// The number of PcintButton
+const MAX_BUTTONS: usize = 4;
+
+// The edges of a button between two loops, the newest are dropped when it is
+// full: the first press is kept
+const MAX_EDGES: usize = 8;
+
+const NO_EDGE: Edge = Edge {
+    pressed: false,
+    at: Instant::from_millis(0),
+};
+
+type Edges = RingBuffer<Edge, MAX_EDGES>;
+
+#[derive(Clone, Copy)]
+struct Slot {
+    pcint: u8,
+    edges: Edges,
+}
+
+static SLOTS: Mutex<RefCell<[Option<Slot>; MAX_BUTTONS]>> =
+    Mutex::new(RefCell::new([None; MAX_BUTTONS]));
+
+// The last levels of the pins of the ports B, C and D, to find the pins that
+// changed
+static LEVELS: Mutex<Cell<[u8; 3]>> = Mutex::new(Cell::new([0; 3]));
+
+// enable enables the pin change interrupt PCINT<pcint>, see the pinout of the
+// ATmega328P: e.g. D11 is PB3 so PCINT3, A0 is PC0 so PCINT8 and D2 is PD2
+// so PCINT18
+pub fn enable(pcint: u8) {
+    let exint = unsafe { &*arduino_hal::pac::EXINT::ptr() };
+    let mask = 1 << (pcint % 8);
+    avr_device::interrupt::free(|cs| {
+        let port = pcint / 8;
+        match port {
+            0 => exint.pcmsk0.modify(|r, w| w.bits(r.bits() | mask)),
+            1 => exint.pcmsk1.modify(|r, w| w.bits(r.bits() | mask)),
+            2 => exint.pcmsk2.modify(|r, w| w.bits(r.bits() | mask)),
+            _ => panic!(),
+        }
+        let mut levels = LEVELS.borrow(cs).get();
+        levels[port as usize] = read_port(port);
+        LEVELS.borrow(cs).set(levels);
+        exint.pcicr.modify(|r, w| w.bits(r.bits() | 1 << port));
+    })
+}
+
+// is_enabled is true if a pin change interrupt can wake the uno up
+pub fn is_enabled() -> bool {
+    let exint = unsafe { &*arduino_hal::pac::EXINT::ptr() };
+    exint.pcicr.read().bits() != 0
+}
+
+fn read_port(port: u8) -> u8 {
+    unsafe {
+        match port {
+            0 => (*arduino_hal::pac::PORTB::ptr()).pinb.read().bits(),
+            1 => (*arduino_hal::pac::PORTC::ptr()).pinc.read().bits(),
+            _ => (*arduino_hal::pac::PORTD::ptr()).pind.read().bits(),
+        }
+    }
+}
+
+// PcintButton is a button with a pull up, pressed when low, read with its pin
+// change interrupt. It can also be read as a plain InputPin.
+pub struct PcintButton {
+    pin: Pin<mode::Input<mode::PullUp>>,
+    slot: usize,
+    presses: Presses,
+}
+
+impl PcintButton {
+    // new reads pin with the interrupt PCINT<pcint>, see enable for the
+    // numbers. It panics after MAX_BUTTONS buttons.
+    pub fn new(pin: Pin<mode::Input<mode::PullUp>>, pcint: u8) -> PcintButton {
+        let slot = avr_device::interrupt::free(|cs| {
+            let mut slots = SLOTS.borrow(cs).borrow_mut();
+            let slot = slots.iter().position(Option::is_none).unwrap();
+            slots[slot] = Some(Slot {
+                pcint,
+                edges: Edges::new(NO_EDGE),
+            });
+            slot
+        });
+        enable(pcint);
+        PcintButton {
+            pin,
+            slot,
+            presses: Presses::new(),
+        }
+    }
+}
+
+impl ErrorType for PcintButton {
+    type Error = Infallible;
+}
+
+impl InputPin for PcintButton {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(self.pin.is_high())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(self.pin.is_low())
+    }
+}
+
+impl Button for PcintButton {
+    // The presses are timed by the interrupt, now is not needed
+    fn press(&mut self, _: Instant) -> Result<Option<Instant>, Infallible> {
+        // The edges are taken at once to keep the interrupts disabled briefly
+        let mut edges = avr_device::interrupt::free(|cs| {
+            let mut slots = SLOTS.borrow(cs).borrow_mut();
+            let slot = slots[self.slot].as_mut().unwrap();
+            core::mem::replace(&mut slot.edges, Edges::new(NO_EDGE))
+        });
+        Ok(self.presses.update(core::iter::from_fn(|| edges.pop())))
+    }
+}
+
+// on_change queues the edges of the buttons of a port
+fn on_change(cs: CriticalSection, port: u8) {
+    let pins = read_port(port);
+    let mut levels = LEVELS.borrow(cs).get();
+    let changed = levels[port as usize] ^ pins;
+    levels[port as usize] = pins;
+    LEVELS.borrow(cs).set(levels);
+
+    let at = timer_0::millis(cs);
+    for slot in SLOTS.borrow(cs).borrow_mut().iter_mut().flatten() {
+        let mask = 1 << (slot.pcint % 8);
+        if slot.pcint / 8 == port && changed & mask != 0 {
+            slot.edges.push(Edge {
+                pressed: pins & mask == 0,
+                at,
+            });
+        }
+    }
+}
+
+#[avr_device::interrupt(atmega328p)]
+fn PCINT0() {
+    avr_device::interrupt::free(|cs| on_change(cs, 0))
+}
+
+#[avr_device::interrupt(atmega328p)]
+fn PCINT1() {
+    avr_device::interrupt::free(|cs| on_change(cs, 1))
+}
+
+#[avr_device::interrupt(atmega328p)]
+fn PCINT2() {
+    avr_device::interrupt::free(|cs| on_change(cs, 2))
+}
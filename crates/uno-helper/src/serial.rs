@@ -0,0 +1,189 @@
+//! This module sends the serial output in the background: the bytes written
+//! are queued in a buffer and sent one by one by the USART "data register
+//! empty" interrupt, so printing the telemetry doesn't stall the loop of the
+//! exercise for the ~170us per byte of 57600 bauds.
+//! The bytes received are buffered by an interrupt too, which also wakes the
+//! mcu from its idle sleep (see the sleep module).
+//!
+//! You need to enable interrupts for anything to be sent or received
+
+use core::cell::{Cell, RefCell};
+use core::convert::Infallible;
+use embedded_hal_nb::serial::{self, ErrorKind};
+use uno_lib::ring_buffer::RingBuffer;
+
+// 128 bytes fit a console reply and a few telemetry frames
+const TX_BUFFER_SIZE: usize = 128;
+
+static TX_BUFFER: avr_device::interrupt::Mutex<RefCell<RingBuffer<u8, TX_BUFFER_SIZE>>> =
+    avr_device::interrupt::Mutex::new(RefCell::new(RingBuffer::new(0)));
+
+// The console reads a command between two loops, 16 bytes give it ~3ms
+const RX_BUFFER_SIZE: usize = 16;
+
+static RX_BUFFER: avr_device::interrupt::Mutex<RefCell<RingBuffer<u8, RX_BUFFER_SIZE>>> =
+    avr_device::interrupt::Mutex::new(RefCell::new(RingBuffer::new(0)));
+
+// The error of a byte received since the last read, reported by the next read
+static RX_ERROR: avr_device::interrupt::Mutex<Cell<Option<ErrorKind>>> =
+    avr_device::interrupt::Mutex::new(Cell::new(None));
+
+// True once a byte was handed to the usart, until then its "transmit
+// complete" flag is not set
+static TX_STARTED: avr_device::interrupt::Mutex<Cell<bool>> =
+    avr_device::interrupt::Mutex::new(Cell::new(false));
+
+// FullPolicy is what a write does when the buffer is full, i.e. when more is
+// printed than the serial port can send
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FullPolicy {
+    // Drop the byte, the loop keeps its timing but the output has holes. The
+    // telemetry decoder skips the damaged frames.
+    Drop,
+    // Wait for the interrupt to make room, as a blocking serial would
+    Block,
+}
+
+// BufferedSerial writes and reads through the buffers, e.g. for the telemetry
+// and the console. There is a single USART on the uno, hence a single pair of
+// buffers.
+pub struct BufferedSerial {
+    // Only kept to own the port, it is configured by arduino_hal
+    _usart: arduino_hal::DefaultSerial,
+    policy: FullPolicy,
+    dropped: u32,
+}
+
+impl BufferedSerial {
+    pub fn new(usart: arduino_hal::DefaultSerial, policy: FullPolicy) -> BufferedSerial {
+        usart().ucsr0b.modify(|_, w| w.rxcie0().set_bit());
+        BufferedSerial {
+            _usart: usart,
+            policy,
+            dropped: 0,
+        }
+    }
+
+    // dropped is the number of bytes dropped since the start
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    fn push(&mut self, b: u8) {
+        loop {
+            let pushed = avr_device::interrupt::free(|cs| {
+                let pushed = TX_BUFFER.borrow(cs).borrow_mut().push(b);
+                // The interrupt fires as long as the data register is empty,
+                // it is disabled again once the buffer is
+                usart().ucsr0b.modify(|_, w| w.udrie0().set_bit());
+                pushed
+            });
+            match (pushed, self.policy) {
+                (true, _) => return,
+                (false, FullPolicy::Drop) => {
+                    self.dropped = self.dropped.wrapping_add(1);
+                    return;
+                }
+                // The interrupts are enabled again between two tries
+                (false, FullPolicy::Block) => {}
+            }
+        }
+    }
+}
+
+// The registers are shared with the interrupts, which can't own the usart
+fn usart() -> &'static arduino_hal::pac::usart0::RegisterBlock {
+    unsafe { &*arduino_hal::pac::USART0::ptr() }
+}
+
+// is_sent is true when every byte written was sent out, the usart can then be
+// powered down without cutting a byte
+pub fn is_sent() -> bool {
+    avr_device::interrupt::free(|cs| {
+        TX_BUFFER.borrow(cs).borrow().is_empty()
+            && (!TX_STARTED.borrow(cs).get() || usart().ucsr0a.read().txc0().bit_is_set())
+    })
+}
+
+impl ufmt::uWrite for BufferedSerial {
+    type Error = Infallible;
+
+    fn write_str(&mut self, s: &str) -> Result<(), Infallible> {
+        for b in s.bytes() {
+            self.push(b);
+        }
+        Ok(())
+    }
+}
+
+impl serial::ErrorType for BufferedSerial {
+    type Error = ErrorKind;
+}
+
+// A write never fails nor blocks with FullPolicy::Drop
+impl serial::Write<u8> for BufferedSerial {
+    fn write(&mut self, b: u8) -> nb::Result<(), ErrorKind> {
+        self.push(b);
+        Ok(())
+    }
+
+    // flush waits for the buffer to be handed to the usart, its last byte may
+    // still be shifting out
+    fn flush(&mut self) -> nb::Result<(), ErrorKind> {
+        let empty = avr_device::interrupt::free(|cs| TX_BUFFER.borrow(cs).borrow().is_empty());
+        if empty && usart().ucsr0a.read().udre0().bit_is_set() {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+impl serial::Read<u8> for BufferedSerial {
+    fn read(&mut self) -> nb::Result<u8, ErrorKind> {
+        avr_device::interrupt::free(|cs| {
+            if let Some(e) = RX_ERROR.borrow(cs).take() {
+                return Err(nb::Error::Other(e));
+            }
+            RX_BUFFER
+                .borrow(cs)
+                .borrow_mut()
+                .pop()
+                .ok_or(nb::Error::WouldBlock)
+        })
+    }
+}
+
+#[avr_device::interrupt(atmega328p)]
+fn USART_UDRE() {
+    avr_device::interrupt::free(|cs| match TX_BUFFER.borrow(cs).borrow_mut().pop() {
+        Some(b) => {
+            // The "transmit complete" flag is cleared by writing it
+            usart().ucsr0a.modify(|_, w| w.txc0().set_bit());
+            usart().udr0.write(|w| w.bits(b));
+            TX_STARTED.borrow(cs).set(true);
+        }
+        None => usart().ucsr0b.modify(|_, w| w.udrie0().clear_bit()),
+    })
+}
+
+#[avr_device::interrupt(atmega328p)]
+fn USART_RX() {
+    avr_device::interrupt::free(|cs| {
+        // The errors are those of the received byte, which must be read anyway
+        let status = usart().ucsr0a.read();
+        let b = usart().udr0.read().bits();
+        let error = if status.dor0().bit_is_set() {
+            Some(ErrorKind::Overrun)
+        } else if status.fe0().bit_is_set() {
+            Some(ErrorKind::FrameFormat)
+        } else if !RX_BUFFER.borrow(cs).borrow_mut().push(b) {
+            Some(ErrorKind::Overrun)
+        } else {
+            None
+        };
+        if error.is_some() {
+            RX_ERROR.borrow(cs).set(error);
+        }
+    })
+}
@@ -0,0 +1,42 @@
+//! This module puts the uno to sleep between two loops of an exercise, see
+//! exercises::exercise::Sleep:
+//! - Sleep::Idle stops the cpu until the next interrupt, e.g. a tick of the
+//!   timer 0 or a byte received by the serial module
+//! - Sleep::Deep powers everything down but the pin change interrupts, the
+//!   buttons that wake the uno up are given to wake_on (or are read with the
+//!   pcint module). The millis of the timer 0 stop meanwhile.
+
+use exercises::exercise::{Idle, Sleep};
+
+use crate::{pcint, serial};
+
+pub struct Sleeper {
+    cpu: arduino_hal::pac::CPU,
+}
+
+impl Sleeper {
+    pub fn new(cpu: arduino_hal::pac::CPU) -> Sleeper {
+        Sleeper { cpu }
+    }
+
+    // wake_on makes the pin change interrupt PCINT<pcint> wake the uno up from
+    // Sleep::Deep, see pcint::enable for the numbers
+    pub fn wake_on(&mut self, pcint: u8) {
+        pcint::enable(pcint)
+    }
+}
+
+impl Idle for Sleeper {
+    fn sleep(&mut self, depth: Sleep) {
+        // A deep sleep would cut the serial output, and could not be woken up
+        // without a pin change interrupt
+        let deep = depth == Sleep::Deep && serial::is_sent() && pcint::is_enabled();
+        match depth {
+            Sleep::Busy => return,
+            _ if deep => self.cpu.smcr.write(|w| w.sm().pdown().se().set_bit()),
+            _ => self.cpu.smcr.write(|w| w.sm().idle().se().set_bit()),
+        }
+        avr_device::asm::sleep();
+        self.cpu.smcr.write(|w| w.se().clear_bit());
+    }
+}
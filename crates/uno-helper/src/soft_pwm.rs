@@ -0,0 +1,163 @@
+//! This module dims leds on any output pin with a software pwm: the timer 2
+//! interrupt counts through a pwm cycle and turns each pin on while the count
+//! is below its duty. The uno only has 6 hardware pwm pins, which are also
+//! used by the timers, so 8 dimmed leds need this.
+//!
+//! The interrupt runs at 8kHz for 64 levels, i.e. a cycle at 125Hz, fast
+//! enough not to flicker. It switches all the pins of a port with a single
+//! write, the bits of the pins being found once by new, so that it is short
+//! and barely delays the timer 0 interrupt of the millis and micros.
+//!
+//! It still wakes the cpu up 8000 times a second: an exercise dimming its
+//! leds with it, e.g. the chenillard, saves almost no power in Sleep::Idle.
+//!
+//! You need to enable interrupts for the pins to change
+
+use core::cell::Cell;
+use core::convert::Infallible;
+
+use arduino_hal::port::{mode, Pin};
+use avr_device::interrupt::Mutex;
+use embedded_hal::pwm::{ErrorType, SetDutyCycle};
+
+// The number of pins of the pwm, those of the chenillard
+pub const NB_CHANNELS: usize = 8;
+
+// The number of levels of a cycle, each lasts one interrupt
+const MAX_DUTY: u8 = 64;
+
+// 16MHz / 8 / 250 = 8kHz
+const PRESCALER_8_COUNTS: u8 = 250;
+
+type Pins = [Pin<mode::Output>; NB_CHANNELS];
+
+// Wire is the bit of a pin in the output register of its port, the ports B,
+// C and D are 0, 1 and 2 as in the pcint module
+#[derive(Clone, Copy)]
+struct Wire {
+    port: u8,
+    mask: u8,
+}
+
+const NO_WIRE: Wire = Wire { port: 0, mask: 0 };
+
+static WIRES: Mutex<Cell<[Wire; NB_CHANNELS]>> = Mutex::new(Cell::new([NO_WIRE; NB_CHANNELS]));
+
+// The bits of the pins in each port, the others are left as they are
+static PORT_MASKS: Mutex<Cell<[u8; 3]>> = Mutex::new(Cell::new([0; 3]));
+
+static DUTIES: Mutex<Cell<[u8; NB_CHANNELS]>> = Mutex::new(Cell::new([0; NB_CHANNELS]));
+
+// The position in the pwm cycle
+static COUNTER: Mutex<Cell<u8>> = Mutex::new(Cell::new(0));
+
+// new starts the timer 2 and returns a channel driving each pin, all off.
+// The timer 2 can't be used for anything else.
+pub fn new(tc2: arduino_hal::pac::TC2, pins: Pins) -> [Channel; NB_CHANNELS] {
+    // The pins stay outputs, the interrupt then writes their ports directly
+    let wires = pins.map(|mut pin| wire(&mut pin));
+    let mut port_masks = [0; 3];
+    for wire in wires {
+        port_masks[wire.port as usize] |= wire.mask;
+    }
+    avr_device::interrupt::free(|cs| {
+        WIRES.borrow(cs).set(wires);
+        PORT_MASKS.borrow(cs).set(port_masks);
+        DUTIES.borrow(cs).set([0; NB_CHANNELS]);
+    });
+    // CTC mode, the counter goes from 0 to OCR2A included
+    tc2.tccr2a.write(|w| w.wgm2().ctc());
+    tc2.ocr2a.write(|w| w.bits(PRESCALER_8_COUNTS - 1));
+    tc2.tccr2b.write(|w| w.cs2().prescale_8());
+    tc2.timsk2.write(|w| w.ocie2a().set_bit());
+    core::array::from_fn(|idx| Channel { idx })
+}
+
+// wire finds the bit of an output pin, the only bit of the output registers
+// that changes when the pin is set high. The pin is left low.
+fn wire(pin: &mut Pin<mode::Output>) -> Wire {
+    pin.set_low();
+    let low = read_ports();
+    pin.set_high();
+    let high = read_ports();
+    pin.set_low();
+    (0..3)
+        .map(|port| Wire {
+            port,
+            mask: low[port as usize] ^ high[port as usize],
+        })
+        .find(|wire| wire.mask != 0)
+        .unwrap_or(NO_WIRE)
+}
+
+fn read_ports() -> [u8; 3] {
+    unsafe {
+        [
+            (*arduino_hal::pac::PORTB::ptr()).portb.read().bits(),
+            (*arduino_hal::pac::PORTC::ptr()).portc.read().bits(),
+            (*arduino_hal::pac::PORTD::ptr()).portd.read().bits(),
+        ]
+    }
+}
+
+// write_port turns on the pins of the port in on and off the others of mask
+fn write_port(port: usize, mask: u8, on: u8) {
+    unsafe {
+        match port {
+            0 => (*arduino_hal::pac::PORTB::ptr())
+                .portb
+                .modify(|r, w| w.bits(r.bits() & !mask | on)),
+            1 => (*arduino_hal::pac::PORTC::ptr())
+                .portc
+                .modify(|r, w| w.bits(r.bits() & !mask | on)),
+            _ => (*arduino_hal::pac::PORTD::ptr())
+                .portd
+                .modify(|r, w| w.bits(r.bits() & !mask | on)),
+        }
+    }
+}
+
+// Channel is the duty of a pin of the software pwm
+pub struct Channel {
+    idx: usize,
+}
+
+impl ErrorType for Channel {
+    type Error = Infallible;
+}
+
+impl SetDutyCycle for Channel {
+    fn max_duty_cycle(&self) -> u16 {
+        MAX_DUTY as u16
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Infallible> {
+        avr_device::interrupt::free(|cs| {
+            let mut duties = DUTIES.borrow(cs).get();
+            duties[self.idx] = duty.min(MAX_DUTY as u16) as u8;
+            DUTIES.borrow(cs).set(duties);
+        });
+        Ok(())
+    }
+}
+
+#[avr_device::interrupt(atmega328p)]
+fn TIMER2_COMPA() {
+    avr_device::interrupt::free(|cs| {
+        let counter = COUNTER.borrow(cs).get();
+        COUNTER.borrow(cs).set((counter + 1) % MAX_DUTY);
+        let duties = DUTIES.borrow(cs).get();
+        // A full duty keeps the pin on for the whole cycle
+        let mut on = [0; 3];
+        for (wire, duty) in WIRES.borrow(cs).get().iter().zip(duties) {
+            if counter < duty {
+                on[wire.port as usize] |= wire.mask;
+            }
+        }
+        for (port, mask) in PORT_MASKS.borrow(cs).get().into_iter().enumerate() {
+            if mask != 0 {
+                write_port(port, mask, on[port]);
+            }
+        }
+    })
+}
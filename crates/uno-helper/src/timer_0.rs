@@ -7,51 +7,91 @@
 //! You need to enable interrupts in order for the timer to work
 
 use core::cell;
-use exercises::chrono::Chrono;
+use exercises::chrono::{Chrono, Instant, Micros};
 use panic_halt as _;
+use uno_lib::timer_0::{compute_timer0_params, Timer0Params};
 
-// Possible Values:
-//
-// ╔═══════════╦══════════════╦═══════════════════╗
-// ║ PRESCALER ║ TIMER_COUNTS ║ Overflow Interval ║
-// ╠═══════════╬══════════════╬═══════════════════╣
-// ║        64 ║          250 ║              1 ms ║
-// ║       256 ║          125 ║              2 ms ║
-// ║       256 ║          250 ║              4 ms ║
-// ║      1024 ║          125 ║              8 ms ║
-// ║      1024 ║          250 ║             16 ms ║
-// ╚═══════════╩══════════════╩═══════════════════╝
-const PRESCALER: u32 = 1024;
-const TIMER_COUNTS: u32 = 125;
-
-const MILLIS_INCREMENT: u32 = PRESCALER * TIMER_COUNTS / 16000;
+// 16MHz, not sure how to get that from arduino hal
+const CPU_FREQUENCY_HZ: u32 = 16_000_000;
 
 static MILLIS_COUNTER: avr_device::interrupt::Mutex<cell::Cell<u32>> =
     avr_device::interrupt::Mutex::new(cell::Cell::new(0));
 
-pub struct Chrono0 {
+// The interrupt is shared by all the tick periods, the increment is set when
+// the timer is configured
+static MILLIS_INCREMENT: avr_device::interrupt::Mutex<cell::Cell<u32>> =
+    avr_device::interrupt::Mutex::new(cell::Cell::new(0));
+
+// Chrono0 counts the time with an interrupt every TICK_US.
+// A shorter tick gives finer millis at the cost of more interrupts, the
+// micros are as fine as the prescaler allows in any case.
+// Only a whole number of ms that fits the timer can be used, e.g. 1, 2, 4, 8
+// or 16ms, anything else fails the compilation.
+pub struct Chrono0<const TICK_US: u32 = 8_000> {
     tc0: arduino_hal::pac::TC0,
 }
 
 impl Chrono0 {
     pub fn new(tc0: arduino_hal::pac::TC0) -> Chrono0 {
+        Chrono0::with_tick(tc0)
+    }
+}
+
+impl<const TICK_US: u32> Chrono0<TICK_US> {
+    // Compute prescaler and counts at compile time
+    const PARAMS: Timer0Params = match compute_timer0_params(CPU_FREQUENCY_HZ, TICK_US) {
+        Some(p) => p,
+        None => panic!("no timer 0 setting for this tick period"),
+    };
+
+    // with_tick is new with another tick period, e.g. Chrono0::<1_000>::with_tick
+    pub fn with_tick(tc0: arduino_hal::pac::TC0) -> Chrono0<TICK_US> {
         let c = Chrono0 { tc0 };
         c.reset();
         c
     }
 }
 
-impl exercises::chrono::Chrono for Chrono0 {
-    fn millis(&self) -> u32 {
-        avr_device::interrupt::free(|cs| MILLIS_COUNTER.borrow(cs).get())
+impl<const TICK_US: u32> exercises::chrono::Chrono for Chrono0<TICK_US> {
+    fn now(&self) -> Instant {
+        Instant::from_millis(avr_device::interrupt::free(|cs| {
+            MILLIS_COUNTER.borrow(cs).get()
+        }))
+    }
+
+    fn micros(&self) -> Instant<Micros> {
+        let timer_counts = Self::PARAMS.timer_counts as u32;
+        let (millis, counts) = avr_device::interrupt::free(|cs| {
+            let millis = MILLIS_COUNTER.borrow(cs).get();
+            let counts = self.tc0.tcnt0.read().bits() as u32;
+            // The interrupts are disabled here, so the compare match may have
+            // happened without the counter being updated yet. In this case
+            // the timer already restarted from 0, unless it is still on the
+            // match value.
+            if self.tc0.tifr0.read().ocf0a().bit_is_set() && counts < timer_counts - 1 {
+                (millis.wrapping_add(TICK_US / 1000), counts)
+            } else {
+                (millis, counts)
+            }
+        });
+        // Wrapping keeps the us counter continuous when the ms one rolls over
+        Instant::from_micros(
+            millis
+                .wrapping_mul(1000)
+                .wrapping_add(counts * TICK_US / timer_counts),
+        )
     }
 
     fn reset(&self) {
         // Configure the timer for the above interval (in CTC mode)
         // and enable its interrupt.
+        // The counter goes from 0 to OCR0A included.
         self.tc0.tccr0a.write(|w| w.wgm0().ctc());
-        self.tc0.ocr0a.write(|w| w.bits(TIMER_COUNTS as u8));
-        self.tc0.tccr0b.write(|w| match PRESCALER {
+        self.tc0
+            .ocr0a
+            .write(|w| w.bits((Self::PARAMS.timer_counts - 1) as u8));
+        self.tc0.tccr0b.write(|w| match Self::PARAMS.prescaler {
+            1 => w.cs0().direct(),
             8 => w.cs0().prescale_8(),
             64 => w.cs0().prescale_64(),
             256 => w.cs0().prescale_256(),
@@ -63,15 +103,21 @@ impl exercises::chrono::Chrono for Chrono0 {
         // Reset the global millisecond counter
         avr_device::interrupt::free(|cs| {
             MILLIS_COUNTER.borrow(cs).set(0);
+            MILLIS_INCREMENT.borrow(cs).set(TICK_US / 1000);
         });
     }
 }
 
+// millis is the time of the chrono for the interrupts, which can't borrow it
+pub(crate) fn millis(cs: avr_device::interrupt::CriticalSection) -> Instant {
+    Instant::from_millis(MILLIS_COUNTER.borrow(cs).get())
+}
+
 #[avr_device::interrupt(atmega328p)]
 fn TIMER0_COMPA() {
     avr_device::interrupt::free(|cs| {
         let counter_cell = MILLIS_COUNTER.borrow(cs);
         let counter = counter_cell.get();
-        counter_cell.set(counter + MILLIS_INCREMENT);
+        counter_cell.set(counter.wrapping_add(MILLIS_INCREMENT.borrow(cs).get()));
     })
 }
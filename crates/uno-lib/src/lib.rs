@@ -1,3 +1,5 @@
 #![no_std]
 
+pub mod ring_buffer;
+pub mod timer_0;
 pub mod timer_1;
@@ -0,0 +1,88 @@
+// RingBuffer is a fixed size FIFO, e.g. of the bytes written to the serial
+// port and not sent yet by its interrupt.
+// It is const so it can be the initial value of a static.
+#[derive(Clone, Copy)]
+pub struct RingBuffer<T, const N: usize> {
+    items: [T; N],
+    // The index of the oldest item
+    start: usize,
+    len: usize,
+}
+
+impl<T: Copy, const N: usize> RingBuffer<T, N> {
+    // new is an empty buffer, its unused slots hold empty
+    pub const fn new(empty: T) -> RingBuffer<T, N> {
+        RingBuffer {
+            items: [empty; N],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    // push adds item at the end of the buffer, it returns false if it is full
+    pub fn push(&mut self, item: T) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        self.items[(self.start + self.len) % N] = item;
+        self.len += 1;
+        true
+    }
+
+    // pop removes the oldest item of the buffer
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let item = self.items[self.start];
+        self.start = (self.start + 1) % N;
+        self.len -= 1;
+        Some(item)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+}
+
+impl<T: Copy + Default, const N: usize> Default for RingBuffer<T, N> {
+    fn default() -> RingBuffer<T, N> {
+        RingBuffer::new(T::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_pop() {
+        let mut buffer = RingBuffer::<u8, 4>::new(0);
+        assert_eq!(None, buffer.pop(), "the buffer should start empty");
+        for b in 1..=4 {
+            assert!(buffer.push(b), "{b} should fit");
+        }
+        assert!(!buffer.push(5), "the buffer should be full");
+
+        // The bytes come out in order, also when the buffer wraps around
+        assert_eq!(Some(1), buffer.pop());
+        assert_eq!(Some(2), buffer.pop());
+        assert!(buffer.push(5));
+        assert!(buffer.push(6));
+        assert_eq!(4, buffer.len());
+        let mut popped = [0; 4];
+        for b in popped.iter_mut() {
+            *b = buffer.pop().unwrap();
+        }
+        assert_eq!([3, 4, 5, 6], popped, "wrong order");
+        assert!(buffer.is_empty());
+    }
+}
@@ -0,0 +1,101 @@
+const POSSIBLE_PRESCALERS: [u16; 5] = [1, 8, 64, 256, 1024];
+
+// This fonction is executed at compile time when possible
+// It computes the prescaler and the number of counts of the timer 0 in CTC
+// mode given:
+// - a CPU clock in Hz
+// - a tick period in us, which must be a whole number of ms to be counted by
+//   a millis counter
+// The smallest prescaler is used for the finest micros resolution.
+// The returned option is empty if no settings gives exactly the tick period.
+pub const fn compute_timer0_params(clock_hz: u32, tick_us: u32) -> Option<Timer0Params> {
+    if tick_us == 0 || (tick_us / 1000) * 1000 != tick_us {
+        return None;
+    }
+    let clock_cycles = clock_hz as u64 * tick_us as u64;
+    let cycles = clock_cycles / 1_000_000;
+    if cycles * 1_000_000 != clock_cycles {
+        return None;
+    }
+    let mut i = 0;
+    while i < POSSIBLE_PRESCALERS.len() {
+        let ps = POSSIBLE_PRESCALERS[i] as u64;
+        // The 8 bits counter goes from 0 to OCR0A included
+        if (cycles / ps) * ps == cycles && cycles / ps <= 256 {
+            return Some(Timer0Params {
+                prescaler: POSSIBLE_PRESCALERS[i],
+                timer_counts: (cycles / ps) as u16,
+            });
+        }
+        i += 1;
+    }
+    None
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Timer0Params {
+    pub prescaler: u16,
+    // The number of counts per tick, OCR0A must be set to timer_counts - 1
+    pub timer_counts: u16,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outputs() {
+        struct TestCase {
+            tick_us: u32,
+            expected: Option<Timer0Params>,
+        }
+        let cases = [
+            TestCase {
+                tick_us: 1_000,
+                expected: Some(Timer0Params {
+                    prescaler: 64,
+                    timer_counts: 250,
+                }),
+            },
+            TestCase {
+                tick_us: 2_000,
+                expected: Some(Timer0Params {
+                    prescaler: 256,
+                    timer_counts: 125,
+                }),
+            },
+            TestCase {
+                tick_us: 8_000,
+                expected: Some(Timer0Params {
+                    prescaler: 1024,
+                    timer_counts: 125,
+                }),
+            },
+            TestCase {
+                tick_us: 16_000,
+                expected: Some(Timer0Params {
+                    prescaler: 1024,
+                    timer_counts: 250,
+                }),
+            },
+            // Too long for the 8 bits counter
+            TestCase {
+                tick_us: 17_000,
+                expected: None,
+            },
+            // Not a whole number of ms
+            TestCase {
+                tick_us: 500,
+                expected: None,
+            },
+            TestCase {
+                tick_us: 0,
+                expected: None,
+            },
+        ];
+        for tc in cases {
+            let actual = compute_timer0_params(16_000_000, tc.tick_us);
+            assert_eq!(tc.expected, actual, "wrong params for {}us", tc.tick_us)
+        }
+    }
+}
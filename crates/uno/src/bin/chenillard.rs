@@ -1,11 +1,19 @@
 #![no_std]
 #![no_main]
 
+use exercises::chrono::Duration;
+use exercises::led_bank::Dimmed;
+use exercises::pattern::Builtin;
 use panic_halt as _;
+use uno_helper::adc::AdcWrapper;
 
 const PARAMS: exercises::chenillard::Parameters = exercises::chenillard::Parameters {
-    // The chenillard loops every second
-    period_ms: 1000,
+    // The chenillard loops every second with the potentiometer at the max
+    period: Duration::from_millis(1000),
+    // Start with a single led walking, the button goes to the next patterns
+    pattern: Builtin::Walk,
+    // The leds fade out for 3 steps, as the tail of a comet
+    trail: 3,
 };
 
 #[arduino_hal::entry]
@@ -14,30 +22,52 @@ fn main() -> ! {
 
     let pins = arduino_hal::pins!(dp);
 
+    let leds = [
+        // Every pin has a different type thus a call to downgrade is
+        // necessary to give them the same type and put them in a list
+        pins.d6.downgrade(),
+        pins.d7.downgrade(),
+        pins.d8.downgrade(),
+        pins.d9.downgrade(),
+        pins.d10.downgrade(),
+        pins.d11.downgrade(),
+        pins.d12.downgrade(),
+        pins.d13.downgrade(),
+    ]
+    // Turn every pin from the list into an output pin
+    .map(|p| p.into_output());
+
     let mut my_pins = exercises::chenillard::OutputPeripherals {
-        ps: [
-            // Every pin has a different type thus a call to downgrade is
-            // necessary to give them the same type and put them in a list
-            pins.d6.downgrade(),
-            pins.d7.downgrade(),
-            pins.d8.downgrade(),
-            pins.d9.downgrade(),
-            pins.d10.downgrade(),
-            pins.d11.downgrade(),
-            pins.d12.downgrade(),
-            pins.d13.downgrade(),
-        ]
-        // Turn every pin from the list into an output pin
-        .map(|p| p.into_output()),
+        // Dim the leds with the timer 2
+        ps: Dimmed(uno_helper::soft_pwm::new(dp.TC2, leds)),
     };
 
-    let ip = exercises::chenillard::InputPeripherals {
+    let mut adc = arduino_hal::Adc::new(dp.ADC, Default::default());
+
+    let mut ip = exercises::chenillard::InputPeripherals {
         // Use "millis" based on timer 0
         chrono: uno_helper::timer_0::Chrono0::new(dp.TC0),
+        // The buttons are on D2 to D5, the other side to the ground
+        button: pins.d2.downgrade().into_pull_up_input(),
+        faster: pins.d3.downgrade().into_pull_up_input(),
+        slower: pins.d4.downgrade().into_pull_up_input(),
+        reverse: pins.d5.downgrade().into_pull_up_input(),
+        // The speed potentiometer is on A0
+        pin: pins.a0.into_analog_input(&mut adc),
+        adc: AdcWrapper(adc),
     };
 
+    // Sleep between two ticks of the timer 0, although the software pwm wakes
+    // the uno up much more often
+    let mut sleeper = uno_helper::sleep::Sleeper::new(dp.CPU);
+
     unsafe { avr_device::interrupt::enable() }
 
     // start the chenillard lib using the arduino specific peripherals
-    exercises::chenillard::run(&PARAMS, &ip, &mut my_pins);
+    exercises::exercise::run_idle::<exercises::chenillard::Chenillard<_, _, _, _, _>, _>(
+        &PARAMS,
+        &mut ip,
+        &mut my_pins,
+        &mut sleeper,
+    );
 }
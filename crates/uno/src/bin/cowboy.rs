@@ -1,12 +1,19 @@
 #![no_std]
 #![no_main]
 
+use exercises::chrono::Duration;
+use rand::{rngs::SmallRng, SeedableRng};
+use uno_helper::pcint::PcintButton;
+use uno_helper::serial::{BufferedSerial, FullPolicy};
 use uno_helper::timer_0;
 
 const PARAMS: exercises::cowboy::Parameters = exercises::cowboy::Parameters {
     nb_blinks: 3,
-    blink_duration_ms: 3_000,
-    show_winner_duration: 5_000,
+    blink_duration: Duration::from_millis(3_000),
+    show_winner_duration: Duration::from_millis(5_000),
+    // The start signal comes 0.5 to 2.5s after the blinking
+    min_hold: Duration::from_millis(500),
+    max_hold: Duration::from_millis(2_500),
 };
 
 #[arduino_hal::entry]
@@ -15,20 +22,40 @@ fn main() -> ! {
 
     let pins = arduino_hal::pins!(dp);
 
-    // Use D12 and D13 as the leds to show the results
+    // Use D12 and D13 as the leds to show the results. The reaction times are
+    // printed on the serial port (57600 bauds) after each round.
     let mut output_peripherals = exercises::cowboy::OutputPeripherals {
         left_led: pins.d12.downgrade().into_output(),
         right_led: pins.d13.downgrade().into_output(),
+        serial: BufferedSerial::new(
+            arduino_hal::default_serial!(dp, pins, 57600),
+            FullPolicy::Block,
+        ),
     };
 
-    // Use D10 and D11 as the button inputs
+    // Blocking read of the unconnected A0 on init to get a seed for the RNG
+    let mut adc = arduino_hal::Adc::new(dp.ADC, Default::default());
+    let seed = pins.a0.into_analog_input(&mut adc).analog_read(&mut adc);
+
+    // Use D10 (PB2 so PCINT2) and D11 (PB3 so PCINT3) as the button inputs.
+    // Their presses are timed by their interrupts to the ms tick of the
+    // chrono, not to the loop, to find the fastest player.
     let mut input_peripherals = exercises::cowboy::InputPeripherals {
-        chrono: timer_0::Chrono0::new(dp.TC0),
-        left_button: pins.d10.downgrade().into_pull_up_input(),
-        right_button: pins.d11.downgrade().into_pull_up_input(),
+        chrono: timer_0::Chrono0::<1_000>::with_tick(dp.TC0),
+        left_button: PcintButton::new(pins.d10.downgrade().into_pull_up_input(), 2),
+        right_button: PcintButton::new(pins.d11.downgrade().into_pull_up_input(), 3),
+        rng: SmallRng::seed_from_u64(seed as u64),
     };
 
+    // Sleep between two ticks of the timer 0
+    let mut sleeper = uno_helper::sleep::Sleeper::new(dp.CPU);
+
     unsafe { avr_device::interrupt::enable() }
 
-    exercises::cowboy::run(&PARAMS, &mut input_peripherals, &mut output_peripherals);
+    exercises::exercise::run_idle::<exercises::cowboy::Cowboy<_, _, _, _>, _>(
+        &PARAMS,
+        &mut input_peripherals,
+        &mut output_peripherals,
+        &mut sleeper,
+    );
 }
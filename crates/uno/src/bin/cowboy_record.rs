@@ -0,0 +1,63 @@
+#![no_std]
+#![no_main]
+
+// Same as the cowboy binary, but every tick read from the buttons is printed
+// on the serial port, e.g. to turn a bug seen on the arduino into a trace
+// that can be replayed in the tests of the exercises crate
+use core::cell::RefCell;
+
+use exercises::button::Polled;
+use exercises::chrono::Duration;
+use exercises::console::Shared;
+use exercises::{cowboy, exercise, trace};
+use rand::{rngs::SmallRng, SeedableRng};
+use uno_helper::timer_0;
+
+const PARAMS: cowboy::Parameters = cowboy::Parameters {
+    nb_blinks: 3,
+    blink_duration: Duration::from_millis(3_000),
+    show_winner_duration: Duration::from_millis(5_000),
+    // The start signal comes 0.5 to 2.5s after the blinking
+    min_hold: Duration::from_millis(500),
+    max_hold: Duration::from_millis(2_500),
+};
+
+#[arduino_hal::entry]
+fn main() -> ! {
+    let dp = arduino_hal::Peripherals::take().unwrap();
+
+    let pins = arduino_hal::pins!(dp);
+
+    // The summaries of the rounds are comments between the ticks
+    let serial = RefCell::new(arduino_hal::default_serial!(dp, pins, 57600));
+
+    // Use D12 and D13 as the leds to show the results
+    let mut output_peripherals = cowboy::OutputPeripherals {
+        left_led: pins.d12.downgrade().into_output(),
+        right_led: pins.d13.downgrade().into_output(),
+        serial: Shared::new(&serial),
+    };
+
+    // Blocking read of the unconnected A0 on init to get a seed for the RNG
+    let mut adc = arduino_hal::Adc::new(dp.ADC, Default::default());
+    let seed = pins.a0.into_analog_input(&mut adc).analog_read(&mut adc);
+
+    // Use D10 and D11 as the button inputs
+    let mut input_peripherals = trace::Recorder {
+        inputs: cowboy::InputPeripherals {
+            chrono: timer_0::Chrono0::new(dp.TC0),
+            left_button: Polled(pins.d10.downgrade().into_pull_up_input()),
+            right_button: Polled(pins.d11.downgrade().into_pull_up_input()),
+            rng: SmallRng::seed_from_u64(seed as u64),
+        },
+        serial: Shared::new(&serial),
+    };
+
+    unsafe { avr_device::interrupt::enable() }
+
+    exercise::run::<trace::Record<cowboy::Cowboy<_, _, _, _>, _>>(
+        &PARAMS,
+        &mut input_peripherals,
+        &mut output_peripherals,
+    );
+}
@@ -1,7 +1,13 @@
 #![no_std]
 #![no_main]
 
+use core::cell::RefCell;
+
+use exercises::console::{self, Shared};
+use exercises::exercise::NoIdle;
+use exercises::persist;
 use fixed::types::U1F15;
+use uno_helper::serial::{BufferedSerial, FullPolicy};
 use uno_helper::timer_0;
 
 const PARAMS: exercises::debounce::Parameters = exercises::debounce::Parameters {
@@ -20,8 +26,16 @@ fn main() -> ! {
 
     let pins = arduino_hal::pins!(dp);
 
+    // The serial port is shared by the telemetry and the console. The
+    // telemetry that doesn't fit in the buffer is dropped rather than slowing
+    // the loop down.
+    let serial = RefCell::new(BufferedSerial::new(
+        arduino_hal::default_serial!(dp, pins, 57600),
+        FullPolicy::Drop,
+    ));
+
     let mut output_peripherals = exercises::debounce::OutputPeripherals {
-        serial: arduino_hal::default_serial!(dp, pins, 57600),
+        serial: Shared::new(&serial),
     };
 
     let mut input_peripherals = exercises::debounce::InputPeripherals {
@@ -30,7 +44,20 @@ fn main() -> ! {
         right_button: pins.d11.downgrade().into_pull_up_input(),
     };
 
+    // The params saved from the console, or the ones above if none were
+    let mut storage = uno_helper::eeprom::EepromStorage::new(arduino_hal::Eeprom::new(dp.EEPROM));
+    let params = persist::load(&mut storage, &PARAMS).unwrap_or(PARAMS);
+
     unsafe { avr_device::interrupt::enable() }
 
-    exercises::debounce::run(&PARAMS, &mut input_peripherals, &mut output_peripherals);
+    // Tune the params with e.g. "set lambda 0.02" in a serial terminal
+    console::run::<exercises::debounce::Debounce<_, _, _>, _, _, _>(
+        &params,
+        &mut input_peripherals,
+        &mut output_peripherals,
+        &mut Shared::new(&serial),
+        &mut storage,
+        // The exercise reads its inputs as fast as possible
+        &mut NoIdle,
+    );
 }
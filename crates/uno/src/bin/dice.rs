@@ -1,12 +1,16 @@
 #![no_std]
 #![no_main]
 
+use exercises::chrono::Duration;
+use exercises::console;
+use exercises::persist;
 use panic_halt as _;
 use rand::{rngs::SmallRng, SeedableRng};
+use uno_helper::serial::{BufferedSerial, FullPolicy};
 
 const PARAMS: exercises::dice::Parameters = exercises::dice::Parameters {
-    first_blink_duration_ms: 100,
-    last_blink_duration_ms: 400,
+    first_blink_duration: Duration::from_millis(100),
+    last_blink_duration: Duration::from_millis(400),
     nb_blinks: 10,
 };
 
@@ -40,7 +44,29 @@ fn main() -> ! {
         rng: SmallRng::seed_from_u64(seed as u64),
     };
 
+    // The serial port is only used by the console, its replies are short
+    let mut serial = BufferedSerial::new(
+        arduino_hal::default_serial!(dp, pins, 57600),
+        FullPolicy::Block,
+    );
+
+    // The params saved from the console, or the ones above if none were
+    let mut storage = uno_helper::eeprom::EepromStorage::new(arduino_hal::Eeprom::new(dp.EEPROM));
+    let params = persist::load(&mut storage, &PARAMS).unwrap_or(PARAMS);
+
+    // Sleep between the loops, a byte received by the console wakes the uno
+    // up too
+    let mut sleeper = uno_helper::sleep::Sleeper::new(dp.CPU);
+
     unsafe { avr_device::interrupt::enable() }
 
-    exercises::dice::run(&PARAMS, &mut ip, &mut my_pins);
+    // Tune the params with e.g. "set nb_blinks 5" in a serial terminal
+    console::run::<exercises::dice::Dice<_, _, _>, _, _, _>(
+        &params,
+        &mut ip,
+        &mut my_pins,
+        &mut serial,
+        &mut storage,
+        &mut sleeper,
+    );
 }
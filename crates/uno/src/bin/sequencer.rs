@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+use avr_progmem::progmem;
+use exercises::led_bank::Dimmed;
+use exercises::sequencer::DEMO;
+use panic_halt as _;
+use uno_helper::flash::FlashProgram;
+
+progmem! {
+    // The light show stays in the flash, write your own with
+    // exercises::sequencer::assemble
+    static progmem SHOW: [u8; DEMO.len()] = DEMO;
+}
+
+#[arduino_hal::entry]
+fn main() -> ! {
+    let dp = arduino_hal::Peripherals::take().unwrap();
+
+    let pins = arduino_hal::pins!(dp);
+
+    // The same leds as the chenillard
+    let leds = [
+        pins.d6.downgrade(),
+        pins.d7.downgrade(),
+        pins.d8.downgrade(),
+        pins.d9.downgrade(),
+        pins.d10.downgrade(),
+        pins.d11.downgrade(),
+        pins.d12.downgrade(),
+        pins.d13.downgrade(),
+    ]
+    .map(|p| p.into_output());
+
+    let mut my_pins = exercises::chenillard::OutputPeripherals {
+        ps: Dimmed(uno_helper::soft_pwm::new(dp.TC2, leds)),
+    };
+
+    let mut ip = exercises::sequencer::InputPeripherals {
+        // Use "millis" based on timer 0
+        chrono: uno_helper::timer_0::Chrono0::new(dp.TC0),
+    };
+
+    let params = exercises::sequencer::Parameters {
+        program: FlashProgram(&SHOW),
+    };
+
+    // Sleep between two ticks of the timer 0, although the software pwm wakes
+    // the uno up much more often
+    let mut sleeper = uno_helper::sleep::Sleeper::new(dp.CPU);
+
+    unsafe { avr_device::interrupt::enable() }
+
+    exercises::exercise::run_idle::<exercises::sequencer::Sequencer<_, _, _>, _>(
+        &params,
+        &mut ip,
+        &mut my_pins,
+        &mut sleeper,
+    );
+}
@@ -1,12 +1,9 @@
 #![no_std]
 #![no_main]
 
-use arduino_hal::{adc::AdcChannel, hal::Atmega};
-use fixed::{traits::ToFixed, types::U10F6};
 use panic_halt as _;
-
-// The uno adc values go up to 1023 (10-bit ADC).
-const ADC_MAX: U10F6 = U10F6::lit("1023");
+use uno_helper::adc::AdcWrapper;
+use uno_helper::serial::{BufferedSerial, FullPolicy};
 
 #[arduino_hal::entry]
 fn main() -> ! {
@@ -26,7 +23,12 @@ fn main() -> ! {
             pins.d13.downgrade(),
         ]
         .map(|p| p.into_output()),
-        serial: arduino_hal::default_serial!(dp, pins, 57600),
+        // The telemetry that doesn't fit in the buffer is dropped rather than
+        // slowing the loop down
+        serial: BufferedSerial::new(
+            arduino_hal::default_serial!(dp, pins, 57600),
+            FullPolicy::Drop,
+        ),
     };
 
     let mut adc = arduino_hal::Adc::new(dp.ADC, Default::default());
@@ -43,22 +45,3 @@ fn main() -> ! {
 
     exercises::vumetre::run(&mut ip, &mut my_pins);
 }
-
-struct AdcWrapper(arduino_hal::adc::Adc);
-
-// Implements Adc trait we declared in this repo to convert adc values in [0, 1023]
-// to a "device independant" value on [0, 1], using a fixed point number
-// The read from the ADC is non blocking. If the ADC is not ready, we get a "WouldBlock"
-// error token
-impl<PIN> exercises::adc::Adc<PIN> for AdcWrapper
-where
-    PIN: AdcChannel<Atmega, arduino_hal::pac::ADC>,
-{
-    fn read_nonblocking(
-        &mut self,
-        pin: &PIN,
-    ) -> nb::Result<fixed::types::U1F15, core::convert::Infallible> {
-        let res = self.0.read_nonblocking(pin);
-        res.map(|x| (U10F6::from_num(x).wide_div(ADC_MAX)).to_fixed())
-    }
-}
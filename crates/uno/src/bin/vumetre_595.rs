@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+use arduino_hal::spi;
+use exercises::shift_register::{ShiftRegister, Spi};
+use panic_halt as _;
+use uno_helper::adc::AdcWrapper;
+use uno_helper::serial::{BufferedSerial, FullPolicy};
+
+// 16 leds on 2 daisy-chained 74HC595
+const CHIPS: usize = 2;
+const NB_LEDS: usize = 8 * CHIPS;
+
+#[arduino_hal::entry]
+fn main() -> ! {
+    let dp = arduino_hal::Peripherals::take().unwrap();
+
+    let pins = arduino_hal::pins!(dp);
+
+    // SCK (D13) to SHCP, MOSI (D11) to DS and D10 to STCP. MISO (D12) isn't
+    // wired, the 74HC595 doesn't answer.
+    let (spi, latch) = arduino_hal::Spi::new(
+        dp.SPI,
+        pins.d13.into_output(),
+        pins.d11.into_output(),
+        pins.d12.into_pull_up_input(),
+        pins.d10.into_output(),
+        spi::Settings::default(),
+    );
+    let register = ShiftRegister::<_, CHIPS>::new(Spi { spi, latch });
+
+    let mut my_pins = exercises::vumetre::OutputPeripherals::<_, _, NB_LEDS> {
+        ps: register,
+        // The telemetry that doesn't fit in the buffer is dropped rather than
+        // slowing the loop down
+        serial: BufferedSerial::new(
+            arduino_hal::default_serial!(dp, pins, 57600),
+            FullPolicy::Drop,
+        ),
+    };
+
+    let mut adc = arduino_hal::Adc::new(dp.ADC, Default::default());
+
+    let adc_a0 = pins.a0.into_analog_input(&mut adc);
+
+    // Use the only adc on the uno, on channel A0
+    let mut ip = exercises::vumetre::InputPeripherals {
+        adc: AdcWrapper(adc),
+        pin: adc_a0,
+    };
+
+    unsafe { avr_device::interrupt::enable() }
+
+    exercises::vumetre::run(&mut ip, &mut my_pins);
+}
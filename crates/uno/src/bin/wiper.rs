@@ -1,10 +1,15 @@
 #![no_std]
 #![no_main]
 
+use core::cell::RefCell;
 use core::ops::Deref;
 
+use exercises::console::{self, Shared};
+use exercises::exercise::NoIdle;
+use exercises::persist;
 use fixed::types::{I3F13, U1F15, U24F8};
 use panic_halt as _;
+use uno_helper::serial::{BufferedSerial, FullPolicy};
 
 // 16MHz, not sure how to get that from arduino hal
 const CPU_FREQUENCY: U24F8 = U24F8::lit("16_000_000");
@@ -47,9 +52,17 @@ fn main() -> ! {
 
     let (s, _) = uno_helper::precise_pwm::new_precise_pwm(dp.TC1.deref(), PRECISE_TIMER_PARAMS);
 
+    // The serial port is shared by the telemetry and the console. The
+    // telemetry that doesn't fit in the buffer is dropped rather than slowing
+    // the loop down.
+    let serial = RefCell::new(BufferedSerial::new(
+        arduino_hal::default_serial!(dp, pins, 57600),
+        FullPolicy::Drop,
+    ));
+
     let mut my_pins = exercises::wiper::OutputPeripherals {
         servo: s,
-        serial: arduino_hal::default_serial!(dp, pins, 57600),
+        serial: Shared::new(&serial),
     };
 
     let mut ip = exercises::wiper::InputPeripherals {
@@ -57,7 +70,20 @@ fn main() -> ! {
         button: pins.d11.into_pull_up_input(),
     };
 
+    // The params saved from the console, or the ones above if none were
+    let mut storage = uno_helper::eeprom::EepromStorage::new(arduino_hal::Eeprom::new(dp.EEPROM));
+    let params = persist::load(&mut storage, &PARAMS).unwrap_or(PARAMS);
+
     unsafe { avr_device::interrupt::enable() }
 
-    exercises::wiper::run(&PARAMS, &mut ip, &mut my_pins);
+    // Tune the params with e.g. "set absolute_speed 0.5" in a serial terminal
+    console::run::<exercises::wiper::Wiper<_, _, _, _>, _, _, _>(
+        &params,
+        &mut ip,
+        &mut my_pins,
+        &mut Shared::new(&serial),
+        &mut storage,
+        // The servo moves a little on every loop, as smoothly as possible
+        &mut NoIdle,
+    );
 }